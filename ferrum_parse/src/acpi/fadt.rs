@@ -0,0 +1,46 @@
+//! Fixed ACPI Description Table
+//!
+//! Only the fields the kernel uses are decoded, all of them are present since ACPI 1.0.
+use super::{parse_table, SdtHeader};
+use crate::bytes::{u16_at, u32_at, u8_at};
+use crate::ParseError;
+
+/// Fixed ACPI Description Table, signature `FACP`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fadt {
+    pub header: SdtHeader,
+    /// Physical address of the DSDT
+    pub dsdt: u32,
+    /// ISA IRQ of the System Control Interrupt
+    pub sci_interrupt: u16,
+    /// CMOS index of the century register of the RTC, 0 if there is none
+    pub century: u8,
+    /// IA-PC boot architecture flags, reserved before ACPI 2.0
+    pub iapc_boot_arch: u16,
+    pub flags: u32,
+}
+impl Fadt {
+    /// `iapc_boot_arch`: the CMOS RTC is absent or not at its legacy location
+    const CMOS_RTC_NOT_PRESENT: u16 = 1 << 5;
+
+    /// Parse and validate the FADT at the start of `data`
+    pub fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        let (header, table) = parse_table(data, b"FACP")?;
+        Ok(Fadt {
+            header,
+            dsdt: u32_at(table, 40)?,
+            sci_interrupt: u16_at(table, 46)?,
+            century: u8_at(table, 108)?,
+            iapc_boot_arch: u16_at(table, 109)?,
+            flags: u32_at(table, 112)?,
+        })
+    }
+    /// The CMOS real-time clock is at its legacy I/O ports
+    pub fn cmos_rtc_present(&self) -> bool {
+        self.header.revision < 2 || self.iapc_boot_arch & Self::CMOS_RTC_NOT_PRESENT == 0
+    }
+    /// CMOS index of the century register, if the firmware provides one
+    pub fn century_register(&self) -> Option<u8> {
+        (self.century != 0).then_some(self.century)
+    }
+}
@@ -0,0 +1,72 @@
+//! High Precision Event Timer Description Table
+use super::{parse_table, GenericAddress, SdtHeader, SDT_HEADER_SIZE};
+use crate::bytes::{u16_at, u32_at, u8_at};
+use crate::ParseError;
+
+/// High Precision Event Timer Description Table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hpet {
+    pub header: SdtHeader,
+    /// Copy of the low half of the capabilities register of the timer block
+    pub event_timer_block_id: u32,
+    /// Location of the registers of the timer block
+    pub base_address: GenericAddress,
+    /// Sequence number of the timer block
+    pub hpet_number: u8,
+    /// Smallest periodic tick, in main counter ticks, that does not lose interrupts
+    pub minimum_tick: u16,
+    pub page_protection: u8,
+}
+impl Hpet {
+    /// Parse and validate the HPET table at the start of `data`
+    pub fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        let (header, table) = parse_table(data, b"HPET")?;
+        Ok(Hpet {
+            header,
+            event_timer_block_id: u32_at(table, SDT_HEADER_SIZE)?,
+            base_address: GenericAddress::parse(table, SDT_HEADER_SIZE + 4)?,
+            hpet_number: u8_at(table, SDT_HEADER_SIZE + 16)?,
+            minimum_tick: u16_at(table, SDT_HEADER_SIZE + 17)?,
+            page_protection: u8_at(table, SDT_HEADER_SIZE + 19)?,
+        })
+    }
+}
+
+/// General capabilities and ID register of a timer block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HpetCapabilities {
+    pub revision: u8,
+    /// Number of comparators, each one can raise its own interrupt
+    pub comparator_count: u8,
+    /// The main counter is 64 bits wide, 32 bits otherwise
+    pub counter_64bit: bool,
+    /// The first two comparators can replace the PIT and RTC interrupts
+    pub legacy_replacement: bool,
+    pub vendor_id: u16,
+    /// Length of a main counter tick in femtoseconds
+    pub period_fs: u32,
+}
+impl HpetCapabilities {
+    /// Upper bound of `period_fs` allowed by the specification (100 ns)
+    pub const MAX_PERIOD_FS: u32 = 100_000_000;
+
+    /// Decode the value of the register, rejecting a zero or out of range period
+    pub fn from_register(value: u64) -> Result<Self, ParseError> {
+        let capabilities = HpetCapabilities {
+            revision: value as u8,
+            comparator_count: ((value >> 8) & 0x1F) as u8 + 1,
+            counter_64bit: value & (1 << 13) != 0,
+            legacy_replacement: value & (1 << 15) != 0,
+            vendor_id: (value >> 16) as u16,
+            period_fs: (value >> 32) as u32,
+        };
+        if capabilities.period_fs == 0 || capabilities.period_fs > Self::MAX_PERIOD_FS {
+            return Err(ParseError::InvalidValue);
+        }
+        Ok(capabilities)
+    }
+    /// Main counter frequency in Hz
+    pub fn frequency(&self) -> u64 {
+        1_000_000_000_000_000 / self.period_fs as u64
+    }
+}
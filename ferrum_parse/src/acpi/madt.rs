@@ -0,0 +1,238 @@
+//! Multiple APIC Description Table
+use super::{parse_table, SdtHeader, SDT_HEADER_SIZE};
+use crate::bytes::{slice, u16_at, u32_at, u64_at, u8_at};
+use crate::ParseError;
+
+/// An interrupt controller structure of the MADT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MadtEntry {
+    LocalApicEntry {
+        // Type 0
+        processor_id: u8, // ACPI Processor ID
+        apic_id: u8,      // Local APIC ID
+        flags: u32,       // Flags
+                          //    bit = 0: Processor Enabled
+                          //    bit = 1: Online Capable
+    },
+    IoApicEntry {
+        // Type 1
+        ioapic_id: u8,                     // IO APIC ID
+        ioapic_address: u32,               // IO APIC Address
+        global_system_interrupt_base: u32, // Global System Interrupt Base
+    },
+    IoApicInterruptSourceOverrideEntry {
+        // Type 2
+        bus_source: u8,
+        irq_source: u8,
+        global_system_interrupt: u32,
+        flags: u16,
+    },
+    IoApicNmiSourceEntry {
+        // Type 3
+        flags: u16,
+        global_system_interrupt: u32,
+    },
+    LocalApicNmiEntry {
+        // Type 4
+        processor_id: u8, // 0xFF means all processors
+        flags: u16,
+        local_apic_lint: u8,
+    },
+    LocalApicAddressOverrideEntry {
+        // Type 5
+        local_apic_address: u64,
+    },
+    ProcessorLocalX2ApicEntry {
+        // Type 9
+        x2apic_id: u32,
+        flags: u32, // Same meaning as for type 0
+        acpi_processor_uid: u32,
+    },
+    LocalX2ApicNmiEntry {
+        // Type 10
+        flags: u16,
+        acpi_processor_uid: u32, // 0xFFFFFFFF means all processors
+        local_x2apic_lint: u8,
+    },
+    UnknownEntry {
+        entry_type: u8,
+        length: u8,
+    },
+}
+impl MadtEntry {
+    /// Parse a single record, `record` holds exactly the bytes of the record
+    fn parse(record: &[u8]) -> Result<Self, ParseError> {
+        let entry_type = u8_at(record, 0)?;
+        let entry = match entry_type {
+            0 => MadtEntry::LocalApicEntry {
+                processor_id: u8_at(record, 2)?,
+                apic_id: u8_at(record, 3)?,
+                flags: u32_at(record, 4)?,
+            },
+            1 => MadtEntry::IoApicEntry {
+                ioapic_id: u8_at(record, 2)?,
+                ioapic_address: u32_at(record, 4)?,
+                global_system_interrupt_base: u32_at(record, 8)?,
+            },
+            2 => MadtEntry::IoApicInterruptSourceOverrideEntry {
+                bus_source: u8_at(record, 2)?,
+                irq_source: u8_at(record, 3)?,
+                global_system_interrupt: u32_at(record, 4)?,
+                flags: u16_at(record, 8)?,
+            },
+            3 => MadtEntry::IoApicNmiSourceEntry {
+                flags: u16_at(record, 2)?,
+                global_system_interrupt: u32_at(record, 4)?,
+            },
+            4 => MadtEntry::LocalApicNmiEntry {
+                processor_id: u8_at(record, 2)?,
+                flags: u16_at(record, 3)?,
+                local_apic_lint: u8_at(record, 5)?,
+            },
+            5 => MadtEntry::LocalApicAddressOverrideEntry {
+                local_apic_address: u64_at(record, 4)?,
+            },
+            9 => MadtEntry::ProcessorLocalX2ApicEntry {
+                x2apic_id: u32_at(record, 4)?,
+                flags: u32_at(record, 8)?,
+                acpi_processor_uid: u32_at(record, 12)?,
+            },
+            10 => MadtEntry::LocalX2ApicNmiEntry {
+                flags: u16_at(record, 2)?,
+                acpi_processor_uid: u32_at(record, 4)?,
+                local_x2apic_lint: u8_at(record, 8)?,
+            },
+            _ => MadtEntry::UnknownEntry {
+                entry_type,
+                length: record.len() as u8,
+            },
+        };
+        Ok(entry)
+    }
+}
+
+/// Signal level that marks the interrupt as active
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh,
+    ActiveLow,
+}
+/// Whether the interrupt is signalled by an edge or a level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    Edge,
+    Level,
+}
+/// A legacy IRQ resolved to the global system interrupt it arrives on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IsaIrqRoute {
+    pub gsi: u32,
+    pub polarity: Polarity,
+    pub trigger_mode: TriggerMode,
+}
+impl IsaIrqRoute {
+    /// Resolve the ISA IRQ `isa_irq` using the interrupt source overrides in `entries`
+    ///
+    /// Without an override the IRQ keeps the ISA defaults: identity mapped,
+    /// edge triggered and active high.
+    pub fn resolve<'a>(entries: impl IntoIterator<Item = &'a MadtEntry>, isa_irq: u8) -> Self {
+        entries
+            .into_iter()
+            .find_map(|entry| match *entry {
+                MadtEntry::IoApicInterruptSourceOverrideEntry {
+                    bus_source: 0,
+                    irq_source,
+                    global_system_interrupt,
+                    flags,
+                } if irq_source == isa_irq => {
+                    Some(IsaIrqRoute::from_override(global_system_interrupt, flags))
+                }
+                _ => None,
+            })
+            .unwrap_or(IsaIrqRoute {
+                gsi: isa_irq as u32,
+                polarity: Polarity::ActiveHigh,
+                trigger_mode: TriggerMode::Edge,
+            })
+    }
+    /// Decode the flags of an interrupt source override to `gsi`
+    pub fn from_override(gsi: u32, flags: u16) -> Self {
+        IsaIrqRoute {
+            gsi,
+            // Bits 0-1: 00 bus default, 01 active high, 11 active low
+            polarity: match flags & 0b11 {
+                0b11 => Polarity::ActiveLow,
+                _ => Polarity::ActiveHigh,
+            },
+            // Bits 2-3: 00 bus default, 01 edge, 11 level
+            trigger_mode: match (flags >> 2) & 0b11 {
+                0b11 => TriggerMode::Level,
+                _ => TriggerMode::Edge,
+            },
+        }
+    }
+}
+
+/// Multiple APIC Description Table
+#[derive(Debug, Clone, Copy)]
+pub struct Madt<'a> {
+    pub header: SdtHeader,
+    pub local_apic_address: u32,
+    pub flags: u32,
+    entries: &'a [u8],
+}
+impl<'a> Madt<'a> {
+    /// Parse and validate the MADT at the start of `data`
+    pub fn parse(data: &'a [u8]) -> Result<Self, ParseError> {
+        let (header, table) = parse_table(data, b"APIC")?;
+        Ok(Madt {
+            header,
+            local_apic_address: u32_at(table, SDT_HEADER_SIZE)?,
+            flags: u32_at(table, SDT_HEADER_SIZE + 4)?,
+            entries: &table[SDT_HEADER_SIZE + 8..],
+        })
+    }
+    /// The entries of the table in the order they appear
+    ///
+    /// A malformed record is returned as an error and ends the iteration.
+    pub fn entries(&self) -> MadtEntries<'a> {
+        MadtEntries {
+            data: self.entries,
+            offset: 0,
+        }
+    }
+}
+
+/// Iterator over the records of a MADT
+#[derive(Debug, Clone)]
+pub struct MadtEntries<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+impl Iterator for MadtEntries<'_> {
+    type Item = Result<MadtEntry, ParseError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.data.len() {
+            return None;
+        }
+        let record = u8_at(self.data, self.offset + 1)
+            .and_then(|length| {
+                // A record is at least 2 bytes long, anything else means the table is corrupt
+                if length < 2 {
+                    return Err(ParseError::InvalidLength);
+                }
+                slice(self.data, self.offset, length as usize)
+            })
+            .and_then(MadtEntry::parse);
+        match record {
+            Ok(entry) => {
+                self.offset += self.data[self.offset + 1] as usize;
+                Some(Ok(entry))
+            }
+            Err(error) => {
+                self.offset = self.data.len();
+                Some(Err(error))
+            }
+        }
+    }
+}
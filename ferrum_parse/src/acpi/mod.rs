@@ -0,0 +1,118 @@
+//! ACPI table parsing
+//!
+//! Every table starts with the same 36 byte header, whose length covers the
+//! whole table and whose checksum makes all the bytes of the table sum to zero.
+use crate::bytes::{array, u32_at, u64_at, u8_at};
+use crate::ParseError;
+
+pub mod fadt;
+pub mod hpet;
+pub mod madt;
+pub mod rsdp;
+pub mod rsdt;
+pub mod xsdt;
+
+pub use fadt::Fadt;
+pub use hpet::{Hpet, HpetCapabilities};
+pub use madt::{Madt, MadtEntry};
+pub use rsdp::Rsdp;
+pub use rsdt::Rsdt;
+pub use xsdt::Xsdt;
+
+/// Size of the header shared by all the system description tables
+pub const SDT_HEADER_SIZE: usize = 36;
+
+/// Header shared by all the system description tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+impl SdtHeader {
+    /// Parse the header at the start of `data`
+    pub fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        Ok(SdtHeader {
+            signature: array(data, 0)?,
+            length: u32_at(data, 4)?,
+            revision: u8_at(data, 8)?,
+            checksum: u8_at(data, 9)?,
+            oem_id: array(data, 10)?,
+            oem_table_id: array(data, 16)?,
+            oem_revision: u32_at(data, 24)?,
+            creator_id: u32_at(data, 28)?,
+            creator_revision: u32_at(data, 32)?,
+        })
+    }
+    /// Returns the signature as a string
+    pub fn signature_str(&self) -> &str {
+        core::str::from_utf8(&self.signature).unwrap_or("????")
+    }
+}
+
+/// Generic Address Structure, locates a register in one of the address spaces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenericAddress {
+    pub address_space_id: u8,
+    pub register_bit_width: u8,
+    pub register_bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+impl GenericAddress {
+    /// Size of the structure
+    pub const SIZE: usize = 12;
+    /// `address_space_id` of memory mapped registers
+    pub const SYSTEM_MEMORY: u8 = 0;
+    /// `address_space_id` of registers in the I/O port space
+    pub const SYSTEM_IO: u8 = 1;
+
+    /// Parse the structure at `offset` in `data`
+    pub fn parse(data: &[u8], offset: usize) -> Result<Self, ParseError> {
+        Ok(GenericAddress {
+            address_space_id: u8_at(data, offset)?,
+            register_bit_width: u8_at(data, offset + 1)?,
+            register_bit_offset: u8_at(data, offset + 2)?,
+            access_size: u8_at(data, offset + 3)?,
+            address: u64_at(data, offset + 4)?,
+        })
+    }
+}
+
+/// Wrapping sum of all the bytes, zero for a valid table
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+/// Check the header, length and checksum of the table at the start of `data`
+///
+/// Returns the header and the bytes of the table, which may be shorter than `data`.
+pub fn parse_table<'a>(
+    data: &'a [u8],
+    signature: &[u8; 4],
+) -> Result<(SdtHeader, &'a [u8]), ParseError> {
+    let header = SdtHeader::parse(data)?;
+    if &header.signature != signature {
+        return Err(ParseError::InvalidSignature);
+    }
+    let length = header.length as usize;
+    if length < SDT_HEADER_SIZE {
+        return Err(ParseError::InvalidLength);
+    }
+    let table = data.get(..length).ok_or(ParseError::UnexpectedEnd {
+        offset: 0,
+        size: length,
+    })?;
+    if checksum(table) != 0 {
+        return Err(ParseError::InvalidChecksum);
+    }
+    Ok((header, table))
+}
+
+#[cfg(test)]
+pub(crate) mod tests;
@@ -0,0 +1,58 @@
+//! Root System Description Pointer
+use super::checksum;
+use crate::bytes::{array, slice, u32_at, u64_at, u8_at};
+use crate::ParseError;
+
+/// Size of the revision 0 part of the RSDP that is covered by the first checksum
+pub const RSDP_V1_SIZE: usize = 20;
+/// Size of the revision 2+ RSDP
+pub const RSDP_V2_SIZE: usize = 36;
+
+/// Root System Description Pointer, locates the RSDT and, from ACPI 2.0, the XSDT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rsdp {
+    pub oem_id: [u8; 6],
+    pub revision: u8,
+    pub rsdt_address: u32,
+    // Fields below are only present from revision 2 (ACPI 2.0) onwards
+    pub length: u32,
+    pub xsdt_address: u64,
+    /// The revision 2+ part failed validation and was dropped, only the RSDT can be used
+    pub extended_rejected: bool,
+}
+impl Rsdp {
+    /// Parse and validate the RSDP at the start of `data`
+    ///
+    /// The first checksum covers the revision 0 fields, the extended checksum
+    /// of revision 2+ tables covers the whole structure. When only the extended
+    /// part is invalid the revision 0 fields are kept, with no XSDT address.
+    pub fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        if &array::<8>(data, 0)? != b"RSD PTR " {
+            return Err(ParseError::InvalidSignature);
+        }
+        let v1 = slice(data, 0, RSDP_V1_SIZE)?;
+        if checksum(v1) != 0 {
+            return Err(ParseError::InvalidChecksum);
+        }
+        let mut rsdp = Rsdp {
+            oem_id: array(data, 9)?,
+            revision: u8_at(data, 15)?,
+            rsdt_address: u32_at(data, 16)?,
+            length: RSDP_V1_SIZE as u32,
+            xsdt_address: 0,
+            extended_rejected: false,
+        };
+        if rsdp.revision >= 2 {
+            let length = u32_at(data, 20)?;
+            let valid = (length as usize) >= RSDP_V2_SIZE
+                && checksum(slice(data, 0, length as usize)?) == 0;
+            if valid {
+                rsdp.length = length;
+                rsdp.xsdt_address = u64_at(data, 24)?;
+            } else {
+                rsdp.extended_rejected = true;
+            }
+        }
+        Ok(rsdp)
+    }
+}
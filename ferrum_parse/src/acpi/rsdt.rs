@@ -0,0 +1,26 @@
+//! Root System Description Table
+use super::{parse_table, SdtHeader, SDT_HEADER_SIZE};
+use crate::bytes::u32_at;
+use crate::ParseError;
+
+/// Root System Description Table, holds 32 bit pointers to the other tables
+#[derive(Debug, Clone, Copy)]
+pub struct Rsdt<'a> {
+    pub header: SdtHeader,
+    entries: &'a [u8],
+}
+impl<'a> Rsdt<'a> {
+    /// Parse and validate the RSDT at the start of `data`
+    pub fn parse(data: &'a [u8]) -> Result<Self, ParseError> {
+        let (header, table) = parse_table(data, b"RSDT")?;
+        Ok(Rsdt {
+            header,
+            entries: &table[SDT_HEADER_SIZE..],
+        })
+    }
+    /// Physical addresses of the tables referenced by the RSDT
+    pub fn entries(&self) -> impl Iterator<Item = u64> + 'a {
+        let entries = self.entries;
+        (0..entries.len() / 4).filter_map(move |i| u32_at(entries, i * 4).ok().map(u64::from))
+    }
+}
@@ -0,0 +1,382 @@
+use super::*;
+use crate::ParseError;
+use std::vec::Vec;
+
+/// Build a table with a valid header and checksum around `body`
+pub(crate) fn table(signature: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(signature);
+    data.extend_from_slice(&((SDT_HEADER_SIZE + body.len()) as u32).to_le_bytes());
+    data.push(1); // revision
+    data.push(0); // checksum
+    data.extend_from_slice(b"BOCHS ");
+    data.extend_from_slice(b"BXPC    ");
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(b"BXPC");
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(body);
+    data[9] = 0u8.wrapping_sub(checksum(&data));
+    data
+}
+/// The MADT QEMU generates for a q35 machine with two CPUs
+fn qemu_madt() -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&0xFEE0_0000u32.to_le_bytes()); // local APIC address
+    body.extend_from_slice(&1u32.to_le_bytes()); // PC-AT compatible
+    for cpu in 0..2u8 {
+        body.extend_from_slice(&[0, 8, cpu, cpu, 1, 0, 0, 0]);
+    }
+    body.extend_from_slice(&[1, 12, 0, 0]);
+    body.extend_from_slice(&0xFEC0_0000u32.to_le_bytes());
+    body.extend_from_slice(&0u32.to_le_bytes());
+    for (irq, gsi, flags) in [
+        (0u8, 2u32, 0u16),
+        (5, 5, 0xD),
+        (9, 9, 0xD),
+        (10, 10, 0xD),
+        (11, 11, 0xD),
+    ] {
+        body.extend_from_slice(&[2, 10, 0, irq]);
+        body.extend_from_slice(&gsi.to_le_bytes());
+        body.extend_from_slice(&flags.to_le_bytes());
+    }
+    body.extend_from_slice(&[4, 6, 0xFF, 0, 0, 1]);
+    table(b"APIC", &body)
+}
+fn rsdp(revision: u8, xsdt_address: u64) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(b"RSD PTR ");
+    data.push(0);
+    data.extend_from_slice(b"BOCHS ");
+    data.push(revision);
+    data.extend_from_slice(&0x7FE2_2000u32.to_le_bytes());
+    data[8] = 0u8.wrapping_sub(checksum(&data));
+    if revision >= 2 {
+        data.extend_from_slice(&36u32.to_le_bytes());
+        data.extend_from_slice(&xsdt_address.to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data[32] = 0u8.wrapping_sub(checksum(&data));
+    }
+    data
+}
+
+#[test]
+fn header_fields() {
+    let data = table(b"FACP", &[0; 8]);
+    let header = SdtHeader::parse(&data).unwrap();
+    assert_eq!(header.signature_str(), "FACP");
+    assert_eq!(header.length, 44);
+    assert_eq!(&header.oem_id, b"BOCHS ");
+    assert_eq!(checksum(&data), 0);
+}
+#[test]
+fn header_truncated() {
+    let data = table(b"FACP", &[]);
+    for length in 0..SDT_HEADER_SIZE {
+        assert!(matches!(
+            SdtHeader::parse(&data[..length]),
+            Err(ParseError::UnexpectedEnd { .. })
+        ));
+    }
+}
+/// Input, expected signature and expected table length
+type TableCase<'a> = (&'a [u8], &'a [u8; 4], Result<usize, ParseError>);
+
+#[test]
+fn table_validation() {
+    let valid = table(b"APIC", &[0; 8]);
+    let mut bad_checksum = valid.clone();
+    bad_checksum[40] ^= 0xFF;
+    let mut short_length = valid.clone();
+    short_length[4] = 10;
+    let cases: [TableCase; 5] = [
+        (&valid, b"APIC", Ok(44)),
+        (&valid, b"HPET", Err(ParseError::InvalidSignature)),
+        (&bad_checksum, b"APIC", Err(ParseError::InvalidChecksum)),
+        (&short_length, b"APIC", Err(ParseError::InvalidLength)),
+        (
+            &valid[..40],
+            b"APIC",
+            Err(ParseError::UnexpectedEnd {
+                offset: 0,
+                size: 44,
+            }),
+        ),
+    ];
+    for (data, signature, expected) in cases {
+        let result = parse_table(data, signature).map(|(_, table)| table.len());
+        assert_eq!(result, expected);
+    }
+}
+#[test]
+fn rsdp_revisions() {
+    let v1 = Rsdp::parse(&rsdp(0, 0)).unwrap();
+    assert_eq!(v1.revision, 0);
+    assert_eq!(v1.rsdt_address, 0x7FE2_2000);
+    assert_eq!(v1.xsdt_address, 0);
+    let v2 = Rsdp::parse(&rsdp(2, 0x7FE2_3000)).unwrap();
+    assert_eq!(v2.revision, 2);
+    assert_eq!(v2.xsdt_address, 0x7FE2_3000);
+}
+#[test]
+fn rsdp_invalid() {
+    let mut signature = rsdp(0, 0);
+    signature[0] = b'X';
+    assert_eq!(Rsdp::parse(&signature), Err(ParseError::InvalidSignature));
+    let mut checksum = rsdp(0, 0);
+    checksum[16] ^= 1;
+    assert_eq!(Rsdp::parse(&checksum), Err(ParseError::InvalidChecksum));
+    assert!(Rsdp::parse(&rsdp(2, 0x1000)[..20]).is_err());
+}
+#[test]
+fn rsdp_bad_extended_checksum() {
+    let mut extended = rsdp(2, 0x1000);
+    extended[24] ^= 1;
+    // The revision 0 fields are still valid, the RSDT is used
+    let rsdp = Rsdp::parse(&extended).unwrap();
+    assert_eq!(rsdp.revision, 2);
+    assert_eq!(rsdp.rsdt_address, 0x7FE2_2000);
+    assert_eq!(rsdp.xsdt_address, 0);
+    assert!(rsdp.extended_rejected);
+    let valid = Rsdp::parse(&self::rsdp(2, 0x1000)).unwrap();
+    assert!(!valid.extended_rejected);
+}
+#[test]
+fn root_tables() {
+    let mut body = Vec::new();
+    for address in [0x1000u32, 0x2000, 0x3000] {
+        body.extend_from_slice(&address.to_le_bytes());
+    }
+    let rsdt_data = table(b"RSDT", &body);
+    let rsdt = Rsdt::parse(&rsdt_data).unwrap();
+    assert_eq!(rsdt.entries().collect::<Vec<_>>(), [0x1000, 0x2000, 0x3000]);
+
+    let mut body = Vec::new();
+    for address in [0x1_0000_1000u64, 0x2000] {
+        body.extend_from_slice(&address.to_le_bytes());
+    }
+    let xsdt_data = table(b"XSDT", &body);
+    let xsdt = Xsdt::parse(&xsdt_data).unwrap();
+    assert_eq!(xsdt.entries().collect::<Vec<_>>(), [0x1_0000_1000, 0x2000]);
+    assert_eq!(
+        Xsdt::parse(&rsdt_data).err(),
+        Some(ParseError::InvalidSignature)
+    );
+}
+#[test]
+fn madt_qemu() {
+    let data = qemu_madt();
+    let madt = Madt::parse(&data).unwrap();
+    assert_eq!(madt.local_apic_address, 0xFEE0_0000);
+    assert_eq!(madt.flags, 1);
+    let entries: Vec<MadtEntry> = madt.entries().map(Result::unwrap).collect();
+    assert_eq!(entries.len(), 9);
+    assert_eq!(
+        entries[1],
+        MadtEntry::LocalApicEntry {
+            processor_id: 1,
+            apic_id: 1,
+            flags: 1
+        }
+    );
+    assert_eq!(
+        entries[2],
+        MadtEntry::IoApicEntry {
+            ioapic_id: 0,
+            ioapic_address: 0xFEC0_0000,
+            global_system_interrupt_base: 0
+        }
+    );
+    assert_eq!(
+        entries[3],
+        MadtEntry::IoApicInterruptSourceOverrideEntry {
+            bus_source: 0,
+            irq_source: 0,
+            global_system_interrupt: 2,
+            flags: 0
+        }
+    );
+    assert_eq!(
+        entries[8],
+        MadtEntry::LocalApicNmiEntry {
+            processor_id: 0xFF,
+            flags: 0,
+            local_apic_lint: 1
+        }
+    );
+}
+#[test]
+fn isa_irq_routes() {
+    use madt::{IsaIrqRoute, Polarity, TriggerMode};
+    let data = qemu_madt();
+    let entries: Vec<MadtEntry> = Madt::parse(&data)
+        .unwrap()
+        .entries()
+        .map(Result::unwrap)
+        .collect();
+    let route = |gsi, polarity, trigger_mode| IsaIrqRoute {
+        gsi,
+        polarity,
+        trigger_mode,
+    };
+    // The PIT is remapped with the bus defaults
+    assert_eq!(
+        IsaIrqRoute::resolve(&entries, 0),
+        route(2, Polarity::ActiveHigh, TriggerMode::Edge)
+    );
+    // No override, identity mapped
+    assert_eq!(
+        IsaIrqRoute::resolve(&entries, 1),
+        route(1, Polarity::ActiveHigh, TriggerMode::Edge)
+    );
+    // 0xD: active high, level triggered
+    assert_eq!(
+        IsaIrqRoute::resolve(&entries, 9),
+        route(9, Polarity::ActiveHigh, TriggerMode::Level)
+    );
+    // 0xF: active low, level triggered
+    assert_eq!(
+        IsaIrqRoute::from_override(20, 0xF),
+        route(20, Polarity::ActiveLow, TriggerMode::Level)
+    );
+    // 0x7: active low, edge triggered
+    assert_eq!(
+        IsaIrqRoute::from_override(3, 0x7),
+        route(3, Polarity::ActiveLow, TriggerMode::Edge)
+    );
+}
+#[test]
+fn madt_entry_types() {
+    let cases: [(&[u8], MadtEntry); 4] = [
+        (
+            &[5, 12, 0, 0, 0, 0, 0xE0, 0xFE, 0, 0, 0, 0],
+            MadtEntry::LocalApicAddressOverrideEntry {
+                local_apic_address: 0xFEE0_0000,
+            },
+        ),
+        (
+            &[9, 16, 0, 0, 7, 0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 0],
+            MadtEntry::ProcessorLocalX2ApicEntry {
+                x2apic_id: 7,
+                flags: 1,
+                acpi_processor_uid: 3,
+            },
+        ),
+        (
+            &[10, 12, 5, 0, 0xFF, 0xFF, 0xFF, 0xFF, 1, 0, 0, 0],
+            MadtEntry::LocalX2ApicNmiEntry {
+                flags: 5,
+                acpi_processor_uid: 0xFFFF_FFFF,
+                local_x2apic_lint: 1,
+            },
+        ),
+        (
+            &[0x7F, 4, 0, 0],
+            MadtEntry::UnknownEntry {
+                entry_type: 0x7F,
+                length: 4,
+            },
+        ),
+    ];
+    for (record, expected) in cases {
+        let mut body = std::vec![0; 8];
+        body.extend_from_slice(record);
+        let data = table(b"APIC", &body);
+        let madt = Madt::parse(&data).unwrap();
+        let entries: Vec<_> = madt.entries().collect();
+        assert_eq!(entries, [Ok(expected)]);
+    }
+}
+#[test]
+fn madt_malformed_records() {
+    let cases: [&[u8]; 3] = [
+        // Zero length record
+        &[0, 0, 0, 0],
+        // Record longer than the table
+        &[1, 12, 0, 0],
+        // Record too short for its type
+        &[1, 4, 0, 0],
+    ];
+    for record in cases {
+        let mut body = std::vec![0; 8];
+        body.extend_from_slice(record);
+        let data = table(b"APIC", &body);
+        let madt = Madt::parse(&data).unwrap();
+        let entries: Vec<_> = madt.entries().collect();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].is_err());
+    }
+}
+/// The HPET table QEMU generates
+fn qemu_hpet() -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&0x8086_A201u32.to_le_bytes());
+    body.extend_from_slice(&[0, 0, 0, 0]);
+    body.extend_from_slice(&0xFED0_0000u64.to_le_bytes());
+    body.push(0); // hpet number
+    body.extend_from_slice(&128u16.to_le_bytes());
+    body.push(0); // page protection
+    table(b"HPET", &body)
+}
+#[test]
+fn hpet_qemu() {
+    let hpet = Hpet::parse(&qemu_hpet()).unwrap();
+    assert_eq!(hpet.event_timer_block_id, 0x8086_A201);
+    assert_eq!(
+        hpet.base_address.address_space_id,
+        GenericAddress::SYSTEM_MEMORY
+    );
+    assert_eq!(hpet.base_address.address, 0xFED0_0000);
+    assert_eq!(hpet.minimum_tick, 128);
+    // Truncated before the page protection byte
+    let mut data = qemu_hpet();
+    data.truncate(data.len() - 1);
+    data[4] -= 1;
+    data[9] = 0;
+    data[9] = 0u8.wrapping_sub(checksum(&data));
+    assert!(matches!(
+        Hpet::parse(&data),
+        Err(ParseError::UnexpectedEnd { .. })
+    ));
+}
+#[test]
+fn hpet_capabilities() {
+    let capabilities = HpetCapabilities::from_register(0x0098_9680_8086_A201).unwrap();
+    assert_eq!(capabilities.revision, 1);
+    assert_eq!(capabilities.comparator_count, 3);
+    assert!(capabilities.counter_64bit);
+    assert!(capabilities.legacy_replacement);
+    assert_eq!(capabilities.vendor_id, 0x8086);
+    assert_eq!(capabilities.period_fs, 10_000_000);
+    assert_eq!(capabilities.frequency(), 100_000_000);
+    assert_eq!(
+        HpetCapabilities::from_register(0x8086_A201),
+        Err(ParseError::InvalidValue)
+    );
+    assert_eq!(
+        HpetCapabilities::from_register(0x05F5_E101_8086_A201),
+        Err(ParseError::InvalidValue)
+    );
+}
+#[test]
+fn fadt_fields() {
+    let mut body = std::vec![0u8; 80];
+    body[4..8].copy_from_slice(&0x7FFE_0040u32.to_le_bytes());
+    body[10..12].copy_from_slice(&9u16.to_le_bytes());
+    body[72] = 0x32;
+    body[73..75].copy_from_slice(&(1u16 << 5).to_le_bytes());
+    let mut data = table(b"FACP", &body);
+    let fadt = Fadt::parse(&data).unwrap();
+    assert_eq!(fadt.dsdt, 0x7FFE_0040);
+    assert_eq!(fadt.sci_interrupt, 9);
+    assert_eq!(fadt.century_register(), Some(0x32));
+    // The boot architecture flags are reserved in revision 1
+    assert!(fadt.cmos_rtc_present());
+    data[8] = 2;
+    data[9] = data[9].wrapping_sub(1);
+    assert!(!Fadt::parse(&data).unwrap().cmos_rtc_present());
+    assert!(matches!(
+        Fadt::parse(&table(b"FACP", &body[..70])),
+        Err(ParseError::UnexpectedEnd { .. })
+    ));
+}
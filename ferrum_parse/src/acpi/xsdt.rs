@@ -0,0 +1,26 @@
+//! Extended System Description Table
+use super::{parse_table, SdtHeader, SDT_HEADER_SIZE};
+use crate::bytes::u64_at;
+use crate::ParseError;
+
+/// Extended System Description Table, holds 64 bit pointers to the other tables
+#[derive(Debug, Clone, Copy)]
+pub struct Xsdt<'a> {
+    pub header: SdtHeader,
+    entries: &'a [u8],
+}
+impl<'a> Xsdt<'a> {
+    /// Parse and validate the XSDT at the start of `data`
+    pub fn parse(data: &'a [u8]) -> Result<Self, ParseError> {
+        let (header, table) = parse_table(data, b"XSDT")?;
+        Ok(Xsdt {
+            header,
+            entries: &table[SDT_HEADER_SIZE..],
+        })
+    }
+    /// Physical addresses of the tables referenced by the XSDT
+    pub fn entries(&self) -> impl Iterator<Item = u64> + 'a {
+        let entries = self.entries;
+        (0..entries.len() / 8).filter_map(move |i| u64_at(entries, i * 8).ok())
+    }
+}
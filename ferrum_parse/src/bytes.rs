@@ -0,0 +1,28 @@
+//! Bounds checked little endian reads from byte slices
+use crate::ParseError;
+
+/// Returns `size` bytes starting at `offset`
+pub(crate) fn slice(data: &[u8], offset: usize, size: usize) -> Result<&[u8], ParseError> {
+    offset
+        .checked_add(size)
+        .and_then(|end| data.get(offset..end))
+        .ok_or(ParseError::UnexpectedEnd { offset, size })
+}
+/// Returns the `N` bytes starting at `offset`
+pub(crate) fn array<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N], ParseError> {
+    let mut result = [0; N];
+    result.copy_from_slice(slice(data, offset, N)?);
+    Ok(result)
+}
+pub(crate) fn u8_at(data: &[u8], offset: usize) -> Result<u8, ParseError> {
+    Ok(array::<1>(data, offset)?[0])
+}
+pub(crate) fn u16_at(data: &[u8], offset: usize) -> Result<u16, ParseError> {
+    Ok(u16::from_le_bytes(array(data, offset)?))
+}
+pub(crate) fn u32_at(data: &[u8], offset: usize) -> Result<u32, ParseError> {
+    Ok(u32::from_le_bytes(array(data, offset)?))
+}
+pub(crate) fn u64_at(data: &[u8], offset: usize) -> Result<u64, ParseError> {
+    Ok(u64::from_le_bytes(array(data, offset)?))
+}
@@ -0,0 +1,178 @@
+//! Kernel command line parsing
+//!
+//! The command line is a list of whitespace separated parameters. A parameter
+//! is either a flag (`acpi.list`) or a `key=value` pair, where the value may be
+//! quoted to include spaces (`key="a b"`) and may hold a comma separated list
+//! (`irq.mask=3,4`). When a key is given several times the last one wins.
+use crate::ParseError;
+
+/// A single parameter of the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Param<'a> {
+    pub key: &'a str,
+    /// `None` for flags
+    pub value: Option<&'a str>,
+}
+
+/// A kernel command line
+#[derive(Debug, Clone, Copy)]
+pub struct CmdLine<'a> {
+    line: &'a str,
+}
+impl<'a> CmdLine<'a> {
+    pub const fn new(line: &'a str) -> Self {
+        CmdLine { line }
+    }
+    /// Parse a command line that is not known to be valid UTF-8
+    pub fn from_bytes(line: &'a [u8]) -> Result<Self, ParseError> {
+        core::str::from_utf8(line)
+            .map(CmdLine::new)
+            .map_err(|_| ParseError::InvalidUtf8)
+    }
+    /// The parameters in the order they appear
+    pub fn params(&self) -> Params<'a> {
+        Params { rest: self.line }
+    }
+    /// The last parameter with the given key
+    pub fn get(&self, key: &str) -> Option<Param<'a>> {
+        self.params().filter(|param| param.key == key).last()
+    }
+    /// Parse the value of the last parameter with the given key
+    ///
+    /// Returns `None` if the key is not present.
+    pub fn value<T: FromParam<'a>>(&self, key: &str) -> Option<Result<T, ParseError>> {
+        self.get(key).map(|param| T::from_param(param.value))
+    }
+}
+
+/// Iterator over the parameters of a command line
+#[derive(Debug, Clone)]
+pub struct Params<'a> {
+    rest: &'a str,
+}
+impl<'a> Iterator for Params<'a> {
+    type Item = Param<'a>;
+    fn next(&mut self) -> Option<Param<'a>> {
+        let line = self.rest.trim_start();
+        if line.is_empty() {
+            self.rest = line;
+            return None;
+        }
+        // A parameter ends at the first whitespace outside of quotes
+        let mut quoted = false;
+        let end = line
+            .char_indices()
+            .find(|(_, c)| {
+                if *c == '"' {
+                    quoted = !quoted;
+                }
+                c.is_whitespace() && !quoted
+            })
+            .map_or(line.len(), |(index, _)| index);
+        let token = &line[..end];
+        self.rest = &line[end..];
+        let param = match token.split_once('=') {
+            Some((key, value)) => Param {
+                key,
+                value: Some(unquote(value)),
+            },
+            None => Param {
+                key: token,
+                value: None,
+            },
+        };
+        Some(param)
+    }
+}
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// A type a parameter value can be parsed into
+pub trait FromParam<'a>: Sized {
+    /// Parse the value, `None` if the parameter was given as a flag
+    fn from_param(value: Option<&'a str>) -> Result<Self, ParseError>;
+}
+impl<'a> FromParam<'a> for bool {
+    fn from_param(value: Option<&'a str>) -> Result<Self, ParseError> {
+        match value {
+            None | Some("1" | "on" | "yes" | "true") => Ok(true),
+            Some("0" | "off" | "no" | "false") => Ok(false),
+            Some(_) => Err(ParseError::InvalidValue),
+        }
+    }
+}
+impl<'a> FromParam<'a> for &'a str {
+    fn from_param(value: Option<&'a str>) -> Result<Self, ParseError> {
+        value.ok_or(ParseError::InvalidValue)
+    }
+}
+impl<'a> FromParam<'a> for u64 {
+    /// Decimal or `0x` prefixed hexadecimal, optionally followed by a `K`, `M` or `G` size suffix
+    fn from_param(value: Option<&'a str>) -> Result<Self, ParseError> {
+        let value = value.ok_or(ParseError::InvalidValue)?;
+        let (number, multiplier) = match value.as_bytes().last() {
+            Some(b'K' | b'k') => (&value[..value.len() - 1], 1 << 10),
+            Some(b'M' | b'm') => (&value[..value.len() - 1], 1 << 20),
+            Some(b'G' | b'g') => (&value[..value.len() - 1], 1 << 30),
+            _ => (value, 1),
+        };
+        let number = match number.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => number.parse(),
+        }
+        .map_err(|_| ParseError::InvalidValue)?;
+        number
+            .checked_mul(multiplier)
+            .ok_or(ParseError::InvalidValue)
+    }
+}
+impl<'a> FromParam<'a> for usize {
+    fn from_param(value: Option<&'a str>) -> Result<Self, ParseError> {
+        usize::try_from(u64::from_param(value)?).map_err(|_| ParseError::InvalidValue)
+    }
+}
+impl<'a> FromParam<'a> for u32 {
+    fn from_param(value: Option<&'a str>) -> Result<Self, ParseError> {
+        u32::try_from(u64::from_param(value)?).map_err(|_| ParseError::InvalidValue)
+    }
+}
+impl<'a> FromParam<'a> for u8 {
+    fn from_param(value: Option<&'a str>) -> Result<Self, ParseError> {
+        u8::try_from(u64::from_param(value)?).map_err(|_| ParseError::InvalidValue)
+    }
+}
+
+/// A comma separated list value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct List<'a> {
+    value: &'a str,
+}
+impl<'a> List<'a> {
+    pub const fn new(value: &'a str) -> Self {
+        List { value }
+    }
+    /// The items of the list, empty items are skipped
+    pub fn items(&self) -> impl Iterator<Item = &'a str> {
+        self.value.split(',').filter(|item| !item.is_empty())
+    }
+    /// Parse every item of the list
+    pub fn parse<T: FromParam<'a>>(&self) -> impl Iterator<Item = Result<T, ParseError>> + 'a {
+        let value = self.value;
+        value
+            .split(',')
+            .filter(|item| !item.is_empty())
+            .map(|item| T::from_param(Some(item)))
+    }
+}
+impl<'a> FromParam<'a> for List<'a> {
+    fn from_param(value: Option<&'a str>) -> Result<Self, ParseError> {
+        value.map(List::new).ok_or(ParseError::InvalidValue)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,86 @@
+use super::*;
+use crate::ParseError;
+use std::vec::Vec;
+
+fn param<'a>(key: &'a str, value: Option<&'a str>) -> Param<'a> {
+    Param { key, value }
+}
+
+#[test]
+fn tokenizing() {
+    let cases: [(&str, Vec<Param>); 6] = [
+        ("", Vec::new()),
+        ("   ", Vec::new()),
+        ("acpi.list", std::vec![param("acpi.list", None)]),
+        (
+            "  heap.limit=128M   acpi.list ",
+            std::vec![param("heap.limit", Some("128M")), param("acpi.list", None)],
+        ),
+        (
+            r#"name="ferrum os" irq.mask=3,4"#,
+            std::vec![
+                param("name", Some("ferrum os")),
+                param("irq.mask", Some("3,4"))
+            ],
+        ),
+        (
+            "key= a=b=c",
+            std::vec![param("key", Some("")), param("a", Some("b=c"))],
+        ),
+    ];
+    for (line, expected) in cases {
+        let params: Vec<Param> = CmdLine::new(line).params().collect();
+        assert_eq!(params, expected, "parsing {:?}", line);
+    }
+}
+#[test]
+fn last_value_wins() {
+    let cmdline = CmdLine::new("heap.limit=1M heap.limit=2M");
+    assert_eq!(cmdline.value::<u64>("heap.limit"), Some(Ok(2 << 20)));
+    assert_eq!(cmdline.value::<u64>("missing"), None);
+}
+#[test]
+fn numbers() {
+    let cases: [(Option<&str>, Result<u64, ParseError>); 8] = [
+        (Some("42"), Ok(42)),
+        (Some("0x2A"), Ok(42)),
+        (Some("4K"), Ok(4096)),
+        (Some("128M"), Ok(128 << 20)),
+        (Some("2g"), Ok(2 << 30)),
+        (Some("-1"), Err(ParseError::InvalidValue)),
+        (Some("99999999999999999999G"), Err(ParseError::InvalidValue)),
+        (None, Err(ParseError::InvalidValue)),
+    ];
+    for (value, expected) in cases {
+        assert_eq!(u64::from_param(value), expected, "parsing {:?}", value);
+    }
+    assert_eq!(u8::from_param(Some("256")), Err(ParseError::InvalidValue));
+}
+#[test]
+fn flags() {
+    let cases: [(Option<&str>, Result<bool, ParseError>); 5] = [
+        (None, Ok(true)),
+        (Some("on"), Ok(true)),
+        (Some("0"), Ok(false)),
+        (Some("no"), Ok(false)),
+        (Some("maybe"), Err(ParseError::InvalidValue)),
+    ];
+    for (value, expected) in cases {
+        assert_eq!(bool::from_param(value), expected, "parsing {:?}", value);
+    }
+}
+#[test]
+fn lists() {
+    let cmdline = CmdLine::new("irq.mask=3,,4,0x10 names=a,b");
+    let mask: List = cmdline.value("irq.mask").unwrap().unwrap();
+    let values: Vec<Result<u8, ParseError>> = mask.parse().collect();
+    assert_eq!(values, [Ok(3), Ok(4), Ok(16)]);
+    let names: List = cmdline.value("names").unwrap().unwrap();
+    assert_eq!(names.items().collect::<Vec<_>>(), ["a", "b"]);
+    assert_eq!(List::from_param(None), Err(ParseError::InvalidValue));
+}
+#[test]
+fn invalid_utf8() {
+    assert!(CmdLine::from_bytes(&[0x66, 0xFF]).is_err());
+    assert!(CmdLine::from_bytes(b"acpi.list").is_ok());
+}
@@ -0,0 +1,137 @@
+//! Parsers must reject malformed input with an error, never with a panic
+//!
+//! Inputs are random bytes and randomly corrupted valid files, generated from a
+//! fixed seed so failures can be reproduced.
+use crate::acpi::{Fadt, Hpet, HpetCapabilities, Madt, Rsdp, Rsdt, SdtHeader, Xsdt};
+use crate::cmdline::{CmdLine, FromParam, List};
+use crate::pit::PITConfig;
+use crate::psf::{decode_utf8, PsfFont};
+use crate::rtc::{DateTime, RtcRegisters};
+use std::vec::Vec;
+
+const ROUNDS: usize = 2000;
+
+/// xorshift64*, good enough to generate test inputs
+struct Rng(u64);
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+    fn bytes(&mut self, length: usize) -> Vec<u8> {
+        (0..length).map(|_| self.next() as u8).collect()
+    }
+    /// Flip a few bytes and maybe truncate
+    fn corrupt(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut data = data.to_vec();
+        for _ in 0..=self.below(4) {
+            let index = self.below(data.len());
+            data[index] = self.next() as u8;
+        }
+        let length = data.len() - self.below(data.len() / 4 + 1);
+        data.truncate(length);
+        data
+    }
+}
+
+/// Run every parser to completion on `data`
+fn parse_all(data: &[u8]) {
+    let _ = SdtHeader::parse(data);
+    let _ = Rsdp::parse(data);
+    let _ = Hpet::parse(data);
+    let _ = Fadt::parse(data);
+    if let Some(bytes) = data.get(..8) {
+        let _ = HpetCapabilities::from_register(u64::from_le_bytes(bytes.try_into().unwrap()));
+    }
+    if let Ok(rsdt) = Rsdt::parse(data) {
+        rsdt.entries().count();
+    }
+    if let Ok(xsdt) = Xsdt::parse(data) {
+        xsdt.entries().count();
+    }
+    if let Ok(madt) = Madt::parse(data) {
+        madt.entries().count();
+    }
+    if let Ok(font) = PsfFont::parse(data) {
+        font.unicode_table().count();
+        font.glyph(0);
+        font.glyph(u32::MAX);
+    }
+    let _ = decode_utf8(data);
+    if let Ok(cmdline) = CmdLine::from_bytes(data) {
+        for param in cmdline.params() {
+            let _ = u64::from_param(param.value);
+            let _ = bool::from_param(param.value);
+            if let Ok(list) = List::from_param(param.value) {
+                list.parse::<u32>().count();
+            }
+        }
+    }
+    if let Some(byte) = data.first() {
+        let _ = PITConfig::parse(*byte);
+    }
+    if let [seconds, minutes, hours, day, month, year, century, status_b, ..] = *data {
+        let registers = RtcRegisters {
+            seconds,
+            minutes,
+            hours,
+            day,
+            month,
+            year,
+            century: Some(century),
+            status_b,
+        };
+        if let Ok(date) = registers.decode() {
+            assert_eq!(DateTime::from_unix_seconds(date.to_unix_seconds()), date);
+        }
+    }
+}
+
+#[test]
+fn random_bytes() {
+    let mut rng = Rng(0x5EED_0001);
+    for _ in 0..ROUNDS {
+        let length = rng.below(256);
+        parse_all(&rng.bytes(length));
+    }
+}
+#[test]
+fn corrupted_tables() {
+    use crate::acpi::tests::table;
+    let mut rng = Rng(0x5EED_0002);
+    let madt = table(
+        b"APIC",
+        &[0, 0, 0xE0, 0xFE, 1, 0, 0, 0, 0, 8, 0, 0, 1, 0, 0, 0],
+    );
+    for _ in 0..ROUNDS {
+        let mut data = rng.corrupt(&madt);
+        // Keep the checksum valid so corruption reaches the record parser
+        if data.len() > 9 {
+            data[9] = 0;
+            data[9] = 0u8.wrapping_sub(crate::acpi::checksum(&data));
+        }
+        parse_all(&data);
+    }
+}
+#[test]
+fn corrupted_font() {
+    const AGAFARI: &[u8] =
+        include_bytes!("../../kernel/src/drivers/fonts/font_files/Agafari-16.psfu");
+    let mut rng = Rng(0x5EED_0003);
+    for _ in 0..ROUNDS / 10 {
+        parse_all(&rng.corrupt(AGAFARI));
+    }
+}
+#[test]
+fn corrupted_cmdline() {
+    const CMDLINE: &[u8] = br#"acpi.list heap.limit=128M irq.mask=3,4 name="ferrum os""#;
+    let mut rng = Rng(0x5EED_0004);
+    for _ in 0..ROUNDS {
+        parse_all(&rng.corrupt(CMDLINE));
+    }
+}
@@ -0,0 +1,56 @@
+//! Parsers for the binary formats used by FerrumOS
+//!
+//! Everything here works on byte slices and never touches hardware, so the
+//! crate builds for the kernel target as well as for the host, where the
+//! parsers are tested with plain `cargo test`.
+#![no_std]
+
+#[cfg(test)]
+extern crate std;
+
+pub mod acpi;
+mod bytes;
+pub mod cmdline;
+pub mod pit;
+pub mod psf;
+pub mod rtc;
+
+use core::fmt;
+
+/// Parser error type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// Reading `size` bytes at `offset` goes past the end of the data
+    UnexpectedEnd {
+        offset: usize,
+        size: usize,
+    },
+    InvalidSignature,
+    InvalidChecksum,
+    InvalidLength,
+    InvalidMagic,
+    InvalidUtf8,
+    InvalidValue,
+}
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedEnd { offset, size } => {
+                write!(
+                    f,
+                    "unexpected end of data reading {} bytes at {:#x}",
+                    size, offset
+                )
+            }
+            ParseError::InvalidSignature => write!(f, "invalid signature"),
+            ParseError::InvalidChecksum => write!(f, "invalid checksum"),
+            ParseError::InvalidLength => write!(f, "invalid length"),
+            ParseError::InvalidMagic => write!(f, "invalid magic number"),
+            ParseError::InvalidUtf8 => write!(f, "invalid UTF-8 sequence"),
+            ParseError::InvalidValue => write!(f, "invalid value"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod fuzz;
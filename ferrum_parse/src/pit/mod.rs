@@ -0,0 +1,122 @@
+//! Mode/command register of the Programmable Interval Timer
+use crate::ParseError;
+
+/// Value written to the mode/command register (port 0x43)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PITConfig(u8);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PITOperatingMode {
+    InterruptOnTerminalCount = 0b000,
+    HardwareRetriggerableOneShot = 0b001,
+    RateGenerator = 0b010,
+    SquareWaveGenerator = 0b011,
+    SoftwareTriggeredStrobe = 0b100,
+    HardwareTriggeredStrobe = 0b101,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PITAccessMode {
+    AccessLowByte = 0b01,
+    AccessHighByte = 0b10,
+    AccessLowByteThenHighByte = 0b11,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PITChannel {
+    Channel0 = 0b00,
+    Channel1 = 0b01,
+    Channel2 = 0b10,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PITEncoding {
+    Binary = 0b0,
+    BCD = 0b1,
+}
+const ENCODING_MASK: u8 = 0b0000_0001;
+const MODE_MASK: u8 = 0b0000_1110;
+const ACCESS_MODE_MASK: u8 = 0b0011_0000;
+const CHANNEL_MASK: u8 = 0b1100_0000;
+
+impl Default for PITConfig {
+    fn default() -> Self {
+        PITConfig::new()
+    }
+}
+impl PITConfig {
+    pub fn new() -> Self {
+        PITConfig(0b0)
+    }
+    pub fn build_from(
+        encoding: PITEncoding,
+        mode: PITOperatingMode,
+        access_mode: PITAccessMode,
+        channel: PITChannel,
+    ) -> Self {
+        PITConfig(
+            (encoding as u8)
+                | ((mode as u8) << 1)
+                | ((access_mode as u8) << 4)
+                | ((channel as u8) << 6),
+        )
+    }
+    /// Decode a raw command byte
+    ///
+    /// Fails for the latch count and read back commands, which do not configure a channel.
+    pub fn parse(value: u8) -> Result<Self, ParseError> {
+        let config = PITConfig(value);
+        config.access_mode()?;
+        config.channel()?;
+        Ok(config)
+    }
+    pub fn get_config(&self) -> u8 {
+        self.0
+    }
+    pub fn encoding(&self) -> PITEncoding {
+        match self.0 & ENCODING_MASK {
+            0 => PITEncoding::Binary,
+            _ => PITEncoding::BCD,
+        }
+    }
+    pub fn mode(&self) -> PITOperatingMode {
+        match (self.0 & MODE_MASK) >> 1 {
+            0b000 => PITOperatingMode::InterruptOnTerminalCount,
+            0b001 => PITOperatingMode::HardwareRetriggerableOneShot,
+            // 0b110 and 0b111 are aliases of modes 2 and 3
+            0b010 | 0b110 => PITOperatingMode::RateGenerator,
+            0b011 | 0b111 => PITOperatingMode::SquareWaveGenerator,
+            0b100 => PITOperatingMode::SoftwareTriggeredStrobe,
+            _ => PITOperatingMode::HardwareTriggeredStrobe,
+        }
+    }
+    pub fn access_mode(&self) -> Result<PITAccessMode, ParseError> {
+        match (self.0 & ACCESS_MODE_MASK) >> 4 {
+            0b01 => Ok(PITAccessMode::AccessLowByte),
+            0b10 => Ok(PITAccessMode::AccessHighByte),
+            0b11 => Ok(PITAccessMode::AccessLowByteThenHighByte),
+            // Latch count value command
+            _ => Err(ParseError::InvalidValue),
+        }
+    }
+    pub fn channel(&self) -> Result<PITChannel, ParseError> {
+        match (self.0 & CHANNEL_MASK) >> 6 {
+            0b00 => Ok(PITChannel::Channel0),
+            0b01 => Ok(PITChannel::Channel1),
+            0b10 => Ok(PITChannel::Channel2),
+            // Read back command
+            _ => Err(ParseError::InvalidValue),
+        }
+    }
+    pub fn set_encoding(&mut self, encoding: PITEncoding) {
+        self.0 = (self.0 & !ENCODING_MASK) | encoding as u8;
+    }
+    pub fn set_mode(&mut self, mode: PITOperatingMode) {
+        self.0 = (self.0 & !MODE_MASK) | (mode as u8) << 1;
+    }
+    pub fn set_access_mode(&mut self, access_mode: PITAccessMode) {
+        self.0 = (self.0 & !ACCESS_MODE_MASK) | (access_mode as u8) << 4;
+    }
+    pub fn set_channel(&mut self, channel: PITChannel) {
+        self.0 = (self.0 & !CHANNEL_MASK) | (channel as u8) << 6;
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,70 @@
+use super::*;
+use crate::ParseError;
+
+#[test]
+fn build_matches_raw_value() {
+    let cases = [
+        (
+            PITEncoding::Binary,
+            PITOperatingMode::RateGenerator,
+            PITAccessMode::AccessLowByteThenHighByte,
+            PITChannel::Channel0,
+            0b0011_0100,
+        ),
+        (
+            PITEncoding::Binary,
+            PITOperatingMode::RateGenerator,
+            PITAccessMode::AccessLowByteThenHighByte,
+            PITChannel::Channel2,
+            0b1011_0100,
+        ),
+        (
+            PITEncoding::BCD,
+            PITOperatingMode::SquareWaveGenerator,
+            PITAccessMode::AccessLowByte,
+            PITChannel::Channel1,
+            0b0101_0111,
+        ),
+    ];
+    for (encoding, mode, access_mode, channel, raw) in cases {
+        let config = PITConfig::build_from(encoding, mode, access_mode, channel);
+        assert_eq!(config.get_config(), raw);
+        let parsed = PITConfig::parse(raw).unwrap();
+        assert_eq!(parsed.encoding(), encoding);
+        assert_eq!(parsed.mode(), mode);
+        assert_eq!(parsed.access_mode(), Ok(access_mode));
+        assert_eq!(parsed.channel(), Ok(channel));
+    }
+}
+#[test]
+fn setters_replace_fields() {
+    let mut config = PITConfig::build_from(
+        PITEncoding::BCD,
+        PITOperatingMode::HardwareTriggeredStrobe,
+        PITAccessMode::AccessLowByteThenHighByte,
+        PITChannel::Channel2,
+    );
+    config.set_encoding(PITEncoding::Binary);
+    config.set_mode(PITOperatingMode::RateGenerator);
+    config.set_access_mode(PITAccessMode::AccessHighByte);
+    config.set_channel(PITChannel::Channel0);
+    assert_eq!(config.get_config(), 0b0010_0100);
+}
+#[test]
+fn mode_aliases() {
+    assert_eq!(
+        PITConfig(0b0011_1100).mode(),
+        PITOperatingMode::RateGenerator
+    );
+    assert_eq!(
+        PITConfig(0b0011_1110).mode(),
+        PITOperatingMode::SquareWaveGenerator
+    );
+}
+#[test]
+fn commands_that_are_not_configurations() {
+    // Latch count value of channel 0
+    assert_eq!(PITConfig::parse(0b0000_0000), Err(ParseError::InvalidValue));
+    // Read back
+    assert_eq!(PITConfig::parse(0b1110_0010), Err(ParseError::InvalidValue));
+}
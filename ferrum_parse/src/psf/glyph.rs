@@ -0,0 +1,87 @@
+//! Unicode table of PSF2 fonts
+use crate::ParseError;
+
+/// Separates the single code points of a glyph from its code point sequences
+const PSF2_SEPARATOR: u8 = 0xFE;
+/// Ends the entry of a glyph
+const PSF2_TERMINATOR: u8 = 0xFF;
+
+/// Decode the UTF-8 character at the start of `data`
+///
+/// Returns the code point and the number of bytes it was encoded in.
+pub fn decode_utf8(data: &[u8]) -> Result<(u32, usize), ParseError> {
+    let first = *data
+        .first()
+        .ok_or(ParseError::UnexpectedEnd { offset: 0, size: 1 })?;
+    let (length, initial) = match first {
+        0x00..=0x7F => return Ok((first as u32, 1)),
+        // Continuation byte
+        0x80..=0xBF => return Err(ParseError::InvalidUtf8),
+        0xC0..=0xDF => (2, first & 0b0001_1111),
+        0xE0..=0xEF => (3, first & 0b0000_1111),
+        0xF0..=0xF7 => (4, first & 0b0000_0111),
+        _ => return Err(ParseError::InvalidUtf8),
+    };
+    let bytes = data.get(1..length).ok_or(ParseError::UnexpectedEnd {
+        offset: 0,
+        size: length,
+    })?;
+    let mut code_point = initial as u32;
+    for byte in bytes {
+        if byte & 0b1100_0000 != 0b1000_0000 {
+            return Err(ParseError::InvalidUtf8);
+        }
+        code_point = (code_point << 6) | (byte & 0b0011_1111) as u32;
+    }
+    Ok((code_point, length))
+}
+
+/// Iterator over the `(code point, glyph index)` pairs of a unicode table
+///
+/// Only single code points are returned, the sequences following `0xFE` are skipped.
+/// Malformed characters are skipped as well.
+#[derive(Debug, Clone)]
+pub struct UnicodeTable<'a> {
+    data: &'a [u8],
+    offset: usize,
+    glyph: u32,
+    in_sequences: bool,
+}
+impl<'a> UnicodeTable<'a> {
+    pub(super) fn new(data: &'a [u8]) -> Self {
+        UnicodeTable {
+            data,
+            offset: 0,
+            glyph: 0,
+            in_sequences: false,
+        }
+    }
+}
+impl Iterator for UnicodeTable<'_> {
+    type Item = (u32, u32);
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(&byte) = self.data.get(self.offset) {
+            match byte {
+                PSF2_TERMINATOR => {
+                    self.offset += 1;
+                    self.glyph += 1;
+                    self.in_sequences = false;
+                }
+                PSF2_SEPARATOR => {
+                    self.offset += 1;
+                    self.in_sequences = true;
+                }
+                _ => match decode_utf8(&self.data[self.offset..]) {
+                    Ok((code_point, length)) => {
+                        self.offset += length;
+                        if !self.in_sequences {
+                            return Some((code_point, self.glyph));
+                        }
+                    }
+                    Err(_) => self.offset += 1,
+                },
+            }
+        }
+        None
+    }
+}
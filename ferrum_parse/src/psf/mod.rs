@@ -0,0 +1,89 @@
+//! PC Screen Font (version 2) parsing
+use crate::bytes::{slice, u32_at};
+use crate::ParseError;
+
+mod glyph;
+pub use glyph::{decode_utf8, UnicodeTable};
+
+/// Magic number at the start of every PSF2 file
+pub const PSF2_MAGIC: u32 = 0x864a_b572;
+/// Size of the PSF2 header
+pub const PSF2_HEADER_SIZE: usize = 32;
+/// Flag set when the font has a unicode table
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x1;
+
+/// A PSF2 font borrowing its glyphs from the font file
+#[derive(Debug, Clone, Copy)]
+pub struct PsfFont<'a> {
+    pub version: u32,
+    pub headersize: u32,
+    pub flags: u32,
+    pub numglyph: u32,
+    pub bytesperglyph: u32,
+    pub height: u32,
+    pub width: u32,
+    glyphs: &'a [u8],
+    unicode_table: &'a [u8],
+}
+impl<'a> PsfFont<'a> {
+    /// Parse the font file in `data`
+    pub fn parse(data: &'a [u8]) -> Result<Self, ParseError> {
+        if u32_at(data, 0)? != PSF2_MAGIC {
+            return Err(ParseError::InvalidMagic);
+        }
+        let headersize = u32_at(data, 8)?;
+        let numglyph = u32_at(data, 16)?;
+        let bytesperglyph = u32_at(data, 20)?;
+        let height = u32_at(data, 24)?;
+        let width = u32_at(data, 28)?;
+        // Every row of a glyph is padded to a whole number of bytes
+        let row_size = width.div_ceil(8);
+        if (headersize as usize) < PSF2_HEADER_SIZE || row_size * height > bytesperglyph {
+            return Err(ParseError::InvalidLength);
+        }
+        let glyphs_size = (numglyph as usize)
+            .checked_mul(bytesperglyph as usize)
+            .ok_or(ParseError::InvalidLength)?;
+        let glyphs = slice(data, headersize as usize, glyphs_size)?;
+        let flags = u32_at(data, 12)?;
+        let unicode_table = if flags & PSF2_HAS_UNICODE_TABLE != 0 {
+            &data[headersize as usize + glyphs_size..]
+        } else {
+            &[]
+        };
+        Ok(PsfFont {
+            version: u32_at(data, 4)?,
+            headersize,
+            flags,
+            numglyph,
+            bytesperglyph,
+            height,
+            width,
+            glyphs,
+            unicode_table,
+        })
+    }
+    /// Bitmap of the glyph at `index`, one bit per pixel, rows padded to whole bytes
+    pub fn glyph(&self, index: u32) -> Option<&'a [u8]> {
+        let size = self.bytesperglyph as usize;
+        let start = (index as usize).checked_mul(size)?;
+        self.glyphs.get(start..start.checked_add(size)?)
+    }
+    /// Number of bytes of every row of a glyph
+    pub fn row_size(&self) -> usize {
+        self.width.div_ceil(8) as usize
+    }
+    /// The code points described by the unicode table, with the glyph they map to
+    ///
+    /// Fonts without a unicode table map code point `n` to glyph `n`.
+    pub fn unicode_table(&self) -> UnicodeTable<'a> {
+        UnicodeTable::new(self.unicode_table)
+    }
+    /// Returns true if the font has a unicode table
+    pub fn has_unicode_table(&self) -> bool {
+        self.flags & PSF2_HAS_UNICODE_TABLE != 0
+    }
+}
+
+#[cfg(test)]
+mod tests;
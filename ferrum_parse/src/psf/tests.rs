@@ -0,0 +1,94 @@
+use super::*;
+use crate::ParseError;
+use std::vec::Vec;
+
+/// The font embedded in the kernel
+const AGAFARI: &[u8] =
+    include_bytes!("../../../kernel/src/drivers/fonts/font_files/Agafari-16.psfu");
+
+#[test]
+fn agafari_header() {
+    let font = PsfFont::parse(AGAFARI).unwrap();
+    assert_eq!(font.numglyph, 512);
+    assert_eq!(font.bytesperglyph, 16);
+    assert_eq!((font.width, font.height), (8, 16));
+    assert_eq!(font.row_size(), 1);
+    assert!(font.has_unicode_table());
+    assert!(font.glyph(511).is_some());
+    assert!(font.glyph(512).is_none());
+}
+#[test]
+fn agafari_unicode_table() {
+    let font = PsfFont::parse(AGAFARI).unwrap();
+    let table: Vec<(u32, u32)> = font.unicode_table().collect();
+    let glyph_of = |c: char| {
+        table
+            .iter()
+            .find(|(code_point, _)| *code_point == c as u32)
+            .map(|(_, glyph)| *glyph)
+    };
+    // Printable ASCII is present and every glyph index is in range
+    for c in ' '..='~' {
+        assert!(glyph_of(c).is_some(), "no glyph for {:?}", c);
+    }
+    assert!(table.iter().all(|(_, glyph)| *glyph < font.numglyph));
+    // 'A' is drawn, ' ' is blank
+    let a = font.glyph(glyph_of('A').unwrap()).unwrap();
+    assert!(a.iter().any(|row| *row != 0));
+    let space = font.glyph(glyph_of(' ').unwrap()).unwrap();
+    assert!(space.iter().all(|row| *row == 0));
+}
+#[test]
+fn invalid_fonts() {
+    let mut magic = AGAFARI.to_vec();
+    magic[0] = 0;
+    assert_eq!(PsfFont::parse(&magic).err(), Some(ParseError::InvalidMagic));
+    // The glyphs do not fit in the file
+    assert!(matches!(
+        PsfFont::parse(&AGAFARI[..1000]),
+        Err(ParseError::UnexpectedEnd { .. })
+    ));
+    // The glyphs are smaller than their bitmaps
+    let mut size = AGAFARI.to_vec();
+    size[20] = 8;
+    assert_eq!(PsfFont::parse(&size).err(), Some(ParseError::InvalidLength));
+}
+/// Input and expected result of a decoding
+type Utf8Case<'a> = (&'a [u8], Result<(u32, usize), ParseError>);
+
+#[test]
+fn utf8_decoding() {
+    let cases: [Utf8Case; 8] = [
+        (b"A", Ok((0x41, 1))),
+        ("é".as_bytes(), Ok((0xE9, 2))),
+        ("€".as_bytes(), Ok((0x20AC, 3))),
+        ("😀".as_bytes(), Ok((0x1F600, 4))),
+        (&[0x80], Err(ParseError::InvalidUtf8)),
+        (&[0xC3, 0x41], Err(ParseError::InvalidUtf8)),
+        (&[0xF8, 0x80], Err(ParseError::InvalidUtf8)),
+        (
+            &[0xE2, 0x82],
+            Err(ParseError::UnexpectedEnd { offset: 0, size: 3 }),
+        ),
+    ];
+    for (data, expected) in cases {
+        assert_eq!(decode_utf8(data), expected, "decoding {:x?}", data);
+    }
+}
+#[test]
+fn unicode_table_sequences() {
+    // Glyph 0: 'a' and 'b', glyph 1: 'c' then a sequence, glyph 2: invalid byte then 'd'
+    let data = [
+        b'a', b'b', 0xFF, b'c', 0xFE, b'x', b'y', 0xFF, 0x80, b'd', 0xFF,
+    ];
+    let table: Vec<_> = UnicodeTable::new(&data).collect();
+    assert_eq!(
+        table,
+        [
+            (b'a' as u32, 0),
+            (b'b' as u32, 0),
+            (b'c' as u32, 1),
+            (b'd' as u32, 2)
+        ]
+    );
+}
@@ -0,0 +1,187 @@
+//! Decoding of the CMOS real-time clock registers and calendar arithmetic
+//!
+//! Depending on status register B the RTC stores its values in BCD or binary,
+//! and the hours in 12 hour format with bit 7 marking PM, or in 24 hour format.
+use crate::ParseError;
+use core::fmt;
+
+/// Status register B: values are binary instead of BCD
+pub const STATUS_B_BINARY: u8 = 1 << 2;
+/// Status register B: hours use the 24 hour format
+pub const STATUS_B_24_HOUR: u8 = 1 << 1;
+/// Hour register bit marking PM in 12 hour format
+const HOUR_PM: u8 = 1 << 7;
+
+/// Raw time and date registers of the RTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RtcRegisters {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    pub day: u8,
+    pub month: u8,
+    /// Year within the century
+    pub year: u8,
+    /// Century register, when the FADT names one
+    pub century: Option<u8>,
+    pub status_b: u8,
+}
+impl RtcRegisters {
+    /// Decode the registers into a date, validating every field
+    ///
+    /// Without a century register the year is assumed to be in 2000-2099.
+    pub fn decode(&self) -> Result<DateTime, ParseError> {
+        let binary = self.status_b & STATUS_B_BINARY != 0;
+        let value = |raw: u8| if binary { Ok(raw) } else { from_bcd(raw) };
+        let pm = self.hours & HOUR_PM != 0;
+        let mut hour = value(self.hours & !HOUR_PM)?;
+        if self.status_b & STATUS_B_24_HOUR == 0 {
+            if !(1..=12).contains(&hour) {
+                return Err(ParseError::InvalidValue);
+            }
+            // 12 AM is midnight and 12 PM is noon
+            hour = hour % 12 + if pm { 12 } else { 0 };
+        }
+        let century = match self.century {
+            Some(century) => value(century)? as u16,
+            None => 20,
+        };
+        let date = DateTime {
+            year: century * 100 + value(self.year)? as u16,
+            month: value(self.month)?,
+            day: value(self.day)?,
+            hour,
+            minute: value(self.minutes)?,
+            second: value(self.seconds)?,
+        };
+        date.validate()?;
+        Ok(date)
+    }
+}
+/// Encode `value` the way the RTC stores it according to `status_b`, for alarms
+pub fn encode_value(value: u8, status_b: u8) -> u8 {
+    if status_b & STATUS_B_BINARY != 0 {
+        value
+    } else {
+        to_bcd(value)
+    }
+}
+/// Encode a 0-23 hour the way the RTC stores it according to `status_b`
+pub fn encode_hour(hour: u8, status_b: u8) -> u8 {
+    if status_b & STATUS_B_24_HOUR != 0 {
+        return encode_value(hour, status_b);
+    }
+    let pm = if hour >= 12 { HOUR_PM } else { 0 };
+    let hour = match hour % 12 {
+        0 => 12,
+        hour => hour,
+    };
+    encode_value(hour, status_b) | pm
+}
+fn from_bcd(value: u8) -> Result<u8, ParseError> {
+    let (high, low) = (value >> 4, value & 0x0F);
+    if high > 9 || low > 9 {
+        return Err(ParseError::InvalidValue);
+    }
+    Ok(high * 10 + low)
+}
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// A calendar date and time of day, in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+impl DateTime {
+    fn validate(&self) -> Result<(), ParseError> {
+        let valid = (1..=12).contains(&self.month)
+            && self.day >= 1
+            && self.day <= days_in_month(self.year, self.month)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60;
+        if valid {
+            Ok(())
+        } else {
+            Err(ParseError::InvalidValue)
+        }
+    }
+    /// Seconds since 1970-01-01 00:00:00 UTC, negative before
+    pub fn to_unix_seconds(&self) -> i64 {
+        let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64);
+        days * 86_400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+    }
+    /// The date `seconds` after 1970-01-01 00:00:00 UTC
+    ///
+    /// Years outside of 0-65535 wrap around.
+    pub fn from_unix_seconds(seconds: i64) -> Self {
+        let days = seconds.div_euclid(86_400);
+        let time = seconds.rem_euclid(86_400);
+        let (year, month, day) = civil_from_days(days);
+        DateTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (time / 3600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+        }
+    }
+}
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+fn is_leap_year(year: u16) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+/// Days since 1970-01-01 of a proleptic Gregorian date (Howard Hinnant's algorithm)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+/// Inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,143 @@
+use super::*;
+use crate::ParseError;
+
+fn date(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+    DateTime {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+    }
+}
+
+#[test]
+fn decode_formats() {
+    let cases: [(RtcRegisters, DateTime); 4] = [
+        // BCD, 24 hour, no century register
+        (
+            RtcRegisters {
+                seconds: 0x56,
+                minutes: 0x34,
+                hours: 0x23,
+                day: 0x29,
+                month: 0x02,
+                year: 0x24,
+                century: None,
+                status_b: STATUS_B_24_HOUR,
+            },
+            date(2024, 2, 29, 23, 34, 56),
+        ),
+        // BCD, 12 hour PM, century register
+        (
+            RtcRegisters {
+                seconds: 0x00,
+                minutes: 0x15,
+                hours: HOUR_PM | 0x01,
+                day: 0x31,
+                month: 0x12,
+                year: 0x99,
+                century: Some(0x19),
+                status_b: 0,
+            },
+            date(1999, 12, 31, 13, 15, 0),
+        ),
+        // Binary, 12 hour, 12 AM is midnight
+        (
+            RtcRegisters {
+                seconds: 59,
+                minutes: 59,
+                hours: 12,
+                day: 1,
+                month: 1,
+                year: 30,
+                century: Some(20),
+                status_b: STATUS_B_BINARY,
+            },
+            date(2030, 1, 1, 0, 59, 59),
+        ),
+        // Binary, 12 hour, 12 PM is noon
+        (
+            RtcRegisters {
+                seconds: 0,
+                minutes: 0,
+                hours: HOUR_PM | 12,
+                day: 15,
+                month: 6,
+                year: 0,
+                century: None,
+                status_b: STATUS_B_BINARY,
+            },
+            date(2000, 6, 15, 12, 0, 0),
+        ),
+    ];
+    for (registers, expected) in cases {
+        assert_eq!(registers.decode(), Ok(expected), "decoding {:?}", registers);
+    }
+}
+#[test]
+fn decode_invalid() {
+    let valid = RtcRegisters {
+        day: 0x01,
+        month: 0x01,
+        status_b: STATUS_B_24_HOUR,
+        ..RtcRegisters::default()
+    };
+    assert!(valid.decode().is_ok());
+    let cases = [
+        RtcRegisters {
+            seconds: 0x5A,
+            ..valid
+        },
+        RtcRegisters {
+            month: 0x13,
+            ..valid
+        },
+        RtcRegisters {
+            day: 0x29,
+            month: 0x02,
+            year: 0x23,
+            ..valid
+        },
+        RtcRegisters {
+            hours: 0x24,
+            ..valid
+        },
+        RtcRegisters {
+            hours: 0x00,
+            status_b: 0,
+            ..valid
+        },
+    ];
+    for registers in cases {
+        assert_eq!(registers.decode(), Err(ParseError::InvalidValue));
+    }
+}
+#[test]
+fn encode_alarm_values() {
+    assert_eq!(encode_value(59, 0), 0x59);
+    assert_eq!(encode_value(59, STATUS_B_BINARY), 59);
+    assert_eq!(encode_hour(0, 0), 0x12);
+    assert_eq!(encode_hour(13, 0), HOUR_PM | 0x01);
+    assert_eq!(encode_hour(12, STATUS_B_BINARY), HOUR_PM | 12);
+    assert_eq!(encode_hour(23, STATUS_B_24_HOUR), 0x23);
+}
+#[test]
+fn unix_time() {
+    let cases = [
+        (date(1969, 12, 31, 23, 59, 59), -1),
+        (date(1970, 1, 1, 0, 0, 0), 0),
+        (date(2000, 3, 1, 0, 0, 0), 951_868_800),
+        (date(2024, 2, 29, 12, 34, 56), 1_709_210_096),
+        (date(2099, 12, 31, 23, 59, 59), 4_102_444_799),
+    ];
+    for (date, seconds) in cases {
+        assert_eq!(date.to_unix_seconds(), seconds, "converting {}", date);
+        assert_eq!(DateTime::from_unix_seconds(seconds), date);
+    }
+    assert_eq!(
+        std::format!("{}", date(2024, 2, 9, 8, 7, 6)),
+        "2024-02-09 08:07:06"
+    );
+}
@@ -1,6 +1,20 @@
+use std::{env, fs, path::PathBuf};
+
 fn main() {
     // Tell cargo to pass the linker script to the linker..
     println!("cargo:rustc-link-arg=-Tlinker.ld");
     // ..and to re-run if it changes.
     println!("cargo:rerun-if-changed=linker.ld");
+    // Embed the symbol table generated by the GNUmakefile from a previous link,
+    // builds without one get an empty table and print raw addresses in backtraces
+    println!("cargo:rerun-if-env-changed=KERNEL_SYMBOLS");
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap()).join("kernel.sym");
+    let symbols = match env::var("KERNEL_SYMBOLS") {
+        Ok(path) => {
+            println!("cargo:rerun-if-changed={}", path);
+            fs::read(path).unwrap_or_default()
+        }
+        Err(_) => Vec::new(),
+    };
+    fs::write(out, symbols).unwrap();
 }
@@ -0,0 +1,46 @@
+#!/bin/bash
+# Cargo runner: boots the kernel ELF given by cargo in QEMU from a Limine ISO.
+# Test kernels (named <binary>-<hash> by cargo) run headless, the code written to
+# the isa-debug-exit device is mapped to the result of the process.
+set -e
+
+KERNEL=$1
+ROOT=$(cd "$(dirname "$0")/.." && pwd)
+LIMINE=$ROOT/limine
+# Whole test kernel timeout in seconds, each test also has an in kernel timeout
+TEST_TIMEOUT=${TEST_TIMEOUT:-300}
+# (QemuExitCode::Success << 1) | 1
+SUCCESS_CODE=33
+
+[ -d "$LIMINE" ] || make -C "$ROOT" limine
+
+WORK=$(mktemp -d)
+trap 'rm -rf "$WORK"' EXIT
+mkdir -p "$WORK/iso_root"
+cp "$KERNEL" "$WORK/iso_root/kernel.elf"
+cp "$ROOT/limine.cfg" "$LIMINE/limine.sys" "$LIMINE/limine-cd.bin" "$LIMINE/limine-cd-efi.bin" \
+    "$WORK/iso_root/"
+xorriso -as mkisofs -b limine-cd.bin \
+    -no-emul-boot -boot-load-size 4 -boot-info-table \
+    --efi-boot limine-cd-efi.bin \
+    -efi-boot-part --efi-boot-image --protective-msdos-label \
+    "$WORK/iso_root" -o "$WORK/image.iso" 2>/dev/null
+"$LIMINE/limine-deploy" "$WORK/image.iso" >/dev/null 2>&1
+
+QEMU_PARAMS="-M q35 -m 2G -smp 4 -cdrom $WORK/image.iso -boot d \
+    -device isa-debug-exit,iobase=0xf4,iosize=0x04 -serial stdio"
+
+if [[ ! "$(basename "$KERNEL")" =~ -[0-9a-f]{16}$ ]]; then
+    qemu-system-x86_64 $QEMU_PARAMS
+    exit
+fi
+
+set +e
+timeout "$TEST_TIMEOUT" qemu-system-x86_64 $QEMU_PARAMS -display none -no-reboot
+CODE=$?
+set -e
+case $CODE in
+    $SUCCESS_CODE) exit 0 ;;
+    124) echo "Test kernel timed out after ${TEST_TIMEOUT}s"; exit 1 ;;
+    *) echo "Test kernel failed with QEMU exit code $CODE"; exit 1 ;;
+esac
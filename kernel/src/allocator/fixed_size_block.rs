@@ -1,10 +1,13 @@
 //! A fixed size block allocator.
-use super::Locked;
+use super::{
+    grow_heap_mapping, report_alloc_failure, HeapStats, Locked, HEAP_GROW_STEP, HEAP_LOW_WATERMARK,
+};
 use alloc::alloc::{GlobalAlloc, Layout};
 use core::{
     mem,
     ptr::{self, NonNull},
 };
+use x86_64::instructions::interrupts::without_interrupts;
 /// A node in the fixed size block allocator
 struct ListNode {
     next: Option<&'static mut ListNode>,
@@ -15,6 +18,7 @@ const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
 pub struct FixedSizeBlockAllocator {
     list_heads: [Option<&'static mut ListNode>; BLOCK_SIZES.len()],
     fallback_allocator: linked_list_allocator::Heap,
+    heap_limit: usize,
 }
 
 impl FixedSizeBlockAllocator {
@@ -25,22 +29,91 @@ impl FixedSizeBlockAllocator {
         FixedSizeBlockAllocator {
             list_heads: [EMPTY; BLOCK_SIZES.len()],
             fallback_allocator: linked_list_allocator::Heap::empty(),
+            heap_limit: 0,
         }
     }
     /// Initialize the allocator with the given heap bounds
-    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+    ///
+    /// The heap starts with `heap_size` mapped bytes and may grow up to `heap_limit` bytes.
+    /// # Safety
+    /// This function is unsafe because the caller must guarantee that the given heap bounds are
+    /// valid, that the memory in the heap bounds is unused and that the virtual range up to
+    /// `heap_start + heap_limit` is free to be mapped.
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize, heap_limit: usize) {
         // TO DO: Check if the conversion is correct
         // there may be a bug
         // Don't use the crate, instead use the linked_list_allocator
         // built by you
         self.fallback_allocator
             .init(heap_start as *mut u8, heap_size);
+        self.heap_limit = heap_limit.max(heap_size);
+    }
+    /// Set the upper bound the heap is allowed to grow to
+    pub fn set_limit(&mut self, heap_limit: usize) {
+        self.heap_limit = heap_limit.max(self.mapped_size());
+    }
+    /// Returns the current heap usage
+    pub fn stats(&self) -> HeapStats {
+        let size = self.mapped_size();
+        let used = self.fallback_allocator.used();
+        HeapStats {
+            size,
+            used,
+            free: size - used,
+            limit: self.heap_limit,
+        }
+    }
+    /// Number of bytes currently mapped for the heap
+    fn mapped_size(&self) -> usize {
+        self.fallback_allocator.top() as usize - self.fallback_allocator.bottom() as usize
     }
     /// Allocate a block of the given size using the fallback allocator
+    ///
+    /// If the fallback allocator runs dry the heap is grown and the
+    /// allocation is retried until it succeeds or the limit is reached.
+    /// Once less than `HEAP_LOW_WATERMARK` is left the heap is grown ahead of time.
     fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
-        match self.fallback_allocator.allocate_first_fit(layout) {
-            Ok(ptr) => ptr.as_ptr(),
-            Err(_) => ptr::null_mut(),
+        loop {
+            match self.fallback_allocator.allocate_first_fit(layout) {
+                Ok(ptr) => {
+                    if self.fallback_allocator.free() < HEAP_LOW_WATERMARK {
+                        // May fail while the VMM is busy, the next allocation tries again
+                        self.grow(Layout::new::<u8>());
+                    }
+                    return ptr.as_ptr();
+                }
+                Err(_) => {
+                    if !self.grow(layout) {
+                        report_alloc_failure(&layout, &self.stats());
+                        return ptr::null_mut();
+                    }
+                }
+            }
+        }
+    }
+    /// Map more memory at the top of the heap so that `layout` can fit
+    ///
+    /// Returns false if the limit is reached or no memory could be mapped.
+    fn grow(&mut self, layout: Layout) -> bool {
+        const PAGE_SIZE: usize = 4096;
+        let available = self.heap_limit.saturating_sub(self.mapped_size());
+        // Leave room for the alignment padding of the request
+        let required = layout.size().saturating_add(layout.align());
+        let size = required
+            .max(HEAP_GROW_STEP)
+            .next_multiple_of(PAGE_SIZE)
+            .min(available);
+        if size == 0 {
+            return false;
+        }
+        // The top of the heap is always page aligned since the heap grows in whole pages
+        let top = self.fallback_allocator.top() as usize;
+        match grow_heap_mapping(top, size) {
+            Ok(()) => {
+                unsafe { self.fallback_allocator.extend(size) };
+                true
+            }
+            Err(_) => false,
         }
     }
 }
@@ -54,7 +127,19 @@ fn list_index(layout: &Layout) -> Option<usize> {
 
 unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
     /// Allocate memory
+    ///
+    /// Interrupts stay disabled while the lock is held, a thread preempted
+    /// while holding it would block every other thread allocating.
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        without_interrupts(|| self.alloc_locked(layout))
+    }
+    /// Deallocate memory
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        without_interrupts(|| self.dealloc_locked(ptr, layout))
+    }
+}
+impl Locked<FixedSizeBlockAllocator> {
+    unsafe fn alloc_locked(&self, layout: Layout) -> *mut u8 {
         // Get a mutable reference to the allocator
         let mut allocator = self.lock();
         // Get the index of the block size that fits the given layout
@@ -86,8 +171,7 @@ unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
             }
         }
     }
-    /// Deallocate memory
-    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    unsafe fn dealloc_locked(&self, ptr: *mut u8, layout: Layout) {
         // Get a mutable reference to the allocator
         let mut allocator = self.lock();
         // Get the index of the block size that fits the given layout
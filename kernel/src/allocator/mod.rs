@@ -1,4 +1,6 @@
 //! This module contains the implementation of the heap allocator.
+use crate::boot_params::BootParam;
+use crate::memory::vmm::{self, VmmError};
 use x86_64::{
     structures::paging::{
         mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB,
@@ -48,26 +50,76 @@ static ALLOCATOR: Locked<FixedSizeBlockAllocator> = Locked::new(FixedSizeBlockAl
 
 /// The start address of the heap.
 pub const HEAP_START: usize = 0x_4444_4444_0000;
-/// The size of the heap.
-pub const HEAP_SIZE: usize = 100 * 1024; //100kb
+/// The size of the heap mapped at boot.
+pub const HEAP_INITIAL_SIZE: usize = 100 * 1024; //100kb
+/// The default upper bound for the heap size.
+pub const HEAP_DEFAULT_LIMIT: usize = 64 * 1024 * 1024; //64mb
+/// Upper bound for the heap size, `heap.limit=` on the command line.
+pub static HEAP_LIMIT: BootParam<usize> =
+    BootParam::new("heap.limit", "maximum heap size", HEAP_DEFAULT_LIMIT);
+/// The minimum amount of memory mapped each time the heap grows.
+pub const HEAP_GROW_STEP: usize = 64 * 1024; //64kb
+/// The heap grows ahead of time once less than this is free.
+///
+/// The VMM allocates while holding the lock the heap needs to grow.
+pub const HEAP_LOW_WATERMARK: usize = 16 * 1024; //16kb
+
+/// Snapshot of the heap usage
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    /// Bytes currently mapped for the heap
+    pub size: usize,
+    /// Bytes handed out by the fallback allocator, including cached blocks
+    pub used: usize,
+    /// Bytes mapped but not yet handed out
+    pub free: usize,
+    /// Upper bound the heap is allowed to grow to
+    pub limit: usize,
+}
+/// Returns the current heap usage.
+pub fn heap_stats() -> HeapStats {
+    ALLOCATOR.lock().stats()
+}
+/// Set the upper bound the heap is allowed to grow to.
+///
+/// The limit is never set below the size that is already mapped.
+pub fn set_heap_limit(limit: usize) {
+    ALLOCATOR.lock().set_limit(limit);
+}
 /// Initialize the heap allocator.
 pub fn init_heap(
     mapper: &mut impl Mapper<Size4KiB>,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    map_heap_range(HEAP_START, HEAP_INITIAL_SIZE, mapper, frame_allocator)?;
+    // Initialize the heap allocator
+    unsafe {
+        ALLOCATOR
+            .lock()
+            .init(HEAP_START, HEAP_INITIAL_SIZE, HEAP_LIMIT.get());
+    }
+    Ok(())
+}
+/// Map `size` bytes of fresh frames at the virtual address `start`.
+fn map_heap_range(
+    start: usize,
+    size: usize,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
 ) -> Result<(), MapToError<Size4KiB>> {
     // Define the range of virtual addresses that map to physical memory
     let page_range = {
-        // Define the start and end of the heap
-        let heap_start = VirtAddr::new(HEAP_START as u64);
-        let heap_end = heap_start
-            + HEAP_SIZE
+        // Define the start and end of the range
+        let range_start = VirtAddr::new(start as u64);
+        let range_end = range_start
+            + size
                 .try_into()
-                .expect("[Allocator]: Failed to fit usize into u64 in init_heap() method")
+                .expect("[Allocator]: Failed to fit usize into u64 in map_heap_range() method")
             - 1u64;
         // Define the range of pages that map to the heap
-        let heap_start_page = Page::containing_address(heap_start);
-        let heap_end_page = Page::containing_address(heap_end);
-        Page::range_inclusive(heap_start_page, heap_end_page)
+        let start_page = Page::containing_address(range_start);
+        let end_page = Page::containing_address(range_end);
+        Page::range_inclusive(start_page, end_page)
     };
     // Map each page to a frame
     for page in page_range {
@@ -77,9 +129,28 @@ pub fn init_heap(
         let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
         unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
     }
-    // Initialize the heap allocator
-    unsafe {
-        ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
-    }
     Ok(())
 }
+/// Map `size` more bytes at `start` through the VMM.
+///
+/// Called by the allocator when the heap runs low. On failure nothing is mapped.
+fn grow_heap_mapping(start: usize, size: usize) -> Result<(), VmmError> {
+    vmm::map_heap(VirtAddr::new(start as u64), size as u64)
+}
+/// Report a failed allocation together with the heap usage.
+///
+/// Uses the serial port only, since printing to the framebuffer may allocate.
+fn report_alloc_failure(layout: &core::alloc::Layout, stats: &HeapStats) {
+    crate::serial_println!(
+        "[Allocator]: Failed to allocate {} bytes (align {})",
+        layout.size(),
+        layout.align()
+    );
+    crate::serial_println!(
+        "[Allocator]: Heap size: {} used: {} free: {} limit: {}",
+        stats.size,
+        stats.used,
+        stats.free,
+        stats.limit
+    );
+}
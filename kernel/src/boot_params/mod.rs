@@ -0,0 +1,138 @@
+//! Kernel command line and boot parameters
+//!
+//! The command line is set with `CMDLINE=` in `limine.cfg` and handed over by
+//! Limine through the kernel file request. Subsystems declare their options as
+//! `BootParam` statics next to the code using them and list them in `PARAMS`,
+//! so unknown keys can be reported and the active configuration printed at boot.
+//!
+//! Parameters can be read before the heap exists, parsing never allocates.
+use core::fmt;
+use ferrum_parse::cmdline::{CmdLine, FromParam};
+use ferrum_parse::ParseError;
+use log::{debug, info, warn};
+
+/// Every parameter the kernel understands
+static PARAMS: &[&dyn ParamInfo] = &[
+    &crate::allocator::HEAP_LIMIT,
+    &crate::drivers::acpi::LIST_TABLES,
+    &crate::drivers::apic::routing::MASKED_IRQS,
+    &crate::timer::lapic::CALIBRATE,
+    &crate::timer::lapic::TICKLESS,
+    &crate::timer::hpet::ENABLE,
+    &crate::time::USE_TSC,
+    &crate::logger::LOG_LEVEL,
+    &crate::logger::SERIAL_LEVEL,
+    &crate::logger::CONSOLE_LEVEL,
+    &crate::task::scheduler::POLICY,
+    &crate::smp::ENABLE,
+];
+
+/// The raw command line, empty if the bootloader gave none or it is not valid UTF-8
+pub fn cmdline() -> &'static str {
+    crate::KERNEL_FILE_REQUEST
+        .get_response()
+        .and_then(|response| core::str::from_utf8(response.file().cmdline()).ok())
+        .unwrap_or("")
+}
+fn parsed_cmdline() -> CmdLine<'static> {
+    CmdLine::new(cmdline())
+}
+
+/// An option of the kernel set on the command line
+pub struct BootParam<T> {
+    name: &'static str,
+    description: &'static str,
+    default: T,
+}
+impl<T> BootParam<T>
+where
+    T: FromParam<'static> + Copy + fmt::Debug + Sync,
+{
+    pub const fn new(name: &'static str, description: &'static str, default: T) -> Self {
+        BootParam {
+            name,
+            description,
+            default,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+    pub fn default(&self) -> T {
+        self.default
+    }
+    /// The value given on the command line, the default if it is missing or invalid
+    pub fn get(&self) -> T {
+        self.parse().and_then(Result::ok).unwrap_or(self.default)
+    }
+    /// The value given on the command line, `None` if the parameter is missing
+    fn parse(&self) -> Option<Result<T, ParseError>> {
+        parsed_cmdline().value(self.name)
+    }
+}
+
+/// Type erased view of a `BootParam` for the registry
+trait ParamInfo: Sync {
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    /// Error of the value given on the command line, if any
+    fn error(&self) -> Option<ParseError>;
+    /// Write the current value
+    fn fmt_value(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+}
+impl<T> ParamInfo for BootParam<T>
+where
+    T: FromParam<'static> + Copy + fmt::Debug + Sync,
+{
+    fn name(&self) -> &'static str {
+        self.name
+    }
+    fn description(&self) -> &'static str {
+        self.description
+    }
+    fn error(&self) -> Option<ParseError> {
+        self.parse().and_then(Result::err)
+    }
+    fn fmt_value(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.get())
+    }
+}
+/// Prints a parameter with its value
+struct ParamDisplay<'a>(&'a dyn ParamInfo);
+impl fmt::Display for ParamDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}=", self.0.name())?;
+        self.0.fmt_value(f)?;
+        write!(f, " ({})", self.0.description())
+    }
+}
+
+/// Check the command line and print the boot configuration
+pub fn init() {
+    let raw = crate::KERNEL_FILE_REQUEST
+        .get_response()
+        .map(|response| response.file().cmdline())
+        .unwrap_or(&[]);
+    if CmdLine::from_bytes(raw).is_err() {
+        warn!("Ignoring command line, it is not valid UTF-8");
+    }
+    info!("Command line: \"{}\"", cmdline());
+    for param in parsed_cmdline().params() {
+        if !PARAMS.iter().any(|known| known.name() == param.key) {
+            warn!("Unknown parameter {}", param.key);
+        }
+    }
+    for param in PARAMS {
+        if let Some(error) = param.error() {
+            warn!(
+                "Invalid value for {} ({}), using the default",
+                param.name(),
+                error
+            );
+        }
+        debug!("{}", ParamDisplay(*param));
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,18 @@
+use super::*;
+
+#[test_case]
+fn test_defaults_without_cmdline() {
+    // The test kernel is booted without a command line
+    let param = BootParam::new("test.value", "test", 42u64);
+    assert_eq!(cmdline(), "");
+    assert_eq!(param.get(), 42);
+    assert!(param.error().is_none());
+}
+#[test_case]
+fn test_registered_names_are_unique() {
+    for (index, param) in PARAMS.iter().enumerate() {
+        assert!(PARAMS[index + 1..]
+            .iter()
+            .all(|other| other.name() != param.name()));
+    }
+}
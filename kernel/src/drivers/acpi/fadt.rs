@@ -0,0 +1,18 @@
+//! Fixed ACPI Description Table
+use super::{AcpiTable, ACPI_TABLES};
+use crate::memory::mmio::MmioRegion;
+/// The FADT, describes fixed hardware features like the RTC century register
+pub use ferrum_parse::acpi::Fadt as FADT;
+use ferrum_parse::ParseError;
+use lazy_static::lazy_static;
+
+impl AcpiTable for FADT {
+    const SIGNATURE: [u8; 4] = *b"FACP";
+    fn from_table(table: &MmioRegion) -> Result<Self, ParseError> {
+        FADT::parse(table.as_bytes())
+    }
+}
+lazy_static! {
+    /// The FADT of the system, parsed once on first use
+    pub static ref FADT_TABLE: Option<FADT> = ACPI_TABLES.find::<FADT>();
+}
@@ -0,0 +1,13 @@
+//! High Precision Event Timer Description Table
+use super::AcpiTable;
+use crate::memory::mmio::MmioRegion;
+/// The HPET table, describes the location of the timer block
+pub use ferrum_parse::acpi::Hpet as HPET;
+use ferrum_parse::ParseError;
+
+impl AcpiTable for HPET {
+    const SIGNATURE: [u8; 4] = *b"HPET";
+    fn from_table(table: &MmioRegion) -> Result<Self, ParseError> {
+        HPET::parse(table.as_bytes())
+    }
+}
@@ -1,178 +1,144 @@
-use super::ACPISDTHeader;
-use crate::{drivers::apic::io_apic::IOAPICStruct, serial_println};
+//! Multiple APIC Description Table parsing
+use super::{AcpiTable, ACPI_TABLES};
+use crate::memory::mmio::MmioRegion;
 use alloc::vec::Vec;
-#[allow(dead_code)]
-#[derive(Debug)]
-enum MADTEntry {
-    LocalApicEntry {
-        // Type 0
-        processor_id: u8, // ACPI Processor ID
-        apic_id: u8,      // Local APIC ID
-        flags: u32,       // Flags
-                          //    bit = 0: Processor Enabled
-                          //    bit = 1: Online Capable
-    },
-    IoApicEntry {
-        // Type 1
-        ioapic_id: u8,                     // IO APIC ID
-        ioapic_address: u32,               // IO APIC Address
-        global_system_interrupt_base: u32, // Global System Interrupt Base
-    },
-    IoApicInterruptSourceOverrideEntry {
-        // Type 2
-        bus_source: u8,
-        irq_source: u8,
-        global_system_interrupt: u32,
-        flags: u16,
-    },
-    // IoApicNmiSourceEntry {
-    //     // Type 3
-    //     nmi_source: u8,
-    //     flags: u16,
-    //     global_system_interrupt: u32,
-    // },
-    LocalApicNmiEntry {
-        // Type 4
-        processor_id: u8,
-        flags: u16,
-        local_apic_lint: u8,
-    },
-    // LocalApicAddressOverrideEntry {
-    //     local_apic_address: u64,
-    // },
-    // PeocessorLocalX2ApicEntry {
-    //     x2apic_id: u32,
-    //     flags: u32,
-    //     acpi_processor_uid: u32,
-    // },
+/// An interrupt controller structure of the MADT
+pub use ferrum_parse::acpi::MadtEntry as MADTEntry;
+use ferrum_parse::acpi::{Madt, SdtHeader};
+use ferrum_parse::ParseError;
+use lazy_static::lazy_static;
+use log::{debug, warn};
+/// A processor listed in the MADT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuInfo {
+    /// ACPI processor UID
+    pub processor_uid: u32,
+    /// Local APIC (or x2APIC) ID
+    pub apic_id: u32,
+    /// The processor is enabled and can be started
+    pub enabled: bool,
+    /// The processor is disabled but can be enabled at runtime
+    pub online_capable: bool,
+}
+/// An IOAPIC listed in the MADT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoApicInfo {
+    /// IOAPIC ID
+    pub id: u8,
+    /// Physical address of the registers
+    pub address: u32,
+    /// First global system interrupt handled by the IOAPIC
+    pub gsi_base: u32,
 }
 #[allow(dead_code)]
 pub struct MADT {
-    header: ACPISDTHeader,
+    header: SdtHeader,
     local_apic_address: u32,
     flags: u32,
-    entries_offset: u32,
     entries: Vec<MADTEntry>,
 }
 impl MADT {
-    pub fn new(base_ptr: u32) -> Self {
-        use core::ptr;
-
-        let header = ACPISDTHeader::new(base_ptr);
-        let header_size = core::mem::size_of::<ACPISDTHeader>() as u32;
-        let local_apic_address =
-            unsafe { ptr::read_unaligned((base_ptr + header_size) as *const u32) };
-        let flags = unsafe { ptr::read_unaligned((base_ptr + header_size + 4) as *const u32) };
-        let mut entries_offset = (base_ptr + header_size + 8) as u32;
+    pub fn new(table: &MmioRegion) -> Result<Self, ParseError> {
+        let madt = Madt::parse(table.as_bytes())?;
         let mut entries: Vec<MADTEntry> = Vec::new();
-        let mut left_to_read = header.length - header_size;
-        while left_to_read > 0 {
-            let entry_type = unsafe { ptr::read_unaligned(entries_offset as *const u8) };
-            let record_length = unsafe { ptr::read_unaligned((entries_offset + 1) as *const u8) };
-
-            match entry_type {
-                0 => {
-                    let processor_id =
-                        unsafe { ptr::read_unaligned((entries_offset + 2) as *const u8) };
-                    let apic_id = unsafe { ptr::read_unaligned((entries_offset + 3) as *const u8) };
-                    let flags = unsafe { ptr::read_unaligned((entries_offset + 4) as *const u32) };
-                    entries_offset += record_length as u32;
-
-                    let entry = MADTEntry::LocalApicEntry {
-                        processor_id,
-                        apic_id,
-                        flags,
-                    };
-                    entries.push(entry);
-                }
-                1 => {
-                    let ioapic_id =
-                        unsafe { ptr::read_unaligned((entries_offset + 2) as *const u8) };
-                    let ioapic_address =
-                        unsafe { ptr::read_unaligned((entries_offset + 4) as *const u32) };
-                    let global_system_interrupt_base =
-                        unsafe { ptr::read_unaligned((entries_offset + 8) as *const u32) };
-                    entries_offset += record_length as u32;
-                    let entry = MADTEntry::IoApicEntry {
-                        ioapic_id,
-                        ioapic_address,
-                        global_system_interrupt_base,
-                    };
-                    entries.push(entry);
-                }
-                2 => {
-                    let bus_source =
-                        unsafe { ptr::read_unaligned((entries_offset + 2) as *const u8) };
-                    let irq_source =
-                        unsafe { ptr::read_unaligned((entries_offset + 3) as *const u8) };
-                    let global_system_interrupt =
-                        unsafe { ptr::read_unaligned((entries_offset + 4) as *const u32) };
-                    let flags = unsafe { ptr::read_unaligned((entries_offset + 8) as *const u16) };
-                    entries_offset += record_length as u32;
-                    let entry = MADTEntry::IoApicInterruptSourceOverrideEntry {
-                        bus_source,
-                        irq_source,
-                        global_system_interrupt,
-                        flags,
-                    };
-                    entries.push(entry);
-                }
-                4 => {
-                    let processor_id =
-                        unsafe { ptr::read_unaligned((entries_offset + 2) as *const u8) };
-                    let flags = unsafe { ptr::read_unaligned((entries_offset + 3) as *const u16) };
-                    let local_apic_lint =
-                        unsafe { ptr::read_unaligned((entries_offset + 5) as *const u8) };
-                    entries_offset += record_length as u32;
-
-                    let entry = MADTEntry::LocalApicNmiEntry {
-                        processor_id,
-                        flags,
-                        local_apic_lint,
-                    };
+        for entry in madt.entries() {
+            match entry {
+                Ok(entry) => {
+                    if let MADTEntry::UnknownEntry { entry_type, .. } = entry {
+                        debug!("Unknown MADT entry type {}", entry_type);
+                    }
                     entries.push(entry);
                 }
-                _ => {
-                    entries_offset += record_length as u32;
-                    // println!("Unknown Entry Type In MADT Table: {}", entry_type);
+                Err(error) => {
+                    // The records after a corrupt one cannot be located
+                    warn!("Corrupt MADT entry: {}", error);
+                    break;
                 }
             }
-            //serial_println!("Entry: {:?}", entries.last().unwrap());
-            if record_length as u32 > left_to_read {
-                left_to_read = 0;
-            } else {
-                left_to_read -= record_length as u32;
-            }
         }
-        // for entry in entries.iter() {
-        //     serial_println!("{:?}", entry);
-        // }
         entries.shrink_to_fit();
-        MADT {
-            header,
-            local_apic_address,
-            flags,
-            entries_offset,
+        Ok(MADT {
+            header: madt.header,
+            local_apic_address: madt.local_apic_address,
+            flags: madt.flags,
             entries,
-        }
+        })
     }
-    pub fn get_ioapic(&self) -> Option<IOAPICStruct> {
-        for entry in self.entries.iter() {
-            match entry {
+    /// All the entries of the table in the order they appear
+    pub fn entries(&self) -> &[MADTEntry] {
+        &self.entries
+    }
+    /// Physical address of the local APIC, taking the address override entry into account
+    pub fn local_apic_address(&self) -> u64 {
+        self.entries
+            .iter()
+            .find_map(|entry| match entry {
+                MADTEntry::LocalApicAddressOverrideEntry { local_apic_address } => {
+                    Some(*local_apic_address)
+                }
+                _ => None,
+            })
+            .unwrap_or(self.local_apic_address as u64)
+    }
+    /// Returns true if the system also has dual 8259 PICs
+    pub fn has_legacy_pics(&self) -> bool {
+        self.flags & 1 != 0
+    }
+    /// All the processors described by local APIC and local x2APIC entries
+    pub fn cpus(&self) -> Vec<CpuInfo> {
+        self.entries
+            .iter()
+            .filter_map(|entry| match *entry {
+                MADTEntry::LocalApicEntry {
+                    processor_id,
+                    apic_id,
+                    flags,
+                } => Some(CpuInfo {
+                    processor_uid: processor_id as u32,
+                    apic_id: apic_id as u32,
+                    enabled: flags & 1 != 0,
+                    online_capable: flags & 2 != 0,
+                }),
+                MADTEntry::ProcessorLocalX2ApicEntry {
+                    x2apic_id,
+                    flags,
+                    acpi_processor_uid,
+                } => Some(CpuInfo {
+                    processor_uid: acpi_processor_uid,
+                    apic_id: x2apic_id,
+                    enabled: flags & 1 != 0,
+                    online_capable: flags & 2 != 0,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+    /// All the IOAPICs described by the table
+    pub fn io_apics(&self) -> Vec<IoApicInfo> {
+        self.entries
+            .iter()
+            .filter_map(|entry| match *entry {
                 MADTEntry::IoApicEntry {
                     ioapic_id,
                     ioapic_address,
                     global_system_interrupt_base,
-                } => {
-                    return Some(IOAPICStruct::new(
-                        *ioapic_id,
-                        *ioapic_address,
-                        *global_system_interrupt_base,
-                    ));
-                }
-                _ => {}
-            }
-        }
-        None
+                } => Some(IoApicInfo {
+                    id: ioapic_id,
+                    address: ioapic_address,
+                    gsi_base: global_system_interrupt_base,
+                }),
+                _ => None,
+            })
+            .collect()
     }
 }
+impl AcpiTable for MADT {
+    const SIGNATURE: [u8; 4] = *b"APIC";
+    fn from_table(table: &MmioRegion) -> Result<Self, ParseError> {
+        MADT::new(table)
+    }
+}
+lazy_static! {
+    /// The MADT of the system, parsed once on first use
+    pub static ref MADT_TABLE: MADT = ACPI_TABLES.find::<MADT>().expect("No MADT found");
+}
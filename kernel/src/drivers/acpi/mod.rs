@@ -1,52 +1,145 @@
-mod madt;
+//! ACPI table discovery
+//!
+//! Tables are found through the XSDT when the firmware provides an ACPI 2.0+
+//! RSDP and through the RSDT otherwise. Every table is checksummed before use.
+use crate::boot_params::BootParam;
+use crate::memory::mmio::{memremap, MmioRegion};
+use alloc::vec::Vec;
+use ferrum_parse::acpi::{parse_table, SdtHeader, SDT_HEADER_SIZE};
+use ferrum_parse::ParseError;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use rsdt::RSDT;
+use x86_64::PhysAddr;
+use xsdt::XSDT;
+
+pub mod fadt;
+pub mod hpet;
+pub mod madt;
 pub mod rsdp;
 pub mod rsdt;
+pub mod xsdt;
+
+/// ACPI error type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    InvalidRsdp,
+    InvalidRootTable,
+}
+/// A table that can be looked up by its signature
+pub trait AcpiTable: Sized {
+    /// Four character signature of the table
+    const SIGNATURE: [u8; 4];
+    /// Parse the table from its mapping, the checksum is already verified
+    fn from_table(table: &MmioRegion) -> Result<Self, ParseError>;
+}
 
-#[repr(C)]
-struct ACPISDTHeader {
-    signature: [u8; 4],
-    length: u32,
+/// Print the ACPI tables at boot, `acpi.list` on the command line
+pub static LIST_TABLES: BootParam<bool> =
+    BootParam::new("acpi.list", "list the ACPI tables at boot", false);
+
+lazy_static! {
+    /// The ACPI tables provided by the firmware
+    pub static ref ACPI_TABLES: AcpiTables =
+        AcpiTables::new().expect("[ACPI]: No valid ACPI tables found");
+}
+/// The list of tables referenced by the XSDT or the RSDT
+pub struct AcpiTables {
     revision: u8,
-    checksum: u8,
-    oem_id: [u8; 6],
-    oem_table_id: [u8; 8],
-    oem_revision: u32,
-    creator_id: u32,
-    creator_revision: u32,
+    tables: Vec<u64>,
 }
-use core::ptr;
-impl ACPISDTHeader {
-    fn new(base_ptr: u32) -> Self {
-        let signature =
-            unsafe { ptr::read_unaligned((base_ptr as *const [u8; 4]) as *const [u8; 4]) };
-        let length = unsafe { ptr::read_unaligned((base_ptr as u64 + 4) as *const u32) };
-        let revision = unsafe { ptr::read_unaligned((base_ptr as u64 + 8) as *const u8) };
-        let checksum = unsafe { ptr::read_unaligned((base_ptr as u64 + 9) as *const u8) };
-        let oem_id = unsafe { ptr::read_unaligned((base_ptr as u64 + 10) as *const [u8; 6]) };
-        let oem_table_id = unsafe { ptr::read_unaligned((base_ptr as u64 + 16) as *const [u8; 8]) };
-        let oem_revision = unsafe { ptr::read_unaligned((base_ptr as u64 + 24) as *const u32) };
-        let creator_id = unsafe { ptr::read_unaligned((base_ptr as u64 + 28) as *const u32) };
-        let creator_revision = unsafe { ptr::read_unaligned((base_ptr as u64 + 32) as *const u32) };
-        ACPISDTHeader {
-            signature,
-            length,
-            revision,
-            checksum,
-            oem_id,
-            oem_table_id,
-            oem_revision,
-            creator_id,
-            creator_revision,
+impl AcpiTables {
+    /// Locate the root table through the RSDP provided by the bootloader
+    ///
+    /// The XSDT is preferred when the RSDP is revision 2 or newer and its
+    /// checksum is valid, the RSDT is used otherwise.
+    pub fn new() -> Result<Self, AcpiError> {
+        let rsdp = rsdp::bootloader_rsdp().map_err(|_| AcpiError::InvalidRsdp)?;
+        if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+            match XSDT::new(rsdp.xsdt_address) {
+                Ok(xsdt) => {
+                    return Ok(AcpiTables {
+                        revision: rsdp.revision,
+                        tables: xsdt.entries().to_vec(),
+                    })
+                }
+                Err(error) => {
+                    warn!("Invalid XSDT ({}), falling back to the RSDT", error);
+                }
+            }
+        }
+        let rsdt = RSDT::new(rsdp.rsdt_address).map_err(|_| AcpiError::InvalidRootTable)?;
+        Ok(AcpiTables {
+            revision: rsdp.revision,
+            tables: rsdt.entries().to_vec(),
+        })
+    }
+    /// Revision of the RSDP the tables were found through
+    pub fn revision(&self) -> u8 {
+        self.revision
+    }
+    /// Physical addresses of all tables
+    pub fn addresses(&self) -> &[u64] {
+        &self.tables
+    }
+    /// Map the first table with the given signature and a valid checksum
+    pub fn find_table(&self, signature: &[u8; 4]) -> Option<MmioRegion> {
+        for address in self.tables.iter() {
+            let table = map_table(*address);
+            match SdtHeader::parse(table.as_bytes()) {
+                Ok(header) if header.signature == *signature => {}
+                _ => continue,
+            }
+            match parse_table(table.as_bytes(), signature) {
+                Ok(_) => return Some(table),
+                Err(error) => {
+                    warn!(
+                        "Skipping invalid {} table: {}",
+                        core::str::from_utf8(signature).unwrap_or("????"),
+                        error
+                    );
+                }
+            }
         }
+        None
     }
-    fn get_sum(&self) -> u32 {
-        let mut sum: u32 = 0;
-        let ptr = self as *const Self as *const u8;
-        let size = core::mem::size_of::<Self>();
-        for i in 0..size {
-            let oct = unsafe { *ptr.offset(i as isize) };
-            sum += oct as u32;
+    /// Find and parse the table of type `T`
+    pub fn find<T: AcpiTable>(&self) -> Option<T> {
+        let table = self.find_table(&T::SIGNATURE)?;
+        match T::from_table(&table) {
+            Ok(parsed) => Some(parsed),
+            Err(error) => {
+                warn!("Failed to parse table: {}", error);
+                None
+            }
         }
-        sum
     }
+    /// Print the signature of every table to the serial port
+    pub fn list_tables(&self) {
+        info!("Listing Tables");
+        let total_tables = self.tables.len();
+        for (i, address) in self.tables.iter().enumerate() {
+            let table = map_table(*address);
+            match SdtHeader::parse(table.as_bytes()) {
+                Ok(header) => {
+                    info!("#{}/{} {}", i + 1, total_tables, header.signature_str());
+                }
+                Err(error) => {
+                    info!("#{}/{} {}", i + 1, total_tables, error);
+                }
+            }
+        }
+    }
+}
+
+/// Map the ACPI table at the physical address `phys` together with its whole body
+fn map_table(phys: u64) -> MmioRegion {
+    let phys = PhysAddr::new(phys);
+    let header_size = SDT_HEADER_SIZE as u64;
+    // Map the header first to find out the length of the table
+    let length = {
+        let header = memremap(phys, header_size).expect("[ACPI]: Failed to map table header");
+        header.read_unaligned::<u32>(4) as u64
+    };
+    memremap(phys, length.max(header_size)).expect("[ACPI]: Failed to map table")
 }
@@ -1,46 +1,26 @@
+use core::slice;
+use ferrum_parse::acpi::rsdp::{RSDP_V1_SIZE, RSDP_V2_SIZE};
+pub use ferrum_parse::acpi::Rsdp;
+use ferrum_parse::ParseError;
+use log::warn;
+
 static RSDP_REQUEST: limine::request::RsdpRequest = limine::request::RsdpRequest::new();
-#[derive(Debug, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Rsdp {
-    signature: [u8; 8],
-    checksum: u8,
-    oem_id: [u8; 6],
-    revision: u8,
-    rsdt_address: u32,
-    // length: u32,
-    // xsdt_address: u64,
-    // extended_checksum: u8,
-    // reserved: [u8; 3],
-}
-impl Rsdp {
-    pub fn new() -> Self {
-        let rsdp_response = RSDP_REQUEST.get_response().unwrap();
-        let rsdp_address = rsdp_response.address();
-        let signature = unsafe { *(rsdp_address as *const [u8; 8]) };
-        let checksum = unsafe { *((rsdp_address as u64 + 8) as *const u8) };
-        let oem_id = unsafe { *((rsdp_address as u64 + 9) as *const [u8; 6]) };
-        let revision = unsafe { *((rsdp_address as u64 + 15) as *const u8) };
-        let rsdt_address = unsafe { *((rsdp_address as u64 + 16) as *const u32) };
-        Rsdp {
-            signature,
-            checksum,
-            oem_id,
-            revision,
-            rsdt_address,
-        }
-    }
-    pub fn is_valid(&self) -> bool {
-        let mut sum: u32 = 0;
-        let ptr = self as *const Self as *const u8;
-        let size = core::mem::size_of::<Self>();
-        for i in 0..size {
-            let oct = unsafe { *ptr.offset(i as isize) };
-            sum += oct as u32;
-        }
-        sum &= 0xff;
-        sum == 0
-    }
-    pub fn rsdt_address(&self) -> u32 {
-        self.rsdt_address
+
+/// Parse and validate the RSDP provided by the bootloader
+pub fn bootloader_rsdp() -> Result<Rsdp, ParseError> {
+    let rsdp_response = RSDP_REQUEST.get_response().unwrap();
+    let rsdp_address = rsdp_response.address() as *const u8;
+    // Only the revision 0 part is known to exist until the revision is read
+    let v1 = unsafe { slice::from_raw_parts(rsdp_address, RSDP_V1_SIZE) };
+    let size = if v1[15] >= 2 {
+        let length = unsafe { core::ptr::read_unaligned(rsdp_address.add(20) as *const u32) };
+        (length as usize).max(RSDP_V2_SIZE)
+    } else {
+        RSDP_V1_SIZE
+    };
+    let rsdp = Rsdp::parse(unsafe { slice::from_raw_parts(rsdp_address, size) })?;
+    if rsdp.extended_rejected {
+        warn!("Invalid extended RSDP checksum, falling back to the RSDT");
     }
+    Ok(rsdp)
 }
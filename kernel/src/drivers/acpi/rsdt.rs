@@ -1,55 +1,27 @@
-use super::madt::MADT;
-use super::ACPISDTHeader;
-use crate::serial_println;
+use super::map_table;
 use alloc::vec::Vec;
+use ferrum_parse::{acpi::Rsdt, ParseError};
+/// Root System Description Table, holds 32 bit pointers to the other tables
 pub struct RSDT {
-    header: ACPISDTHeader,
-    entries: Vec<u32>,
+    revision: u8,
+    entries: Vec<u64>,
 }
-use core::ptr;
 impl RSDT {
-    pub fn new(base_ptr: u32) -> Self {
-        let header = ACPISDTHeader::new(base_ptr);
-        let mut entries: Vec<u32> = Vec::new();
-        let header_size = core::mem::size_of::<ACPISDTHeader>() as u32;
-        let num_entries = (header.length - header_size) / 4;
-        for i in 0..num_entries {
-            let entry =
-                unsafe { ptr::read_unaligned((base_ptr + header_size + i * 4) as *const u32) };
-            entries.push(entry);
-        }
-        RSDT { header, entries }
+    /// Map and validate the RSDT at the physical address `base_ptr`
+    pub fn new(base_ptr: u32) -> Result<Self, ParseError> {
+        let table = map_table(base_ptr as u64);
+        let rsdt = Rsdt::parse(table.as_bytes())?;
+        Ok(RSDT {
+            revision: rsdt.header.revision,
+            entries: rsdt.entries().collect(),
+        })
     }
-    pub fn is_valid(&self) -> bool {
-        let header_sum = self.header.get_sum();
-        let mut entries_sum: u32 = 0;
-        for entry in self.entries.iter() {
-            let entry = *entry;
-            let temp_sum = (entry as u8) as u32
-                + ((entry >> 8) as u8) as u32
-                + ((entry >> 16) as u8) as u32
-                + ((entry >> 24) as u8) as u32;
-            entries_sum += temp_sum;
-        }
-        (header_sum + entries_sum) & 0xff == 0
+    /// Physical addresses of the tables referenced by the RSDT
+    pub fn entries(&self) -> &[u64] {
+        &self.entries
     }
-    pub fn list_tables(&self) {
-        serial_println!("Listing Tables");
-        let total_tables = (self.header.length - core::mem::size_of::<ACPISDTHeader>() as u32) / 4;
-        for (i, entry) in self.entries.iter().enumerate() {
-            let header = ACPISDTHeader::new(*entry);
-            let signature = unsafe { core::str::from_utf8_unchecked(&header.signature) };
-            serial_println!("#{}/{} {}", i + 1, total_tables, signature);
-        }
-    }
-    pub fn get_madt(&self) -> Option<MADT> {
-        for entry in self.entries.iter() {
-            let header = ACPISDTHeader::new(*entry);
-            let signature = unsafe { core::str::from_utf8_unchecked(&header.signature) };
-            if signature == "APIC" {
-                return Some(MADT::new(*entry));
-            }
-        }
-        None
+    /// Revision of the table
+    pub fn revision(&self) -> u8 {
+        self.revision
     }
 }
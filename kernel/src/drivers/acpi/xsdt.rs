@@ -0,0 +1,27 @@
+use super::map_table;
+use alloc::vec::Vec;
+use ferrum_parse::{acpi::Xsdt, ParseError};
+/// Extended System Description Table, holds 64 bit pointers to the other tables
+pub struct XSDT {
+    revision: u8,
+    entries: Vec<u64>,
+}
+impl XSDT {
+    /// Map and validate the XSDT at the physical address `base_ptr`
+    pub fn new(base_ptr: u64) -> Result<Self, ParseError> {
+        let table = map_table(base_ptr);
+        let xsdt = Xsdt::parse(table.as_bytes())?;
+        Ok(XSDT {
+            revision: xsdt.header.revision,
+            entries: xsdt.entries().collect(),
+        })
+    }
+    /// Physical addresses of the tables referenced by the XSDT
+    pub fn entries(&self) -> &[u64] {
+        &self.entries
+    }
+    /// Revision of the table
+    pub fn revision(&self) -> u8 {
+        self.revision
+    }
+}
@@ -1,9 +1,21 @@
-// use crate::serial_println;
+use super::routing::RedirectionEntry;
+use crate::memory::mmio::{ioremap, MmioRegion};
+use log::{info, warn};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::PhysAddr;
+/// Offset of the register select register
+const IOREGSEL: u64 = 0x00;
+/// Offset of the register window register
+const IOWIN: u64 = 0x10;
 #[allow(dead_code)]
 pub struct IOAPICStruct {
     io_apic_id: u8,
     io_apic_address: u32,
     global_system_interrupt_base: u32,
+    registers: MmioRegion,
+    /// Held across each IOREGSEL and IOWIN access pair
+    register_lock: Mutex<()>,
 }
 #[allow(dead_code)]
 enum IOAPICReg {
@@ -15,27 +27,43 @@ enum IOAPICReg {
 #[allow(dead_code)]
 impl IOAPICStruct {
     pub fn new(io_apic_id: u8, io_apic_address: u32, global_system_interrupt_base: u32) -> Self {
+        let registers = ioremap(PhysAddr::new(io_apic_address as u64), 0x20)
+            .expect("[IOAPIC]: Failed to map registers");
         IOAPICStruct {
             io_apic_id,
             io_apic_address,
             global_system_interrupt_base,
+            registers,
+            register_lock: Mutex::new(()),
         }
     }
     fn read_register(&self, register: u32) -> u32 {
-        unsafe {
-            let io_reg_sel = self.io_apic_address as *mut u32;
-            let io_reg_win = (self.io_apic_address + 0x10) as *mut u32;
-            *io_reg_sel = register;
-            *io_reg_win
-        }
+        without_interrupts(|| {
+            let _guard = self.register_lock.lock();
+            self.registers.write::<u32>(IOREGSEL, register);
+            self.registers.read::<u32>(IOWIN)
+        })
     }
     fn write_register(&self, register: u32, value: u32) {
-        unsafe {
-            let io_reg_sel = self.io_apic_address as *mut u32;
-            let io_reg_win = (self.io_apic_address + 0x10) as *mut u32;
-            *io_reg_sel = register;
-            *io_reg_win = value;
-        }
+        without_interrupts(|| {
+            let _guard = self.register_lock.lock();
+            self.registers.write::<u32>(IOREGSEL, register);
+            self.registers.write::<u32>(IOWIN, value);
+        })
+    }
+    /// First global system interrupt handled by this IOAPIC
+    pub fn gsi_base(&self) -> u32 {
+        self.global_system_interrupt_base
+    }
+    /// Number of redirection entries (input pins) of this IOAPIC
+    pub fn redirection_entries(&self) -> u32 {
+        // Bits 16 to 23 of the version register hold the index of the last entry
+        ((self.read_register(IOAPICReg::IOAPICVER as u32) >> 16) & 0xFF) + 1
+    }
+    /// Returns true if the global system interrupt is handled by this IOAPIC
+    pub fn handles_gsi(&self, gsi: u32) -> bool {
+        gsi >= self.global_system_interrupt_base
+            && gsi - self.global_system_interrupt_base < self.redirection_entries()
     }
     fn set_mask(&self, interrupt: u8, mask: bool) {
         let reg = (IOAPICReg::IOAPICREDTBL as u32 + (interrupt as u32) * 2) as u32;
@@ -51,28 +79,76 @@ impl IOAPICStruct {
             }
         }
     }
-    /// Set the interrupt vector for the given interrupt
-    fn set_red_tbl_vec(&self, interrupt: u8, vector: u8) {
-        let entry = IOAPICReg::IOAPICREDTBL as u32 + (interrupt as u32) * 2;
-        self.write_register(entry, vector as u32);
+    /// Program the full redirection entry of the given interrupt
+    fn set_redirection(&self, interrupt: u8, entry: RedirectionEntry) {
+        let reg = IOAPICReg::IOAPICREDTBL as u32 + (interrupt as u32) * 2;
+        let value = entry.as_u64();
+        // Mask the entry while it is half written so no interrupt is sent to a stale destination
+        self.write_register(reg, (value as u32) | 0x10000);
+        self.write_register(reg + 1, (value >> 32) as u32);
+        self.write_register(reg, value as u32);
     }
 }
+use alloc::vec::Vec;
 use lazy_static::lazy_static;
 lazy_static! {
-    static ref IO_APIC: IOAPICStruct = {
-        let ioapic = {
-            use crate::drivers::acpi::{rsdp::Rsdp, rsdt::RSDT};
-            let rsdp = Rsdp::new();
-            let rsdt_header = RSDT::new(rsdp.rsdt_address());
-            let madt = rsdt_header.get_madt().expect("No MADT found");
-            madt.get_ioapic().expect("No IOAPIC found")
-        };
-
-        ioapic
+    /// Every IOAPIC of the system, ordered by their first global system interrupt
+    static ref IO_APICS: Vec<IOAPICStruct> = {
+        use crate::drivers::acpi::madt::MADT_TABLE;
+        let mut io_apics: Vec<IOAPICStruct> = MADT_TABLE
+            .io_apics()
+            .iter()
+            .map(|info| IOAPICStruct::new(info.id, info.address, info.gsi_base))
+            .collect();
+        if io_apics.is_empty() {
+            panic!("No IOAPIC found");
+        }
+        io_apics.sort_by_key(|io_apic| io_apic.gsi_base());
+        io_apics
     };
 }
+/// Find the IOAPIC handling `gsi` and the pin the interrupt arrives on
+fn io_apic_for_gsi(gsi: u32) -> Option<(&'static IOAPICStruct, u8)> {
+    IO_APICS
+        .iter()
+        .find(|io_apic| io_apic.handles_gsi(gsi))
+        .map(|io_apic| (io_apic, (gsi - io_apic.gsi_base()) as u8))
+}
+/// Program the redirection entry of the given global system interrupt
+pub fn set_gsi_redirection(gsi: u32, entry: RedirectionEntry) {
+    match io_apic_for_gsi(gsi) {
+        Some((io_apic, pin)) => io_apic.set_redirection(pin, entry),
+        None => {
+            warn!("No IOAPIC handles GSI {}", gsi);
+        }
+    }
+}
+/// Mask or unmask the given global system interrupt
+pub fn set_gsi_mask(gsi: u32, mask: bool) {
+    match io_apic_for_gsi(gsi) {
+        Some((io_apic, pin)) => io_apic.set_mask(pin, mask),
+        None => {
+            warn!("No IOAPIC handles GSI {}", gsi);
+        }
+    }
+}
+/// Total number of global system interrupts handled by all IOAPICs
+pub fn gsi_count() -> u32 {
+    IO_APICS
+        .iter()
+        .map(|io_apic| io_apic.gsi_base() + io_apic.redirection_entries())
+        .max()
+        .unwrap_or(0)
+}
 pub fn init() {
+    use super::{
+        local_apic::LOCAL_APIC,
+        routing::{apply_irq_masks, route_irq},
+    };
     use crate::interrupts::InterruptIndexAPIC;
-    IO_APIC.set_red_tbl_vec(2, InterruptIndexAPIC::Timer as u8);
-    IO_APIC.set_red_tbl_vec(1, InterruptIndexAPIC::Keyboard as u8);
+    let bsp = LOCAL_APIC.apic_id() as u8;
+    route_irq(0, InterruptIndexAPIC::Timer as u8, bsp);
+    route_irq(1, InterruptIndexAPIC::Keyboard as u8, bsp);
+    apply_irq_masks();
+    info!("{} IOAPIC(s) handling {} GSIs", IO_APICS.len(), gsi_count());
 }
@@ -1,13 +1,16 @@
-use crate::println;
+use crate::memory::mmio::{ioremap, MmioRegion};
 use crate::serial_println;
 use crate::utils::msr::*;
 use crate::utils::registers::*;
+use log::info;
+use x86_64::PhysAddr;
 static IA32_APIC_BASE_MSR: u32 = 0x1b;
 #[allow(dead_code)]
 pub enum LAPICReg {
     ID = 0x20,
     Version = 0x30,
     EOI = 0xb0,
+    InterruptCommandLow = 0x300,
     TimerLVT = 0x320,
     TimerICnt = 0x380, // initial count
     TimerCCnt = 0x390, // current count
@@ -21,6 +24,7 @@ pub struct LocalAPIC {
     bsc: bool,         // Boot strap processor
     is_enabled: bool,  // APIC Enabled
     base_address: u64, // APIC Base Address
+    registers: MmioRegion,
 }
 #[allow(dead_code)]
 impl LocalAPIC {
@@ -28,25 +32,22 @@ impl LocalAPIC {
         let register = read_msr(IA32_APIC_BASE_MSR);
         let bsc = (register >> 8) & 1 == 1;
         let is_enabled = (register >> 11) & 1 == 1;
-        let base = register >> 12;
+        // Bits 12 to 51 hold the physical base address
+        let base_address = register & 0x000F_FFFF_FFFF_F000;
+        let registers =
+            ioremap(PhysAddr::new(base_address), 0x1000).expect("[LAPIC]: Failed to map registers");
         LocalAPIC {
             bsc,
             is_enabled,
-            base_address: base << 12,
+            base_address,
+            registers,
         }
     }
     pub fn read_register(&self, register: LAPICReg) -> u32 {
-        let offset = register as u64;
-        unsafe {
-            return *((self.base_address + offset) as *const u32);
-        }
+        self.registers.read::<u32>(register as u64)
     }
     pub fn write_register(&self, register: LAPICReg, value: u32) {
-        let offset = register as u64;
-        unsafe {
-            let ptr = (self.base_address + offset) as *mut u32;
-            *ptr = value;
-        }
+        self.registers.write::<u32>(register as u64, value)
     }
     // TO DO consider removing those in favor of read_register and write_register
     fn get_version(&self) -> u32 {
@@ -55,9 +56,18 @@ impl LocalAPIC {
     fn get_id(&self) -> u32 {
         self.read_register(LAPICReg::ID)
     }
+    /// APIC ID of the current processor, stored in bits 24 to 31 of the ID register
+    pub fn apic_id(&self) -> u32 {
+        self.get_id() >> 24
+    }
     pub fn set_eoi(&self) {
         self.write_register(LAPICReg::EOI, 0);
     }
+    /// Raise `vector` on the current processor with a fixed, edge triggered IPI
+    pub fn send_self_ipi(&self, vector: u8) {
+        // Destination shorthand 01 (self) in bits 18 and 19
+        self.write_register(LAPICReg::InterruptCommandLow, 1 << 18 | vector as u32);
+    }
     fn set_spurious_interrupt_vector(&self, value: u32) {
         self.write_register(LAPICReg::SpuriousInterruptVector, value)
     }
@@ -87,10 +97,14 @@ lazy_static! {
     };
 }
 
+/// Whether the running processor is the bootstrap processor, read from its APIC base MSR
+pub fn is_bsp() -> bool {
+    (read_msr(IA32_APIC_BASE_MSR) >> 8) & 1 == 1
+}
 pub fn init() {
     // 0x100 -> Enable LAPIC
     // 0xFF  -> Set the vector
     let spourious_interrupt_vector = LOCAL_APIC.get_spurious_interrupt_vector() | 0x1FF;
     LOCAL_APIC.set_spurious_interrupt_vector(spourious_interrupt_vector);
-    println!("LAPIC[{}] Enabled", LOCAL_APIC.get_id());
+    info!("LAPIC[{}] Enabled", LOCAL_APIC.get_id());
 }
@@ -1,2 +1,3 @@
 pub mod io_apic;
 pub mod local_apic;
+pub mod routing;
@@ -0,0 +1,101 @@
+//! Legacy IRQ routing through the IOAPICs
+//!
+//! ISA IRQs are translated to global system interrupts using the interrupt
+//! source overrides of the MADT, which also decide the polarity and trigger
+//! mode of the line. Everything else uses the ISA defaults (edge triggered,
+//! active high, identity mapped).
+use super::io_apic;
+use crate::boot_params::BootParam;
+use crate::drivers::acpi::madt::MADT_TABLE;
+pub use ferrum_parse::acpi::madt::{IsaIrqRoute, Polarity, TriggerMode};
+use ferrum_parse::cmdline::List;
+use log::warn;
+
+/// ISA IRQs left masked after routing, `irq.mask=3,4` on the command line
+pub static MASKED_IRQS: BootParam<List> =
+    BootParam::new("irq.mask", "ISA IRQs to keep masked", List::new(""));
+
+/// How the interrupt is delivered to the destination CPU
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryMode {
+    Fixed = 0b000,
+    LowestPriority = 0b001,
+    Smi = 0b010,
+    Nmi = 0b100,
+    Init = 0b101,
+    ExtInt = 0b111,
+}
+
+/// A full 64 bit IOAPIC redirection table entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RedirectionEntry {
+    pub vector: u8,
+    pub delivery_mode: DeliveryMode,
+    pub logical_destination: bool,
+    pub polarity: Polarity,
+    pub trigger_mode: TriggerMode,
+    pub masked: bool,
+    pub destination: u8, // APIC ID, or a set of processors in logical mode
+}
+impl RedirectionEntry {
+    /// Create an unmasked, fixed delivery entry sending `vector` to the CPU with the given APIC ID
+    pub fn new(vector: u8, destination: u8) -> Self {
+        RedirectionEntry {
+            vector,
+            delivery_mode: DeliveryMode::Fixed,
+            logical_destination: false,
+            polarity: Polarity::ActiveHigh,
+            trigger_mode: TriggerMode::Edge,
+            masked: false,
+            destination,
+        }
+    }
+    /// Encode the entry as written to the redirection table
+    pub fn as_u64(&self) -> u64 {
+        (self.vector as u64)
+            | ((self.delivery_mode as u64) << 8)
+            | ((self.logical_destination as u64) << 11)
+            | (((self.polarity == Polarity::ActiveLow) as u64) << 13)
+            | (((self.trigger_mode == TriggerMode::Level) as u64) << 15)
+            | ((self.masked as u64) << 16)
+            | ((self.destination as u64) << 56)
+    }
+}
+
+/// Resolve an ISA IRQ using the interrupt source overrides of the MADT
+pub fn resolve_isa_irq(isa_irq: u8) -> IsaIrqRoute {
+    IsaIrqRoute::resolve(MADT_TABLE.entries(), isa_irq)
+}
+/// Route the ISA IRQ `isa_irq` to `vector` on the CPU with the APIC ID `cpu`
+///
+/// Returns the global system interrupt the IRQ was routed through.
+pub fn route_irq(isa_irq: u8, vector: u8, cpu: u8) -> u32 {
+    let route = resolve_isa_irq(isa_irq);
+    let entry = RedirectionEntry {
+        polarity: route.polarity,
+        trigger_mode: route.trigger_mode,
+        ..RedirectionEntry::new(vector, cpu)
+    };
+    io_apic::set_gsi_redirection(route.gsi, entry);
+    route.gsi
+}
+/// Route the global system interrupt `gsi` with an explicit redirection entry
+///
+/// Used for devices that are not ISA IRQs, like PCI interrupts.
+pub fn route_gsi(gsi: u32, entry: RedirectionEntry) {
+    io_apic::set_gsi_redirection(gsi, entry);
+}
+/// Mask the ISA IRQs listed with `irq.mask` on the command line
+pub fn apply_irq_masks() {
+    for irq in MASKED_IRQS.get().parse::<u8>() {
+        match irq {
+            Ok(irq) if irq < 16 => mask_irq(irq, true),
+            _ => warn!("Ignoring invalid ISA IRQ in irq.mask"),
+        }
+    }
+}
+/// Mask or unmask the ISA IRQ `isa_irq`
+pub fn mask_irq(isa_irq: u8, mask: bool) {
+    io_apic::set_gsi_mask(resolve_isa_irq(isa_irq).gsi, mask);
+}
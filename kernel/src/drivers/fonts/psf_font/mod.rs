@@ -1,58 +1,36 @@
 //! This module contains the implementation of the PsfFont struct,
 //! which is used to represent a font in the PSF format.
 use super::super::framebuffer::FrameBuffer;
-use glyph::Glyphs;
-mod glyph;
+use alloc::collections::BTreeMap;
+use ferrum_parse::psf;
 /// PsfFont struct containing the font data
 #[derive(Debug)]
-#[repr(C)]
 pub struct PsfFont {
-    magic: u32,
-    version: u32,
-    headersize: u32,
-    flags: u32,
-    numglyph: u32,
-    bytesperglyph: u32,
-    height: u32,
-    width: u32,
-    glyphs: Glyphs,
+    font: psf::PsfFont<'static>,
+    // Unicode code point to glyph index, empty if the font has no unicode table
+    unicode: BTreeMap<u32, u32>,
 }
 
 impl PsfFont {
     /// Creates a new PsfFont from the given data
     ///
-    /// Note: More general implementation needed
-    pub fn from(data: &[u8]) -> Self {
-        let magic = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
-        let version = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
-        let headersize = u32::from_le_bytes([data[8], data[9], data[10], data[11]]);
-        let flags = u32::from_le_bytes([data[12], data[13], data[14], data[15]]);
-        let numglyph = u32::from_le_bytes([data[16], data[17], data[18], data[19]]);
-        let bytesperglyph = u32::from_le_bytes([data[20], data[21], data[22], data[23]]);
-        let height = u32::from_le_bytes([data[24], data[25], data[26], data[27]]);
-        let width = u32::from_le_bytes([data[28], data[29], data[30], data[31]]);
-        let glyphs = Glyphs::new(&data[32..], numglyph, bytesperglyph);
-        let result = PsfFont {
-            magic,
-            version,
-            headersize,
-            flags,
-            numglyph,
-            bytesperglyph,
-            height,
-            width,
-            glyphs,
-        };
-
-        result
+    /// Panics if the data is not a valid PSF2 font.
+    pub fn from(data: &'static [u8]) -> Self {
+        let font = psf::PsfFont::parse(data).expect("[FONT]: Invalid PSF2 font");
+        // The first glyph listed for a code point wins
+        let mut unicode = BTreeMap::new();
+        for (code_point, glyph) in font.unicode_table() {
+            unicode.entry(code_point).or_insert(glyph);
+        }
+        PsfFont { font, unicode }
     }
     /// Gets the height of the font
     pub fn get_height(&self) -> u32 {
-        self.height
+        self.font.height
     }
     /// Gets the width of the font
     pub fn get_width(&self) -> u32 {
-        self.width
+        self.font.width
     }
     /// Displays a character on the framebuffer at the given position with the given colors
     pub fn display_char(
@@ -64,10 +42,12 @@ impl PsfFont {
         bg_color: u32,
         font_size_multiplier: u64,
     ) {
-        let glyph = self.find_glyph(character as u32).unwrap_or([0; 16]);
-        for row in 0..16_u64 {
-            for col in 0..8_u64 {
-                let bit = glyph[row as usize] & (1 << (7 - col));
+        let glyph = self.find_glyph(character as u32);
+        let row_size = self.font.row_size();
+        for row in 0..self.font.height as u64 {
+            for col in 0..self.font.width as u64 {
+                let byte = row as usize * row_size + col as usize / 8;
+                let bit = glyph.map_or(0, |glyph| glyph[byte] & (0x80 >> (col % 8)));
                 let pixel_coord = (
                     position.0 + col * font_size_multiplier,
                     position.1 + row * font_size_multiplier,
@@ -82,21 +62,13 @@ impl PsfFont {
             }
         }
     }
-    /// Gets the glyph data for the given index
-    fn get_glyph(&self, index: u32) -> [u8; 16] {
-        let mut glyph = [0; 16];
-        for i in 0..16 {
-            glyph[i] = self.glyphs.get_glyph(index as usize)[i];
-        }
-        glyph
-    }
     /// Finds the glyph for the given unicode
-    fn find_glyph(&self, unicode: u32) -> Option<[u8; 16]> {
-        for i in 0..self.numglyph {
-            if self.glyphs.get_unicode(i as usize) == unicode {
-                return Some(self.get_glyph(i));
-            }
-        }
-        None
+    fn find_glyph(&self, unicode: u32) -> Option<&'static [u8]> {
+        let index = if self.font.has_unicode_table() {
+            *self.unicode.get(&unicode)?
+        } else {
+            unicode
+        };
+        self.font.glyph(index)
     }
 }
@@ -83,6 +83,10 @@ impl FrameBuffer {
             }
         }
     }
+    /// Gets the start address of the framebuffer.
+    pub fn get_address(&self) -> *mut u8 {
+        self.buffer.add(0)
+    }
     /// Gets the width of the framebuffer.
     pub const fn get_width(&self) -> u64 {
         self.width
@@ -2,27 +2,37 @@
 //!
 //! The GDT is responsible for defining memory segments and their access permissions.
 //! It also includes the Task State Segment (TSS) which holds information about task switching.
+use crate::memory::vmm::{self, RegionKind, VmmError};
+use alloc::boxed::Box;
 use lazy_static::lazy_static;
 use x86_64::{
     structures::{
         gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector},
+        paging::{PageSize, PageTableFlags, Size4KiB},
         tss::TaskStateSegment,
     },
     VirtAddr,
 };
 /// Initialize the GDT with the code and TSS segments
 pub fn init() {
+    load(&GDT.0, &GDT.1);
+}
+/// Load the tables built by `new_ap_tables` on the running application processor
+pub fn init_ap(tables: &'static CpuTables) {
+    load(&tables.gdt, &tables.selectors);
+}
+fn load(gdt: &'static GlobalDescriptorTable, selectors: &Selectors) {
     use x86_64::instructions::segmentation::{Segment, CS, DS, SS};
     use x86_64::instructions::tables::load_tss;
     // Initialize the GDT
-    GDT.0.load();
+    gdt.load();
     unsafe {
         // Reload the code segment register
-        CS::set_reg(GDT.1.code_selector);
-        DS::set_reg(GDT.1.data_selector);
-        SS::set_reg(GDT.1.data_selector);
+        CS::set_reg(selectors.code_selector);
+        DS::set_reg(selectors.data_selector);
+        SS::set_reg(selectors.data_selector);
         // Load the Task State Segment (TSS)
-        load_tss(GDT.1.tss_selector);
+        load_tss(selectors.tss_selector);
     }
 }
 /// The index of the double fault stack in the Interrupt Stack Table (IST)
@@ -34,27 +44,24 @@ pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
 lazy_static! {
     /// The Global Descriptor Table (GDT) with the Task State Segment (TSS)
     /// and the selectors for code and TSS segments
-    static ref GDT: (GlobalDescriptorTable, Selectors) = {
-        // Create a new GDT with the code and TSS segments
-        let mut gdt = GlobalDescriptorTable::new();
-        // Add the code and TSS segments to the GDT
-        // The code segment is used for executing code
-        // The TSS segment is used for task switching
-        let code_selector = gdt.append(Descriptor::kernel_code_segment());
-        let data_selector = gdt.append(Descriptor::kernel_data_segment());
-        let tss_selector = gdt.append(Descriptor::tss_segment(&TSS));
-        // TO DO: Remove the following line after checking the code is working
-        // let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
-        // let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
-        (
-            gdt,
-            Selectors {
-                code_selector,
-                data_selector,
-                tss_selector,
-            },
-        )
-    };
+    static ref GDT: (GlobalDescriptorTable, Selectors) = build(&TSS);
+}
+/// Create a GDT with the code, data and TSS segments
+fn build(tss: &'static TaskStateSegment) -> (GlobalDescriptorTable, Selectors) {
+    let mut gdt = GlobalDescriptorTable::new();
+    // The code segment is used for executing code
+    // The TSS segment is used for task switching
+    let code_selector = gdt.append(Descriptor::kernel_code_segment());
+    let data_selector = gdt.append(Descriptor::kernel_data_segment());
+    let tss_selector = gdt.append(Descriptor::tss_segment(tss));
+    (
+        gdt,
+        Selectors {
+            code_selector,
+            data_selector,
+            tss_selector,
+        },
+    )
 }
 /// Struct used to store the selectors for the code and TSS segments
 /// in the GDT
@@ -63,6 +70,32 @@ struct Selectors {
     data_selector: SegmentSelector,
     tss_selector: SegmentSelector,
 }
+/// GDT and TSS of an application processor, built by the BSP before starting it
+pub struct CpuTables {
+    gdt: GlobalDescriptorTable,
+    selectors: Selectors,
+}
+/// Size of the double fault stack of an application processor
+const AP_STACK_SIZE: u64 = 4096 * 5;
+/// Build the GDT and TSS of an application processor, they are never freed
+///
+/// Unlike the static stack of the BSP, the double fault stack comes from the
+/// VMM with a guard page below it.
+pub fn new_ap_tables() -> Result<&'static CpuTables, VmmError> {
+    let stack = vmm::with_kernel_space(|space| {
+        space.allocate(
+            "double fault stack",
+            RegionKind::Stack,
+            AP_STACK_SIZE,
+            Size4KiB::SIZE,
+            PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+        )
+    })?;
+    let mut tss = TaskStateSegment::new();
+    tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = stack + AP_STACK_SIZE;
+    let (gdt, selectors) = build(Box::leak(Box::new(tss)));
+    Ok(Box::leak(Box::new(CpuTables { gdt, selectors })))
+}
 // TO DO : Stack overflow error
 const fn stack_initializer() -> VirtAddr {
     // Set the stack size to 5 pages (5 * 4096 bytes)
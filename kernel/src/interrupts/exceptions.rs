@@ -0,0 +1,465 @@
+//! CPU exception entry and fault policy
+//!
+//! Every exception enters through an assembly stub that saves all the general
+//! purpose registers and calls `exception_dispatch` with the full frame. The
+//! dispatcher then decides what to do with the fault: resume at a registered
+//! fixup, resume in place, kill the running task or panic.
+use crate::utils::backtrace::{self, BacktraceDisplay};
+use crate::{println, serial_println};
+use alloc::vec::Vec;
+use core::arch::global_asm;
+use core::fmt;
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use lazy_static::lazy_static;
+use spin::RwLock;
+use x86_64::registers::control::{Cr0, Cr2, Cr3, Cr4};
+use x86_64::structures::idt::{InterruptDescriptorTable, PageFaultErrorCode, SelectorErrorCode};
+use x86_64::VirtAddr;
+
+/// The CPU exceptions handled by the kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Exception {
+    DivideError = 0,
+    Debug = 1,
+    Breakpoint = 3,
+    Overflow = 4,
+    BoundRangeExceeded = 5,
+    InvalidOpcode = 6,
+    DeviceNotAvailable = 7,
+    DoubleFault = 8,
+    InvalidTss = 10,
+    SegmentNotPresent = 11,
+    StackSegmentFault = 12,
+    GeneralProtectionFault = 13,
+    PageFault = 14,
+    X87FloatingPoint = 16,
+    AlignmentCheck = 17,
+    SimdFloatingPoint = 19,
+}
+impl Exception {
+    const ALL: [Exception; 16] = [
+        Exception::DivideError,
+        Exception::Debug,
+        Exception::Breakpoint,
+        Exception::Overflow,
+        Exception::BoundRangeExceeded,
+        Exception::InvalidOpcode,
+        Exception::DeviceNotAvailable,
+        Exception::DoubleFault,
+        Exception::InvalidTss,
+        Exception::SegmentNotPresent,
+        Exception::StackSegmentFault,
+        Exception::GeneralProtectionFault,
+        Exception::PageFault,
+        Exception::X87FloatingPoint,
+        Exception::AlignmentCheck,
+        Exception::SimdFloatingPoint,
+    ];
+    pub fn from_vector(vector: u8) -> Option<Exception> {
+        Exception::ALL
+            .into_iter()
+            .find(|exception| *exception as u8 == vector)
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Exception::DivideError => "DIVISION ERROR",
+            Exception::Debug => "DEBUG",
+            Exception::Breakpoint => "BREAKPOINT",
+            Exception::Overflow => "OVERFLOW",
+            Exception::BoundRangeExceeded => "BOUND RANGE EXCEEDED",
+            Exception::InvalidOpcode => "INVALID OPCODE",
+            Exception::DeviceNotAvailable => "DEVICE NOT AVAILABLE",
+            Exception::DoubleFault => "DOUBLE FAULT",
+            Exception::InvalidTss => "INVALID TSS",
+            Exception::SegmentNotPresent => "SEGMENT NOT PRESENT",
+            Exception::StackSegmentFault => "STACK SEGMENT FAULT",
+            Exception::GeneralProtectionFault => "GENERAL PROTECTION FAULT",
+            Exception::PageFault => "PAGE FAULT",
+            Exception::X87FloatingPoint => "x87 FLOATING POINT",
+            Exception::AlignmentCheck => "ALIGNMENT CHECK",
+            Exception::SimdFloatingPoint => "SIMD FLOATING POINT",
+        }
+    }
+    /// Returns true if the saved instruction pointer is already past the instruction
+    pub fn is_trap(&self) -> bool {
+        matches!(
+            self,
+            Exception::Debug | Exception::Breakpoint | Exception::Overflow
+        )
+    }
+    /// Policy used until `set_fault_policy` changes it
+    fn default_action(&self) -> FaultAction {
+        match self {
+            Exception::Debug | Exception::Breakpoint | Exception::Overflow => FaultAction::Resume,
+            // Killing a task holding a lock would deadlock the next user of the lock
+            _ => FaultAction::Panic,
+        }
+    }
+}
+
+/// What to do when an exception is not covered by a fixup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FaultAction {
+    /// Print the fault report and panic
+    Panic,
+    /// Print the fault report and end the running task, panics if there is no task to kill
+    KillTask,
+    /// Return to the interrupted code, for faults this executes the instruction again
+    Resume,
+}
+impl FaultAction {
+    fn from_u8(value: u8) -> FaultAction {
+        match value {
+            0 => FaultAction::Panic,
+            1 => FaultAction::KillTask,
+            _ => FaultAction::Resume,
+        }
+    }
+}
+
+/// General purpose registers saved by the entry stubs, in stack order
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct SavedRegisters {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+}
+/// Everything on the stack when an entry stub calls `exception_dispatch`
+///
+/// Changes made to the frame are restored when the handler returns.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct ExceptionFrame {
+    pub registers: SavedRegisters,
+    pub vector: u64,
+    pub error_code: u64, // 0 for exceptions without an error code
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+/// An instruction range whose faults resume at `fixup` instead of going through the policy
+#[derive(Debug, Clone, Copy)]
+struct Fixup {
+    start: u64,
+    end: u64,
+    fixup: u64,
+}
+
+lazy_static! {
+    /// Registered fixups, looked up by faulting instruction pointer
+    static ref FIXUPS: RwLock<Vec<Fixup>> = RwLock::new(Vec::new());
+}
+/// Policy of every exception vector, stored as `FaultAction`
+static POLICIES: [AtomicU8; 32] = [const { AtomicU8::new(u8::MAX) }; 32];
+/// Function the interrupted code is redirected to when a task is killed, 0 if none
+static KILL_TASK_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+/// Change what happens when `exception` is raised outside of a fixup
+///
+/// Double faults always panic, the policy of `Exception::DoubleFault` is ignored.
+/// `FaultAction::KillTask` never releases the locks held by the killed task:
+/// only choose it when the faulting code is known to hold none.
+pub fn set_fault_policy(exception: Exception, action: FaultAction) {
+    POLICIES[exception as usize].store(action as u8, Ordering::Relaxed);
+}
+/// Current policy of `exception`
+pub fn fault_policy(exception: Exception) -> FaultAction {
+    match POLICIES[exception as usize].load(Ordering::Relaxed) {
+        u8::MAX => exception.default_action(),
+        value => FaultAction::from_u8(value),
+    }
+}
+/// Resume at `fixup` when an instruction in `[start, end)` faults
+pub fn register_fixup(start: VirtAddr, end: VirtAddr, fixup: VirtAddr) {
+    let entry = Fixup {
+        start: start.as_u64(),
+        end: end.as_u64(),
+        fixup: fixup.as_u64(),
+    };
+    // The fixups are also read from exception context
+    x86_64::instructions::interrupts::without_interrupts(|| FIXUPS.write().push(entry));
+}
+/// Set the function a faulting task is redirected to by `FaultAction::KillTask`
+///
+/// The function runs on the stack of the killed task and must never return to it.
+pub fn set_kill_task_handler(handler: fn() -> !) {
+    KILL_TASK_HANDLER.store(handler as usize, Ordering::Relaxed);
+}
+/// Find the fixup covering `rip`
+fn find_fixup(rip: u64) -> Option<u64> {
+    // A fault while the fixups are being modified cannot wait for the lock
+    let fixups = FIXUPS.try_read()?;
+    fixups
+        .iter()
+        .find(|fixup| (fixup.start..fixup.end).contains(&rip))
+        .map(|fixup| fixup.fixup)
+}
+
+/// Called by the entry stubs with the saved state of the interrupted code
+extern "sysv64" fn exception_dispatch(frame: &mut ExceptionFrame) {
+    let Some(exception) = Exception::from_vector(frame.vector as u8) else {
+        panic!("EXCEPTION: unexpected vector {}", frame.vector);
+    };
+    if exception != Exception::DoubleFault {
+        if let Some(fixup) = find_fixup(frame.rip) {
+            frame.rip = fixup;
+            return;
+        }
+    }
+    let report = FaultReport { exception, frame };
+    match fault_policy(exception) {
+        FaultAction::Resume if exception != Exception::DoubleFault => {
+            println!("EXCEPTION: {} at {:#x}", exception.name(), frame.rip);
+            serial_println!("EXCEPTION: {} at {:#x}", exception.name(), frame.rip);
+        }
+        FaultAction::KillTask if exception != Exception::DoubleFault => {
+            println!("{}", report);
+            serial_println!("{}", report);
+            match KILL_TASK_HANDLER.load(Ordering::Relaxed) {
+                0 => panic!("EXCEPTION: {} with no task to kill", exception.name()),
+                handler => {
+                    println!("Killing the running task");
+                    serial_println!("Killing the running task");
+                    frame.rip = handler as u64;
+                    // Enter the handler as if it was called, with an aligned stack
+                    frame.rsp = (frame.rsp & !0xF) - 8;
+                }
+            }
+        }
+        _ => {
+            println!("{}", report);
+            serial_println!("{}", report);
+            panic!("EXCEPTION: {}", exception.name());
+        }
+    }
+}
+
+/// Human readable description of a fault
+struct FaultReport<'a> {
+    exception: Exception,
+    frame: &'a ExceptionFrame,
+}
+impl fmt::Display for FaultReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frame = self.frame;
+        let regs = &frame.registers;
+        writeln!(
+            f,
+            "EXCEPTION: {} (vector {})",
+            self.exception.name(),
+            frame.vector
+        )?;
+        match self.exception {
+            Exception::PageFault => {
+                writeln!(f, "Accessed Address: {:#x}", Cr2::read_raw())?;
+                writeln!(
+                    f,
+                    "Error Code: {:?}",
+                    PageFaultErrorCode::from_bits_truncate(frame.error_code)
+                )?;
+            }
+            Exception::InvalidTss
+            | Exception::SegmentNotPresent
+            | Exception::StackSegmentFault
+            | Exception::GeneralProtectionFault => {
+                writeln!(
+                    f,
+                    "Error Code: {:?}",
+                    SelectorErrorCode::new_truncate(frame.error_code)
+                )?;
+            }
+            Exception::DoubleFault | Exception::AlignmentCheck => {
+                writeln!(f, "Error Code: {:#x}", frame.error_code)?;
+            }
+            _ => {}
+        }
+        if let Some(symbol) = backtrace::resolve(frame.rip) {
+            writeln!(f, "Symbol: {}", symbol)?;
+        }
+        writeln!(
+            f,
+            "RIP: {:#018x} CS: {:#06x} RFLAGS: {:#018x}",
+            frame.rip, frame.cs, frame.rflags
+        )?;
+        writeln!(f, "RSP: {:#018x} SS: {:#06x}", frame.rsp, frame.ss)?;
+        writeln!(
+            f,
+            "RAX: {:#018x} RBX: {:#018x} RCX: {:#018x}",
+            regs.rax, regs.rbx, regs.rcx
+        )?;
+        writeln!(
+            f,
+            "RDX: {:#018x} RSI: {:#018x} RDI: {:#018x}",
+            regs.rdx, regs.rsi, regs.rdi
+        )?;
+        writeln!(
+            f,
+            "RBP: {:#018x} R8:  {:#018x} R9:  {:#018x}",
+            regs.rbp, regs.r8, regs.r9
+        )?;
+        writeln!(
+            f,
+            "R10: {:#018x} R11: {:#018x} R12: {:#018x}",
+            regs.r10, regs.r11, regs.r12
+        )?;
+        writeln!(
+            f,
+            "R13: {:#018x} R14: {:#018x} R15: {:#018x}",
+            regs.r13, regs.r14, regs.r15
+        )?;
+        write!(
+            f,
+            "CR0: {:#018x} CR2: {:#018x} CR3: {:#018x} CR4: {:#018x}",
+            Cr0::read_raw(),
+            Cr2::read_raw(),
+            Cr3::read_raw().0.start_address().as_u64(),
+            Cr4::read_raw()
+        )?;
+        let backtrace = BacktraceDisplay {
+            rip: Some(frame.rip),
+            rbp: regs.rbp,
+        };
+        write!(f, "\n{}", backtrace)
+    }
+}
+
+// Entry stubs, exceptions without an error code push a 0 so all frames look the same
+global_asm!(
+    ".macro EXCEPTION_STUB vector",
+    ".global exception_stub_\\vector",
+    "exception_stub_\\vector:",
+    "    push 0",
+    "    push \\vector",
+    "    jmp exception_common",
+    ".endm",
+    ".macro EXCEPTION_STUB_ERROR_CODE vector",
+    ".global exception_stub_\\vector",
+    "exception_stub_\\vector:",
+    "    push \\vector",
+    "    jmp exception_common",
+    ".endm",
+    "EXCEPTION_STUB 0",
+    "EXCEPTION_STUB 1",
+    "EXCEPTION_STUB 3",
+    "EXCEPTION_STUB 4",
+    "EXCEPTION_STUB 5",
+    "EXCEPTION_STUB 6",
+    "EXCEPTION_STUB 7",
+    "EXCEPTION_STUB_ERROR_CODE 8",
+    "EXCEPTION_STUB_ERROR_CODE 10",
+    "EXCEPTION_STUB_ERROR_CODE 11",
+    "EXCEPTION_STUB_ERROR_CODE 12",
+    "EXCEPTION_STUB_ERROR_CODE 13",
+    "EXCEPTION_STUB_ERROR_CODE 14",
+    "EXCEPTION_STUB 16",
+    "EXCEPTION_STUB_ERROR_CODE 17",
+    "EXCEPTION_STUB 19",
+    // The CPU aligned the stack before pushing its frame, the 22 pushed words keep it aligned for the call
+    "exception_common:",
+    "    push rax",
+    "    push rbx",
+    "    push rcx",
+    "    push rdx",
+    "    push rsi",
+    "    push rdi",
+    "    push rbp",
+    "    push r8",
+    "    push r9",
+    "    push r10",
+    "    push r11",
+    "    push r12",
+    "    push r13",
+    "    push r14",
+    "    push r15",
+    "    cld",
+    "    mov rdi, rsp",
+    "    call {dispatch}",
+    "    pop r15",
+    "    pop r14",
+    "    pop r13",
+    "    pop r12",
+    "    pop r11",
+    "    pop r10",
+    "    pop r9",
+    "    pop r8",
+    "    pop rbp",
+    "    pop rdi",
+    "    pop rsi",
+    "    pop rdx",
+    "    pop rcx",
+    "    pop rbx",
+    "    pop rax",
+    // Drop the vector and the error code
+    "    add rsp, 16",
+    "    iretq",
+    dispatch = sym exception_dispatch,
+);
+extern "C" {
+    fn exception_stub_0();
+    fn exception_stub_1();
+    fn exception_stub_3();
+    fn exception_stub_4();
+    fn exception_stub_5();
+    fn exception_stub_6();
+    fn exception_stub_7();
+    fn exception_stub_8();
+    fn exception_stub_10();
+    fn exception_stub_11();
+    fn exception_stub_12();
+    fn exception_stub_13();
+    fn exception_stub_14();
+    fn exception_stub_16();
+    fn exception_stub_17();
+    fn exception_stub_19();
+}
+
+/// Point the exception entries of the IDT to the entry stubs
+pub(super) fn install_stubs(idt: &mut InterruptDescriptorTable) {
+    let addr = |stub: unsafe extern "C" fn()| VirtAddr::new(stub as usize as u64);
+    unsafe {
+        idt.divide_error.set_handler_addr(addr(exception_stub_0));
+        idt.debug.set_handler_addr(addr(exception_stub_1));
+        idt.breakpoint.set_handler_addr(addr(exception_stub_3));
+        idt.overflow.set_handler_addr(addr(exception_stub_4));
+        idt.bound_range_exceeded
+            .set_handler_addr(addr(exception_stub_5));
+        idt.invalid_opcode.set_handler_addr(addr(exception_stub_6));
+        idt.device_not_available
+            .set_handler_addr(addr(exception_stub_7));
+        idt.double_fault
+            .set_handler_addr(addr(exception_stub_8))
+            .set_stack_index(crate::gdt::DOUBLE_FAULT_IST_INDEX);
+        idt.invalid_tss.set_handler_addr(addr(exception_stub_10));
+        idt.segment_not_present
+            .set_handler_addr(addr(exception_stub_11));
+        idt.stack_segment_fault
+            .set_handler_addr(addr(exception_stub_12));
+        idt.general_protection_fault
+            .set_handler_addr(addr(exception_stub_13));
+        idt.page_fault.set_handler_addr(addr(exception_stub_14));
+        idt.x87_floating_point
+            .set_handler_addr(addr(exception_stub_16));
+        idt.alignment_check
+            .set_handler_addr(addr(exception_stub_17));
+        idt.simd_floating_point
+            .set_handler_addr(addr(exception_stub_19));
+    }
+}
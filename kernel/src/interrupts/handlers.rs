@@ -1,133 +1,35 @@
 //! Interrupt handlers for the different interrupts
 use core::sync::atomic::{AtomicBool, AtomicU64};
 
-use crate::*;
-use lazy_static::lazy_static;
-use x86_64::structures::idt::{InterruptStackFrame, PageFaultErrorCode, SelectorErrorCode};
-/// Handler for the breakpoint interrupt
-pub extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
-}
-/// Handler for the double fault interrupt
-pub extern "x86-interrupt" fn double_fault_handler(
-    stack_frame: InterruptStackFrame,
-    _error_code: u64,
-) -> ! {
-    panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
-}
-/// Handler for the page fault interrupt
-pub extern "x86-interrupt" fn page_fault_handler(
-    stack_frame: InterruptStackFrame,
-    error_code: PageFaultErrorCode,
-) {
-    use x86_64::registers::control::Cr2;
-    println!("EXCEPTION: PAGE FAULT");
-    println!("Accessed Address: {:?}", Cr2::read());
-    println!("Error Code: {:?}", error_code);
-    println!("{:#?}", stack_frame);
-    hlt_loop();
-}
-// TO DO: DOCUMENTATION
-// TO DO: Better implementation
-pub extern "x86-interrupt" fn division_error_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: DIVISION ERROR\n{:#?}", stack_frame);
-    // TO DO : Throw sth when division error to prevent infinite loop
-    hlt_loop();
-}
-pub extern "x86-interrupt" fn bound_range_exceeded_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: BOUND RANGE EXCEEDED\n{:#?}", stack_frame);
-}
-pub extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: INVALID OPCODE\n{:#?}", stack_frame);
-}
-pub extern "x86-interrupt" fn device_not_available_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: DEVICE NOT AVAILABLE\n{:#?}", stack_frame);
-}
-pub extern "x86-interrupt" fn invalid_tss_handler(
-    stack_frame: InterruptStackFrame,
-    error_code: u64,
-) {
-    println!("EXCEPTION: INVALID TSS");
-    println!(
-        "Index: {:?}",
-        SelectorErrorCode::new_truncate(error_code).index()
-    );
-    println!("{:#?}", stack_frame);
-}
-pub extern "x86-interrupt" fn segment_not_present_handler(
-    stack_frame: InterruptStackFrame,
-    error_code: u64,
-) {
-    println!("EXCEPTION: SEGMENT NOT PRESENT");
-    println!(
-        "Index: {:?}",
-        SelectorErrorCode::new_truncate(error_code).index()
-    );
-    println!("{:#?}", stack_frame);
-}
-pub extern "x86-interrupt" fn stack_segment_fault_handler(
-    stack_frame: InterruptStackFrame,
-    error_code: u64,
-) {
-    println!("EXCEPTION: STACK SEGMENT FAULT");
-    println!(
-        "Index: {:?}",
-        SelectorErrorCode::new_truncate(error_code).index()
-    );
-    println!("{:#?}", stack_frame);
-}
-pub extern "x86-interrupt" fn general_protection_fault_handler(
-    stack_frame: InterruptStackFrame,
-    error_code: u64,
-) {
-    println!("EXCEPTION: GENERAL PROTECTION FAULT");
-    println!(
-        "Index: {:?}",
-        SelectorErrorCode::new_truncate(error_code).index()
-    );
-    println!("{:#?}", stack_frame);
-}
-pub extern "x86-interrupt" fn x87_floating_point_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: x87 FLOATING POINT\n{:#?}", stack_frame);
-}
-pub extern "x86-interrupt" fn simd_floating_point_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: SIMD FLOATING POINT\n{:#?}", stack_frame);
-}
-pub extern "x86-interrupt" fn debug_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: DEBUG\n{:#?}", stack_frame);
-}
-pub extern "x86-interrupt" fn overflow_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: OVERFLOW\n{:#?}", stack_frame);
-}
-use crate::drivers::apic::local_apic::LOCAL_APIC;
+use super::irq::IrqReturn;
 use core::sync::atomic::*;
+use lazy_static::lazy_static;
 pub static PIT_COUNTER: AtomicU64 = AtomicU64::new(0);
 pub static PIT_SLEEP_COUNTER: AtomicI64 = AtomicI64::new(0);
 pub static PIT_SLEEP_FLAG: AtomicBool = AtomicBool::new(false);
 /// Handler for the timer interrupt
-pub extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
+pub fn timer_interrupt_handler(_vector: u8) -> IrqReturn {
     // print!(".");
     PIT_COUNTER.store(PIT_COUNTER.load(Ordering::Relaxed) + 1, Ordering::Relaxed);
     if PIT_SLEEP_FLAG.load(Ordering::Relaxed) {
         let ptr = PIT_SLEEP_COUNTER.as_ptr();
         unsafe { *ptr = PIT_SLEEP_COUNTER.load(Ordering::Relaxed) - 1 };
     }
-    // Notify the CPU that the interrupt has been handled
-    // and can continue to send other interrupts
-    LOCAL_APIC.set_eoi();
+    // Fallback for the async timers until the LAPIC timer is calibrated
+    if !crate::timer::lapic::is_running() {
+        crate::task::thread::tick();
+        crate::task::timer::wake_expired();
+    }
+    IrqReturn::Handled
 }
-pub static LAPIC_TIMER_SLEEP_FLAG: AtomicBool = AtomicBool::new(false);
-pub static LAPIC_TIMER_SLEEP_COUNTER: AtomicI64 = AtomicI64::new(0);
-pub extern "x86-interrupt" fn lapic_timer_handler(_stack_frame: InterruptStackFrame) {
+pub fn lapic_timer_handler(_vector: u8) -> IrqReturn {
     // print!(".");
-    if LAPIC_TIMER_SLEEP_FLAG.load(Ordering::Relaxed) {
-        let ptr = LAPIC_TIMER_SLEEP_COUNTER.as_ptr();
-        unsafe { *ptr = LAPIC_TIMER_SLEEP_COUNTER.load(Ordering::Relaxed) - 1 };
-    }
-    LOCAL_APIC.set_eoi();
+    crate::task::thread::tick();
+    crate::task::timer::wake_expired();
+    IrqReturn::Handled
 }
 /// Handler for the keyboard interrupt
-pub extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
+pub fn keyboard_interrupt_handler(_vector: u8) -> IrqReturn {
     use pc_keyboard::{layouts, HandleControl, Keyboard, ScancodeSet1};
     use spin::Mutex;
     use x86_64::instructions::port::Port;
@@ -148,7 +50,5 @@ pub extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: Interrupt
     let scancode: u8 = unsafe { port.read() };
     crate::task::keyboard::add_scancode(scancode);
 
-    // Notify the CPU that the interrupt has been handled
-    // and can continue to send other interrupts
-    LOCAL_APIC.set_eoi();
+    IrqReturn::Handled
 }
@@ -0,0 +1,251 @@
+//! Dynamic registration of hardware interrupt handlers
+//!
+//! Every vector from `IRQ_VECTOR_START` up to the spurious vector is routed to a
+//! common stub that counts the interrupt, runs the handlers registered for the
+//! vector and signals the end of interrupt to the local APIC. Several handlers can
+//! share a vector, each one reports if the interrupt came from its device.
+//! Preemption of the interrupted kernel thread happens last, after the EOI.
+use super::InterruptIndexAPIC;
+use crate::drivers::apic::local_apic::LOCAL_APIC;
+use alloc::{boxed::Box, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+use lazy_static::lazy_static;
+use spin::{Mutex, RwLock};
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::structures::idt::{HandlerFunc, InterruptDescriptorTable, InterruptStackFrame};
+
+/// First vector available for hardware interrupts, the ones below are CPU exceptions
+pub const IRQ_VECTOR_START: u8 = 32;
+/// Number of vectors of the IDT
+const VECTOR_COUNT: usize = 256;
+
+/// Result of an interrupt handler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqReturn {
+    /// The interrupt was raised by the device of the handler
+    Handled,
+    /// The interrupt belongs to another device sharing the vector
+    NotHandled,
+}
+/// A handler that can be registered for an interrupt vector
+///
+/// Runs in interrupt context: it must not block, allocate or register handlers.
+pub trait IrqHandler: Send + Sync {
+    fn handle(&self, vector: u8) -> IrqReturn;
+}
+impl<F> IrqHandler for F
+where
+    F: Fn(u8) -> IrqReturn + Send + Sync,
+{
+    fn handle(&self, vector: u8) -> IrqReturn {
+        self(vector)
+    }
+}
+/// Identifies a registered handler so it can be removed later
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandlerId {
+    vector: u8,
+    id: u64,
+}
+impl HandlerId {
+    /// Vector the handler is registered for
+    pub fn vector(&self) -> u8 {
+        self.vector
+    }
+}
+/// Interrupt registration error type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    ReservedVector,
+    NoFreeVector,
+    HandlerNotFound,
+    /// The vector was not handed out by `allocate_vector`
+    VectorNotAllocated,
+}
+
+struct Registration {
+    id: u64,
+    handler: Box<dyn IrqHandler>,
+}
+
+lazy_static! {
+    /// Handlers registered for every vector
+    static ref HANDLERS: RwLock<Vec<Vec<Registration>>> = {
+        let mut handlers = Vec::with_capacity(VECTOR_COUNT);
+        handlers.resize_with(VECTOR_COUNT, Vec::new);
+        RwLock::new(handlers)
+    };
+    /// Vectors handed out by `allocate_vector`, the fixed ones are reserved from the start
+    static ref ALLOCATED_VECTORS: Mutex<[bool; VECTOR_COUNT]> = {
+        let mut allocated = [false; VECTOR_COUNT];
+        for (vector, used) in allocated.iter_mut().enumerate() {
+            *used = is_fixed(vector as u8);
+        }
+        Mutex::new(allocated)
+    };
+}
+/// Number of interrupts received on every vector
+static IRQ_COUNTERS: [AtomicU64; VECTOR_COUNT] = [const { AtomicU64::new(0) }; VECTOR_COUNT];
+/// Interrupts no registered handler claimed
+static UNHANDLED_COUNTER: AtomicU64 = AtomicU64::new(0);
+/// Source of handler ids
+static NEXT_HANDLER_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Reserve a free vector for a device
+pub fn allocate_vector() -> Result<u8, IrqError> {
+    let mut allocated = ALLOCATED_VECTORS.lock();
+    let vector = allocated
+        .iter()
+        .position(|used| !used)
+        .ok_or(IrqError::NoFreeVector)?;
+    allocated[vector] = true;
+    Ok(vector as u8)
+}
+/// Give back a vector reserved with `allocate_vector`
+///
+/// Exception and built in vectors are never freed.
+pub fn free_vector(vector: u8) -> Result<(), IrqError> {
+    if is_fixed(vector) {
+        return Err(IrqError::ReservedVector);
+    }
+    let mut allocated = ALLOCATED_VECTORS.lock();
+    if !allocated[vector as usize] {
+        return Err(IrqError::VectorNotAllocated);
+    }
+    allocated[vector as usize] = false;
+    Ok(())
+}
+/// Exception vectors and the vectors of the built in devices, never handed out
+fn is_fixed(vector: u8) -> bool {
+    vector < IRQ_VECTOR_START
+        || [
+            InterruptIndexAPIC::Timer,
+            InterruptIndexAPIC::LAPICTimer,
+            InterruptIndexAPIC::Keyboard,
+            InterruptIndexAPIC::Spurious,
+        ]
+        .iter()
+        .any(|index| index.as_u8() == vector)
+}
+/// Register a handler for the given vector
+///
+/// The vector may already have other handlers, in which case the line is shared.
+pub fn register_irq_handler<H>(vector: u8, handler: H) -> Result<HandlerId, IrqError>
+where
+    H: IrqHandler + 'static,
+{
+    if vector < IRQ_VECTOR_START || vector == InterruptIndexAPIC::Spurious.as_u8() {
+        return Err(IrqError::ReservedVector);
+    }
+    let id = NEXT_HANDLER_ID.fetch_add(1, Ordering::Relaxed);
+    let registration = Registration {
+        id,
+        handler: Box::new(handler),
+    };
+    // The handlers are also read from interrupt context
+    without_interrupts(|| HANDLERS.write()[vector as usize].push(registration));
+    Ok(HandlerId { vector, id })
+}
+/// Remove a handler registered with `register_irq_handler`
+pub fn unregister_irq_handler(handler: HandlerId) -> Result<(), IrqError> {
+    let removed = without_interrupts(|| {
+        let mut handlers = HANDLERS.write();
+        let registrations = &mut handlers[handler.vector as usize];
+        let position = registrations
+            .iter()
+            .position(|registration| registration.id == handler.id)?;
+        Some(registrations.remove(position))
+    });
+    // Drop the handler with interrupts enabled, it may own heap memory
+    match removed {
+        Some(_) => Ok(()),
+        None => Err(IrqError::HandlerNotFound),
+    }
+}
+/// Number of interrupts received on the given vector
+pub fn irq_count(vector: u8) -> u64 {
+    IRQ_COUNTERS[vector as usize].load(Ordering::Relaxed)
+}
+/// Number of interrupts no handler claimed
+pub fn unhandled_count() -> u64 {
+    UNHANDLED_COUNTER.load(Ordering::Relaxed)
+}
+/// Vectors that received at least one interrupt with their counters
+pub fn irq_stats() -> Vec<(u8, u64)> {
+    (0..VECTOR_COUNT)
+        .map(|vector| (vector as u8, irq_count(vector as u8)))
+        .filter(|(_, count)| *count != 0)
+        .collect()
+}
+
+/// Run the handlers of a vector and acknowledge the interrupt
+fn dispatch(vector: u8) {
+    IRQ_COUNTERS[vector as usize].fetch_add(1, Ordering::Relaxed);
+    let mut handled = false;
+    // A handler being registered on this CPU cannot hold the lock, interrupts are disabled meanwhile
+    for registration in HANDLERS.read()[vector as usize].iter() {
+        if registration.handler.handle(vector) == IrqReturn::Handled {
+            handled = true;
+        }
+    }
+    if !handled {
+        UNHANDLED_COUNTER.fetch_add(1, Ordering::Relaxed);
+    }
+    LOCAL_APIC.set_eoi();
+    // Only switch threads once the interrupt is acknowledged, the other thread may run for a while
+    crate::task::thread::preempt();
+}
+/// Entry point of the vector `V`
+extern "x86-interrupt" fn irq_stub<const V: u8>(_stack_frame: InterruptStackFrame) {
+    dispatch(V);
+}
+/// Stubs for the 16 vectors starting at `$base`
+macro_rules! stub_row {
+    ($base:expr) => {
+        [
+            irq_stub::<{ $base }>,
+            irq_stub::<{ $base + 1 }>,
+            irq_stub::<{ $base + 2 }>,
+            irq_stub::<{ $base + 3 }>,
+            irq_stub::<{ $base + 4 }>,
+            irq_stub::<{ $base + 5 }>,
+            irq_stub::<{ $base + 6 }>,
+            irq_stub::<{ $base + 7 }>,
+            irq_stub::<{ $base + 8 }>,
+            irq_stub::<{ $base + 9 }>,
+            irq_stub::<{ $base + 10 }>,
+            irq_stub::<{ $base + 11 }>,
+            irq_stub::<{ $base + 12 }>,
+            irq_stub::<{ $base + 13 }>,
+            irq_stub::<{ $base + 14 }>,
+            irq_stub::<{ $base + 15 }>,
+        ]
+    };
+}
+/// Point every hardware interrupt vector of the IDT to its dispatch stub
+pub(super) fn install_stubs(idt: &mut InterruptDescriptorTable) {
+    let rows: [[HandlerFunc; 16]; 14] = [
+        stub_row!(0x20),
+        stub_row!(0x30),
+        stub_row!(0x40),
+        stub_row!(0x50),
+        stub_row!(0x60),
+        stub_row!(0x70),
+        stub_row!(0x80),
+        stub_row!(0x90),
+        stub_row!(0xA0),
+        stub_row!(0xB0),
+        stub_row!(0xC0),
+        stub_row!(0xD0),
+        stub_row!(0xE0),
+        stub_row!(0xF0),
+    ];
+    for (index, stub) in rows.iter().flatten().enumerate() {
+        let vector = IRQ_VECTOR_START + index as u8;
+        // Spurious interrupts must not be acknowledged
+        if vector == InterruptIndexAPIC::Spurious.as_u8() {
+            continue;
+        }
+        idt[vector].set_handler_fn(*stub);
+    }
+}
@@ -1,11 +1,13 @@
 //! Module for handling interrupts
 
-use crate::gdt;
 use handlers::*;
 use lazy_static::lazy_static;
 use x86_64::structures::idt::InterruptDescriptorTable;
 
+pub mod exceptions;
 pub mod handlers;
+pub mod irq;
+#[cfg(test)]
 mod tests;
 
 /// Programmable Interrupt Controller used for hardware
@@ -70,32 +72,10 @@ lazy_static! {
         // pg 201
         // Create a new IDT
         let mut idt = InterruptDescriptorTable::new();
-        // Set the handler for the breakpoint interrupt
-        idt.breakpoint.set_handler_fn(breakpoint_handler);
-        // Set the handler for the double fault interrupt
-        unsafe {
-            idt.double_fault
-                .set_handler_fn(double_fault_handler)
-                .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
-        }
-        // Set the handler for the page fault interrupt
-        idt.page_fault.set_handler_fn(page_fault_handler);
-        // TO DO : DOCUMENTATION
-        idt.divide_error.set_handler_fn(division_error_handler);
-        idt.bound_range_exceeded.set_handler_fn(bound_range_exceeded_handler);
-        idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
-        idt.device_not_available.set_handler_fn(device_not_available_handler);
-        idt.invalid_tss.set_handler_fn(invalid_tss_handler);
-        idt.segment_not_present.set_handler_fn(segment_not_present_handler);
-        idt.stack_segment_fault.set_handler_fn(stack_segment_fault_handler);
-        idt.general_protection_fault.set_handler_fn(general_protection_fault_handler);
-        idt.x87_floating_point.set_handler_fn(x87_floating_point_handler);
-        idt.simd_floating_point.set_handler_fn(simd_floating_point_handler);
-        idt.debug.set_handler_fn(debug_handler);
-        idt.overflow.set_handler_fn(overflow_handler);
-        idt[InterruptIndexAPIC::Timer.as_u8()].set_handler_fn(timer_interrupt_handler);
-        idt[InterruptIndexAPIC::Keyboard.as_u8()].set_handler_fn(keyboard_interrupt_handler);
-        idt[InterruptIndexAPIC::LAPICTimer.as_u8()].set_handler_fn(lapic_timer_handler);
+        // CPU exceptions go through the register saving stubs of `exceptions`
+        exceptions::install_stubs(&mut idt);
+        // Hardware interrupts go through the handlers registered in `irq`
+        irq::install_stubs(&mut idt);
         idt
     };
 }
@@ -103,3 +83,18 @@ lazy_static! {
 pub fn init_idt() {
     IDT.load();
 }
+/// Register the handlers of the built in devices
+///
+/// Must be called after the heap is initialized.
+pub fn init_irq_handlers() {
+    use irq::register_irq_handler;
+    register_irq_handler(InterruptIndexAPIC::Timer.as_u8(), timer_interrupt_handler)
+        .expect("[IRQ]: Failed to register the timer handler");
+    register_irq_handler(InterruptIndexAPIC::LAPICTimer.as_u8(), lapic_timer_handler)
+        .expect("[IRQ]: Failed to register the LAPIC timer handler");
+    register_irq_handler(
+        InterruptIndexAPIC::Keyboard.as_u8(),
+        keyboard_interrupt_handler,
+    )
+    .expect("[IRQ]: Failed to register the keyboard handler");
+}
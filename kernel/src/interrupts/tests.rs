@@ -1,5 +1,134 @@
-// #[test_case]
-// fn test_breakpoint_exception() {
-//     // invoke a breakpoint exception
-//     x86_64::instructions::interrupts::int3();
-// }
+use super::exceptions::{self, Exception, FaultAction};
+use super::irq::{self, IrqError, IrqReturn};
+use super::InterruptIndexAPIC;
+use crate::drivers::apic::local_apic::LOCAL_APIC;
+use alloc::sync::Arc;
+use core::arch::global_asm;
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::VirtAddr;
+
+// Reads the `u64` at `rdi`, the fixup returns `u64::MAX` instead of faulting
+global_asm!(
+    "fixup_test_read:",
+    "mov rax, [rdi]",
+    "fixup_test_read_end:",
+    "ret",
+    "fixup_test_fixup:",
+    "mov rax, -1",
+    "ret",
+);
+extern "sysv64" {
+    fn fixup_test_read(addr: u64) -> u64;
+    fn fixup_test_read_end();
+    fn fixup_test_fixup();
+}
+
+#[test_case]
+fn test_breakpoint_exception() {
+    // invoke a breakpoint exception
+    x86_64::instructions::interrupts::int3();
+}
+#[test_case]
+fn test_faults_panic_by_default() {
+    assert_eq!(
+        exceptions::fault_policy(Exception::PageFault),
+        FaultAction::Panic
+    );
+    assert_eq!(
+        exceptions::fault_policy(Exception::GeneralProtectionFault),
+        FaultAction::Panic
+    );
+    assert_eq!(
+        exceptions::fault_policy(Exception::Breakpoint),
+        FaultAction::Resume
+    );
+}
+#[test_case]
+fn test_fixup_resumes() {
+    let addr = |function: usize| VirtAddr::new(function as u64);
+    exceptions::register_fixup(
+        addr(fixup_test_read as usize),
+        addr(fixup_test_read_end as usize),
+        addr(fixup_test_fixup as usize),
+    );
+    let value = 42u64;
+    assert_eq!(unsafe { fixup_test_read(&value as *const u64 as u64) }, 42);
+    // Non canonical address, the general protection fault resumes at the fixup
+    assert_eq!(unsafe { fixup_test_read(0xdead_beef_0000_0000) }, u64::MAX);
+}
+#[test_case]
+fn test_allocate_free_vector() {
+    let vector = irq::allocate_vector().unwrap();
+    assert!(vector >= irq::IRQ_VECTOR_START);
+    assert_eq!(irq::free_vector(vector), Ok(()));
+    assert_eq!(irq::free_vector(vector), Err(IrqError::VectorNotAllocated));
+    // The lowest free vector is handed out again
+    assert_eq!(irq::allocate_vector(), Ok(vector));
+    irq::free_vector(vector).unwrap();
+}
+#[test_case]
+fn test_free_fixed_vector() {
+    for vector in [
+        0,
+        14,
+        InterruptIndexAPIC::Timer.as_u8(),
+        InterruptIndexAPIC::LAPICTimer.as_u8(),
+        InterruptIndexAPIC::Keyboard.as_u8(),
+        InterruptIndexAPIC::Spurious.as_u8(),
+    ] {
+        assert_eq!(irq::free_vector(vector), Err(IrqError::ReservedVector));
+    }
+}
+#[test_case]
+fn test_register_unregister() {
+    let vector = irq::allocate_vector().unwrap();
+    let first = irq::register_irq_handler(vector, |_| IrqReturn::NotHandled).unwrap();
+    let second = irq::register_irq_handler(vector, |_| IrqReturn::Handled).unwrap();
+    assert_eq!(first.vector(), vector);
+    assert_ne!(first, second);
+    assert_eq!(irq::unregister_irq_handler(first), Ok(()));
+    assert_eq!(
+        irq::unregister_irq_handler(first),
+        Err(IrqError::HandlerNotFound)
+    );
+    assert_eq!(irq::unregister_irq_handler(second), Ok(()));
+    irq::free_vector(vector).unwrap();
+    let reserved = [3, InterruptIndexAPIC::Spurious.as_u8()];
+    for vector in reserved {
+        assert_eq!(
+            irq::register_irq_handler(vector, |_| IrqReturn::Handled).unwrap_err(),
+            IrqError::ReservedVector
+        );
+    }
+}
+#[test_case]
+fn test_shared_vector_dispatch() {
+    let vector = irq::allocate_vector().unwrap();
+    let calls = Arc::new([AtomicU64::new(0), AtomicU64::new(0)]);
+    let (first_calls, second_calls) = (calls.clone(), calls.clone());
+    let first = irq::register_irq_handler(vector, move |_| {
+        first_calls[0].fetch_add(1, Ordering::Relaxed);
+        IrqReturn::NotHandled
+    })
+    .unwrap();
+    let second = irq::register_irq_handler(vector, move |_| {
+        second_calls[1].fetch_add(1, Ordering::Relaxed);
+        IrqReturn::Handled
+    })
+    .unwrap();
+    let count = irq::irq_count(vector);
+    LOCAL_APIC.send_self_ipi(vector);
+    // Delivered as soon as interrupts are enabled, give the LAPIC a moment
+    for _ in 0..1_000_000 {
+        if irq::irq_count(vector) != count {
+            break;
+        }
+        core::hint::spin_loop();
+    }
+    assert_eq!(irq::irq_count(vector), count + 1);
+    assert_eq!(calls[0].load(Ordering::Relaxed), 1);
+    assert_eq!(calls[1].load(Ordering::Relaxed), 1);
+    irq::unregister_irq_handler(first).unwrap();
+    irq::unregister_irq_handler(second).unwrap();
+    irq::free_vector(vector).unwrap();
+}
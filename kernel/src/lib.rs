@@ -1,29 +1,32 @@
 #![no_std]
 #![no_main]
 #![feature(abi_x86_interrupt)]
-// TO SOLVE
-// #![feature(custom_test_frameworks)]
-// #![test_runner(utils::test_runner)]
-// #![reexport_test_harness_main = "test_main"]
+#![feature(custom_test_frameworks)]
+#![test_runner(utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
 #![feature(const_mut_refs)]
 
 extern crate alloc;
 //maybe refactor in memory?
 pub mod allocator;
 //-------------------------
+pub mod boot_params;
 pub mod drivers;
 pub mod gdt;
 pub mod interrupts;
 pub mod io;
+pub mod logger;
 pub mod memory;
+pub mod smp;
 pub mod utils;
 //maybe refactor in multiTasking or sth?
 pub mod task;
+pub mod time;
 pub mod timer;
 // use lazy_static::lazy_static;
 //--------------------------------------
 use limine::{
-    request::{HhdmRequest, KernelAddressRequest},
+    request::{HhdmRequest, KernelAddressRequest, KernelFileRequest, SmpRequest},
     BaseRevision,
 };
 #[used]
@@ -31,16 +34,20 @@ use limine::{
 static BASE_REVISION: BaseRevision = BaseRevision::new();
 #[used]
 #[link_section = ".requests"]
-static KERNEL_ADDRESS_REQUEST: KernelAddressRequest = KernelAddressRequest::new();
+pub(crate) static KERNEL_ADDRESS_REQUEST: KernelAddressRequest = KernelAddressRequest::new();
 #[used]
 #[link_section = ".requests"]
 pub static HHDM_REQUEST: HhdmRequest = HhdmRequest::new();
-// TO SOLVE
-// #[cfg(test)]
-// use utils::test_runner;
+#[used]
+#[link_section = ".requests"]
+pub(crate) static KERNEL_FILE_REQUEST: KernelFileRequest = KernelFileRequest::new();
+#[used]
+#[link_section = ".requests"]
+pub(crate) static SMP_REQUEST: SmpRequest = SmpRequest::new();
 /// Function to initialize necessary functionalities of the kernel
 /// such as gdt or interrupts
 pub fn init() {
+    logger::init();
     gdt::init();
     interrupts::init_idt();
     // unsafe {
@@ -53,18 +60,33 @@ pub fn init() {
     // unsafe { interrupts::PICS.lock().disable() };
     x86_64::instructions::interrupts::enable();
     // Mem init
-    use memory::BootInfoFrameAllocator;
+    use memory::FRAME_ALLOCATOR;
     use x86_64::VirtAddr;
 
     let phys_base = HHDM_REQUEST.get_response().unwrap().offset();
     let phys_mem_offset = VirtAddr::new(phys_base);
     let mut mapper = unsafe { memory::init(phys_mem_offset) };
-    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init() };
-    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    unsafe { FRAME_ALLOCATOR.lock().init(phys_mem_offset) };
+    allocator::init_heap(&mut mapper, &mut *FRAME_ALLOCATOR.lock())
+        .expect("heap initialization failed");
+    logger::enable_console();
+    memory::vmm::init();
+    boot_params::init();
+    interrupts::init_irq_handlers();
 
     use drivers::apic::{io_apic, local_apic};
     local_apic::init();
     io_apic::init();
+    // Keep the uptime clock of the log timestamps running
+    timer::pit::PIT::start_millisecond_ticks();
+    timer::hpet::init();
+    time::init();
+    timer::rtc::init();
+    task::thread::init();
+    smp::init();
+    if drivers::acpi::LIST_TABLES.get() {
+        drivers::acpi::ACPI_TABLES.list_tables();
+    }
 }
 /// Performant empty loop thet saves cpu time
 pub fn hlt_loop() -> ! {
@@ -73,15 +95,11 @@ pub fn hlt_loop() -> ! {
     }
 }
 
-// #[cfg(test)]
-// #[no_mangle]
-// unsafe extern "C" fn _start() -> ! {
-//     // init();
-//     test_main();
-//     // hlt_loop();
-//     loop {}
-// }
-// #[test_case]
-// fn trivial_assertion() {
-//     assert_eq!(1, 1);
-// }
+/// Entry point of the test kernel of the library
+#[cfg(test)]
+#[no_mangle]
+unsafe extern "C" fn _start() -> ! {
+    init();
+    test_main();
+    hlt_loop();
+}
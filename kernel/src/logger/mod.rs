@@ -0,0 +1,179 @@
+//! Kernel logger behind the `log` crate facade
+//!
+//! Every record is stored with its time since boot in a lock-free ring buffer that
+//! can be dumped later with `dmesg`, then forwarded to the sinks (serial port
+//! and framebuffer console) whose level allows it. Levels are set with the
+//! `log.level`, `log.serial` and `log.console` boot parameters.
+use crate::boot_params::BootParam;
+use crate::{println, serial_println};
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use log::{LevelFilter, Log, Metadata, Record};
+use ring_buffer::{LogEntry, RingBuffer};
+
+pub mod ring_buffer;
+
+/// Number of records kept for `dmesg`
+pub const LOG_BUFFER_SIZE: usize = 256;
+
+/// Most verbose level recorded at all
+pub static LOG_LEVEL: BootParam<&str> =
+    BootParam::new("log.level", "most verbose level recorded", "debug");
+/// Most verbose level printed to the serial port
+pub static SERIAL_LEVEL: BootParam<&str> =
+    BootParam::new("log.serial", "most verbose level on serial", "info");
+/// Most verbose level printed to the framebuffer console
+pub static CONSOLE_LEVEL: BootParam<&str> =
+    BootParam::new("log.console", "most verbose level on the console", "warn");
+
+static LOGGER: KernelLogger = KernelLogger;
+static LOG_BUFFER: RingBuffer<LOG_BUFFER_SIZE> = RingBuffer::new();
+
+/// An output the log records are printed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sink {
+    Serial,
+    /// Framebuffer console, silent until `enable_console` is called
+    Console,
+}
+impl Sink {
+    const ALL: [Sink; 2] = [Sink::Serial, Sink::Console];
+    fn write(self, entry: &LogEntry) {
+        match self {
+            Sink::Serial => {
+                serial_println!("{}", EntryDisplay(entry));
+            }
+            Sink::Console => {
+                println!("{}", EntryDisplay(entry));
+            }
+        }
+    }
+}
+/// Level of every sink, as `LevelFilter as usize`
+static SINK_LEVELS: [AtomicUsize; 2] = [
+    AtomicUsize::new(LevelFilter::Info as usize),
+    AtomicUsize::new(LevelFilter::Off as usize),
+];
+
+/// Set the most verbose level printed to `sink`
+pub fn set_sink_level(sink: Sink, level: LevelFilter) {
+    SINK_LEVELS[sink as usize].store(level as usize, Ordering::Relaxed);
+}
+/// Most verbose level printed to `sink`
+pub fn sink_level(sink: Sink) -> LevelFilter {
+    let level = SINK_LEVELS[sink as usize].load(Ordering::Relaxed);
+    LevelFilter::iter().nth(level).unwrap_or(LevelFilter::Off)
+}
+
+struct KernelLogger;
+impl Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let target = record
+            .target()
+            .strip_prefix("ferrum_os::")
+            .unwrap_or(record.target());
+        let sequence = LOG_BUFFER.push(
+            record.level(),
+            crate::time::uptime().as_millis() as u64,
+            format_args!("{}: {}", target, record.args()),
+        );
+        if Sink::ALL
+            .iter()
+            .all(|sink| record.level() > sink_level(*sink))
+        {
+            return;
+        }
+        // Print the stored copy, so every sink shows the same truncated text
+        let Some(entry) = LOG_BUFFER.read(sequence) else {
+            return;
+        };
+        for sink in Sink::ALL {
+            if entry.level <= sink_level(sink) {
+                sink.write(&entry);
+            }
+        }
+    }
+    fn flush(&self) {}
+}
+
+/// Prints a record like `[    1.250] INFO  acpi: message`
+struct EntryDisplay<'a>(&'a LogEntry);
+impl fmt::Display for EntryDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entry = self.0;
+        write!(
+            f,
+            "[{:>5}.{:03}] {:<5} {}",
+            entry.timestamp / 1000,
+            entry.timestamp % 1000,
+            entry.level,
+            entry.text()
+        )
+    }
+}
+
+/// Parse a level boot parameter, falling back to its default
+fn level_param(param: &BootParam<&'static str>) -> LevelFilter {
+    param
+        .get()
+        .parse()
+        .or_else(|_| param.default().parse())
+        .unwrap_or(LevelFilter::Info)
+}
+/// Report the level boot parameters that are not a level name
+fn check_level_params() {
+    for param in [&LOG_LEVEL, &SERIAL_LEVEL, &CONSOLE_LEVEL] {
+        if param.get().parse::<LevelFilter>().is_err() {
+            log::warn!(
+                "Invalid level {:?} for {}, using {}",
+                param.get(),
+                param.name(),
+                param.default()
+            );
+        }
+    }
+}
+/// Install the logger, records are only kept from this point on
+///
+/// Does not allocate, so it can run before the heap is set up.
+pub fn init() {
+    if log::set_logger(&LOGGER).is_err() {
+        return;
+    }
+    log::set_max_level(level_param(&LOG_LEVEL));
+    set_sink_level(Sink::Serial, level_param(&SERIAL_LEVEL));
+    check_level_params();
+}
+/// Start printing records to the framebuffer console, which needs the heap
+pub fn enable_console() {
+    set_sink_level(Sink::Console, level_param(&CONSOLE_LEVEL));
+}
+
+/// Write every record still in the buffer, oldest first
+pub fn dump(writer: &mut impl fmt::Write) -> fmt::Result {
+    for entry in LOG_BUFFER.entries() {
+        writeln!(writer, "{}", EntryDisplay(&entry))?;
+    }
+    Ok(())
+}
+/// Print the log buffer to the console and the serial port
+pub fn dmesg() {
+    let total = LOG_BUFFER.total();
+    let dropped = total.saturating_sub(LOG_BUFFER_SIZE as u64);
+    if dropped != 0 {
+        println!("({} older records dropped)", dropped);
+    }
+    for entry in LOG_BUFFER.entries() {
+        println!("{}", EntryDisplay(&entry));
+        serial_println!("{}", EntryDisplay(&entry));
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,145 @@
+//! Lock-free buffer of the most recent log records
+//!
+//! Writers claim a slot by incrementing a sequence counter, so records can be
+//! added from interrupt context without taking a lock. Every slot carries a
+//! state word used like a seqlock: readers copy a record and check that the
+//! state did not change meanwhile, otherwise the record was overwritten.
+use core::fmt::{self, Write};
+use core::sync::atomic::{fence, AtomicU64, AtomicU8, Ordering};
+use log::Level;
+
+/// Bytes of text kept per record, longer messages are truncated
+pub const MESSAGE_SIZE: usize = 120;
+
+struct Slot {
+    /// `2 * sequence + 1` while the record is written, `2 * sequence + 2` once complete
+    state: AtomicU64,
+    timestamp: AtomicU64,
+    level: AtomicU8,
+    length: AtomicU8,
+    text: [AtomicU8; MESSAGE_SIZE],
+}
+impl Slot {
+    const fn new() -> Self {
+        Slot {
+            state: AtomicU64::new(0),
+            timestamp: AtomicU64::new(0),
+            level: AtomicU8::new(0),
+            length: AtomicU8::new(0),
+            text: [const { AtomicU8::new(0) }; MESSAGE_SIZE],
+        }
+    }
+}
+/// Copies formatted text into a slot, dropping what does not fit
+struct SlotWriter<'a> {
+    slot: &'a Slot,
+    length: usize,
+}
+impl Write for SlotWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes().take(MESSAGE_SIZE - self.length) {
+            self.slot.text[self.length].store(byte, Ordering::Relaxed);
+            self.length += 1;
+        }
+        Ok(())
+    }
+}
+
+/// A record copied out of the buffer
+#[derive(Clone)]
+pub struct LogEntry {
+    /// Position of the record since boot, gaps mean records were overwritten
+    pub sequence: u64,
+    /// Milliseconds since boot
+    pub timestamp: u64,
+    pub level: Level,
+    length: usize,
+    text: [u8; MESSAGE_SIZE],
+}
+impl LogEntry {
+    /// The message, cut at the last complete character if it was truncated
+    pub fn text(&self) -> &str {
+        let text = &self.text[..self.length];
+        match core::str::from_utf8(text) {
+            Ok(text) => text,
+            Err(error) => core::str::from_utf8(&text[..error.valid_up_to()]).unwrap_or(""),
+        }
+    }
+}
+
+/// Ring buffer holding the last `N` records
+pub struct RingBuffer<const N: usize> {
+    /// Sequence number of the next record
+    next: AtomicU64,
+    slots: [Slot; N],
+}
+impl<const N: usize> RingBuffer<N> {
+    pub const fn new() -> Self {
+        RingBuffer {
+            next: AtomicU64::new(0),
+            slots: [const { Slot::new() }; N],
+        }
+    }
+    /// Add a record, overwriting the oldest one when the buffer is full
+    ///
+    /// Returns the sequence number of the record.
+    pub fn push(&self, level: Level, timestamp: u64, args: fmt::Arguments) -> u64 {
+        let sequence = self.next.fetch_add(1, Ordering::Relaxed);
+        let slot = &self.slots[(sequence % N as u64) as usize];
+        slot.state.store(2 * sequence + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        slot.timestamp.store(timestamp, Ordering::Relaxed);
+        slot.level.store(level as u8, Ordering::Relaxed);
+        let mut writer = SlotWriter { slot, length: 0 };
+        let _ = writer.write_fmt(args);
+        slot.length.store(writer.length as u8, Ordering::Relaxed);
+        slot.state.store(2 * sequence + 2, Ordering::Release);
+        sequence
+    }
+    /// Copy the record with the given sequence number, if it is still in the buffer
+    pub fn read(&self, sequence: u64) -> Option<LogEntry> {
+        let slot = &self.slots[(sequence % N as u64) as usize];
+        let state = slot.state.load(Ordering::Acquire);
+        if state != 2 * sequence + 2 {
+            return None;
+        }
+        let mut text = [0; MESSAGE_SIZE];
+        for (byte, stored) in text.iter_mut().zip(slot.text.iter()) {
+            *byte = stored.load(Ordering::Relaxed);
+        }
+        let entry = LogEntry {
+            sequence,
+            timestamp: slot.timestamp.load(Ordering::Relaxed),
+            level: level_from_u8(slot.level.load(Ordering::Relaxed))?,
+            length: slot.length.load(Ordering::Relaxed) as usize,
+            text,
+        };
+        // A writer reusing the slot while it was copied invalidates the copy
+        fence(Ordering::Acquire);
+        (slot.state.load(Ordering::Relaxed) == state).then_some(entry)
+    }
+    /// Number of records added since boot, including overwritten ones
+    pub fn total(&self) -> u64 {
+        self.next.load(Ordering::Acquire)
+    }
+    /// The records still in the buffer, oldest first
+    pub fn entries(&self) -> impl Iterator<Item = LogEntry> + '_ {
+        let end = self.total();
+        (end.saturating_sub(N as u64)..end).filter_map(|sequence| self.read(sequence))
+    }
+}
+impl<const N: usize> Default for RingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+fn level_from_u8(level: u8) -> Option<Level> {
+    match level {
+        1 => Some(Level::Error),
+        2 => Some(Level::Warn),
+        3 => Some(Level::Info),
+        4 => Some(Level::Debug),
+        5 => Some(Level::Trace),
+        _ => None,
+    }
+}
@@ -0,0 +1,41 @@
+use super::ring_buffer::{RingBuffer, MESSAGE_SIZE};
+use super::*;
+use alloc::{string::String, vec::Vec};
+use log::Level;
+
+#[test_case]
+fn test_ring_buffer_keeps_latest() {
+    let buffer: RingBuffer<4> = RingBuffer::new();
+    for index in 0..6 {
+        buffer.push(Level::Info, index, format_args!("record {}", index));
+    }
+    let entries: Vec<LogEntry> = buffer.entries().collect();
+    assert_eq!(buffer.total(), 6);
+    assert_eq!(entries.len(), 4);
+    assert_eq!(entries[0].sequence, 2);
+    assert_eq!(entries[0].text(), "record 2");
+    assert_eq!(entries[3].timestamp, 5);
+    assert!(buffer.read(1).is_none());
+}
+#[test_case]
+fn test_ring_buffer_truncates() {
+    let buffer: RingBuffer<2> = RingBuffer::new();
+    let long = "é".repeat(MESSAGE_SIZE);
+    let sequence = buffer.push(Level::Warn, 0, format_args!("x{}", long));
+    let entry = buffer.read(sequence).unwrap();
+    assert_eq!(entry.level, Level::Warn);
+    // Two byte characters must not be cut in half
+    assert_eq!(entry.text().len(), MESSAGE_SIZE - 1);
+    assert!(entry.text().chars().skip(1).all(|c| c == 'é'));
+}
+#[test_case]
+fn test_log_records_are_buffered() {
+    let before = LOG_BUFFER.total();
+    log::info!("buffered record");
+    let entry = LOG_BUFFER.read(before).unwrap();
+    assert_eq!(entry.level, Level::Info);
+    assert!(entry.text().ends_with("buffered record"));
+    let mut dumped = String::new();
+    dump(&mut dumped).unwrap();
+    assert!(dumped.contains("buffered record"));
+}
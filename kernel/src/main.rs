@@ -1,5 +1,8 @@
 #![no_std]
 #![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(ferrum_os::utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
 
 use alloc::string::{String, ToString};
 use ferrum_os::*;
@@ -9,29 +12,26 @@ use task::{executor, keyboard, Task};
 
 extern crate alloc;
 
-#[cfg(test)]
-pub fn test_runner(tests: &[&dyn Fn()]) {
-    serial_println!("Running {} tests", tests.len());
-    for test in tests {
-        test();
-    }
-}
 #[no_mangle]
 unsafe extern "C" fn _start() -> ! {
     ferrum_os::init();
+    #[cfg(test)]
+    test_main();
     welcome();
     use timer::lapic::*;
-    use timer::pit::PIT;
-    lapic_calibrate();
-    serial_println!("start");
-    let start = PIT::get_counter();
-    LAPICTimer::sleep(100);
-    // timer::pit::PIT::sleep(1000);
-    let end = PIT::get_counter();
-    serial_println!("end");
-    serial_println!("Ticks: {}", end - start);
+    // The LAPIC timer can only be used once calibrated
+    if CALIBRATE.get() {
+        lapic_calibrate();
+        timer::stop_unused_ticks();
+        log::debug!("start");
+        let start = time::Instant::now();
+        LAPICTimer::sleep(100);
+        // timer::pit::PIT::sleep(1000);
+        log::debug!("end");
+        log::debug!("LAPIC sleep took {:?}", start.elapsed());
+    }
     let mut executor = executor::Executor::new();
-    executor.spawn(Task::new(keyboard::print_keypresses()));
+    executor.spawn(Task::new(keyboard::print_keypresses()).with_name("keyboard"));
     executor.run();
 }
 fn calibrate() {
@@ -99,7 +99,7 @@ async fn _async_world() {
     }
 }
 
-use core::{char, pin::Pin, time};
+use core::{char, pin::Pin};
 
 fn _heap_test_debug() {
     use alloc::{boxed::Box, rc::Rc, vec, vec::Vec};
@@ -0,0 +1,349 @@
+//! Bitmap based physical frame allocator
+//!
+//! Every usable region of the Limine memory map gets its own slice of a
+//! single bitmap (one bit per 4 KiB frame, 1 = used). The bitmap itself is
+//! stored in the first usable region that is large enough to hold it and
+//! is accessed through the higher half direct map.
+use super::MEMORY_REGIONS;
+use limine::memory_map::EntryType;
+use x86_64::{
+    structures::paging::{
+        FrameAllocator, FrameDeallocator, PageSize, PhysFrame, Size1GiB, Size2MiB, Size4KiB,
+    },
+    PhysAddr, VirtAddr,
+};
+/// Size of a frame in bytes
+const FRAME_SIZE: u64 = Size4KiB::SIZE;
+/// Number of frames tracked by a single bitmap word
+const BITS_PER_WORD: usize = u64::BITS as usize;
+/// Maximum number of usable memory regions tracked by the allocator
+const MAX_REGIONS: usize = 64;
+
+/// A usable region of physical memory tracked by the allocator
+#[derive(Debug, Clone, Copy)]
+pub struct FrameRegion {
+    start: u64,          // Physical address of the first frame
+    frames: usize,       // Number of frames in the region
+    free: usize,         // Number of free frames in the region
+    bitmap_start: usize, // Index of the first bitmap word of the region
+    next_hint: usize,    // Word index where the next single frame search starts
+}
+impl FrameRegion {
+    /// Create an empty region
+    const fn empty() -> Self {
+        FrameRegion {
+            start: 0,
+            frames: 0,
+            free: 0,
+            bitmap_start: 0,
+            next_hint: 0,
+        }
+    }
+    /// Physical address of the first frame of the region
+    pub fn start_address(&self) -> PhysAddr {
+        PhysAddr::new(self.start)
+    }
+    /// Total number of frames in the region
+    pub fn frame_count(&self) -> usize {
+        self.frames
+    }
+    /// Number of frames that are currently free
+    pub fn free_frames(&self) -> usize {
+        self.free
+    }
+    /// Number of frames that are currently allocated
+    pub fn used_frames(&self) -> usize {
+        self.frames - self.free
+    }
+    /// Number of bitmap words used by the region
+    fn words(&self) -> usize {
+        self.frames.div_ceil(BITS_PER_WORD)
+    }
+    /// Returns the index of the frame containing `addr` if it lies in the region
+    fn frame_index(&self, addr: u64) -> Option<usize> {
+        let end = self.start + self.frames as u64 * FRAME_SIZE;
+        if addr >= self.start && addr < end {
+            Some(((addr - self.start) / FRAME_SIZE) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// Why a run of frames could not be freed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// The first frame is outside the managed regions
+    NotManaged,
+    /// The run crosses the end of its region
+    CrossesRegion,
+    /// A frame of the run is already free
+    DoubleFree,
+}
+
+/// A physical frame allocator supporting deallocation and contiguous runs
+///
+/// Single frames are served with a next-fit search over whole bitmap words,
+/// so the cost of an allocation does not grow with the number of frames
+/// already handed out.
+pub struct BitmapFrameAllocator {
+    regions: [FrameRegion; MAX_REGIONS],
+    region_count: usize,
+    bitmap: &'static mut [u64],
+}
+
+impl BitmapFrameAllocator {
+    /// Create a new empty allocator
+    ///
+    /// The allocator has no memory to hand out until `init` is called.
+    pub const fn new() -> Self {
+        BitmapFrameAllocator {
+            regions: [FrameRegion::empty(); MAX_REGIONS],
+            region_count: 0,
+            bitmap: &mut [],
+        }
+    }
+    /// Initialize the allocator from the bootloader's memory map
+    /// # Safety
+    /// This function is unsafe because the caller must guarantee that the physical memory offset
+    /// is correct, that the usable regions of the memory map are not used by anything else and
+    /// that the function is called only once.
+    pub unsafe fn init(&mut self, physical_memory_offset: VirtAddr) {
+        // Record every usable region and the position of its bitmap slice
+        let mut words = 0;
+        let usable_regions = MEMORY_REGIONS
+            .iter()
+            .filter(|r| r.entry_type == EntryType::USABLE);
+        for entry in usable_regions {
+            if self.region_count == MAX_REGIONS {
+                log::warn!("Too many usable regions, ignoring the rest");
+                break;
+            }
+            let start = align_up(entry.base, FRAME_SIZE);
+            let end = (entry.base + entry.length) & !(FRAME_SIZE - 1);
+            if end <= start {
+                continue;
+            }
+            let frames = ((end - start) / FRAME_SIZE) as usize;
+            self.regions[self.region_count] = FrameRegion {
+                start,
+                frames,
+                free: frames,
+                bitmap_start: words,
+                next_hint: 0,
+            };
+            words += frames.div_ceil(BITS_PER_WORD);
+            self.region_count += 1;
+        }
+        // Place the bitmap in the first region that can hold it
+        let bitmap_frames = ((words * core::mem::size_of::<u64>()) as u64).div_ceil(FRAME_SIZE);
+        let host = self.regions[..self.region_count]
+            .iter()
+            .position(|r| r.frames as u64 >= bitmap_frames)
+            .expect("[Frame Allocator]: No usable region can hold the frame bitmap");
+        let bitmap_ptr: *mut u64 = (physical_memory_offset + self.regions[host].start).as_mut_ptr();
+        self.bitmap = core::slice::from_raw_parts_mut(bitmap_ptr, words);
+        self.clear_bitmap();
+        // Reserve the frames holding the bitmap itself
+        self.mark_range(host, 0, bitmap_frames as usize, true);
+    }
+    /// Mark every frame free, and the bits past the end of every region as used
+    /// so they are never handed out
+    fn clear_bitmap(&mut self) {
+        self.bitmap.fill(0);
+        for region in self.regions[..self.region_count].iter() {
+            let tail = region.frames % BITS_PER_WORD;
+            if tail != 0 {
+                self.bitmap[region.bitmap_start + region.words() - 1] = !0 << tail;
+            }
+        }
+    }
+    /// Usable regions tracked by the allocator
+    pub fn regions(&self) -> &[FrameRegion] {
+        &self.regions[..self.region_count]
+    }
+    /// Total number of frames managed by the allocator
+    pub fn total_frames(&self) -> usize {
+        self.regions().iter().map(|r| r.frames).sum()
+    }
+    /// Number of frames that are currently free
+    pub fn free_frames(&self) -> usize {
+        self.regions().iter().map(|r| r.free).sum()
+    }
+    /// Allocate `count` physically contiguous frames
+    ///
+    /// The physical address of the first frame is aligned to `align` bytes,
+    /// which must be a power of two. Returns the first frame of the run.
+    pub fn allocate_contiguous(&mut self, count: usize, align: u64) -> Option<PhysFrame> {
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+        if count == 0 {
+            return None;
+        }
+        if count == 1 && align <= FRAME_SIZE {
+            return self.allocate_single();
+        }
+        let align = align.max(FRAME_SIZE);
+        for region in 0..self.region_count {
+            if let Some(index) = self.find_run(region, count, align) {
+                self.mark_range(region, index, count, true);
+                return Some(self.frame_at(region, index));
+            }
+        }
+        None
+    }
+    /// Free `count` contiguous frames starting at `start`
+    ///
+    /// Panics if a frame is outside the managed regions or already free,
+    /// since both mean that a caller freed memory it did not own.
+    pub fn deallocate_contiguous(&mut self, start: PhysFrame, count: usize) {
+        if let Err(error) = self.try_deallocate_contiguous(start, count) {
+            panic!("[Frame Allocator]: Failed to free {:?}: {:?}", start, error);
+        }
+    }
+    /// Free `count` contiguous frames starting at `start`
+    ///
+    /// The whole run is checked first, nothing is freed on error.
+    pub fn try_deallocate_contiguous(
+        &mut self,
+        start: PhysFrame,
+        count: usize,
+    ) -> Result<(), FrameError> {
+        let addr = start.start_address().as_u64();
+        let (region, index) = self.locate(addr).ok_or(FrameError::NotManaged)?;
+        if index + count > self.regions[region].frames {
+            return Err(FrameError::CrossesRegion);
+        }
+        if (index..index + count).any(|i| !self.is_used(region, i)) {
+            return Err(FrameError::DoubleFree);
+        }
+        self.mark_range(region, index, count, false);
+        // Let the next search start at the freed frames
+        let word = index / BITS_PER_WORD;
+        if word < self.regions[region].next_hint {
+            self.regions[region].next_hint = word;
+        }
+        Ok(())
+    }
+    /// Returns true if the given frame is currently allocated
+    pub fn is_allocated(&self, frame: PhysFrame) -> bool {
+        match self.locate(frame.start_address().as_u64()) {
+            Some((region, index)) => self.is_used(region, index),
+            None => false,
+        }
+    }
+    /// Allocate a single frame using a next-fit search over bitmap words
+    fn allocate_single(&mut self) -> Option<PhysFrame> {
+        for region in 0..self.region_count {
+            let info = self.regions[region];
+            if info.free == 0 {
+                continue;
+            }
+            let words = info.words();
+            for offset in 0..words {
+                let word_index = (info.next_hint + offset) % words;
+                let word = self.bitmap[info.bitmap_start + word_index];
+                if word != !0 {
+                    let index = word_index * BITS_PER_WORD + word.trailing_ones() as usize;
+                    self.mark_range(region, index, 1, true);
+                    self.regions[region].next_hint = word_index;
+                    return Some(self.frame_at(region, index));
+                }
+            }
+        }
+        None
+    }
+    /// Find `count` free frames in a region whose first frame is aligned to `align` bytes
+    fn find_run(&self, region: usize, count: usize, align: u64) -> Option<usize> {
+        let info = &self.regions[region];
+        if info.free < count {
+            return None;
+        }
+        let align_frames = (align / FRAME_SIZE) as usize;
+        // Index of the first frame in the region with an aligned physical address
+        let first = ((align_up(info.start, align) - info.start) / FRAME_SIZE) as usize;
+        let mut index = first;
+        while index + count <= info.frames {
+            match (index..index + count).rfind(|&i| self.is_used(region, i)) {
+                None => return Some(index),
+                // Skip to the first aligned candidate after the used frame
+                Some(used) => index = first + ((used - first) / align_frames + 1) * align_frames,
+            }
+        }
+        None
+    }
+    /// Find the region and frame index of a physical address
+    fn locate(&self, addr: u64) -> Option<(usize, usize)> {
+        self.regions()
+            .iter()
+            .enumerate()
+            .find_map(|(region, info)| info.frame_index(addr).map(|index| (region, index)))
+    }
+    /// Returns true if the frame at `index` in `region` is used
+    fn is_used(&self, region: usize, index: usize) -> bool {
+        let word = self.bitmap[self.regions[region].bitmap_start + index / BITS_PER_WORD];
+        word & (1 << (index % BITS_PER_WORD)) != 0
+    }
+    /// Mark `count` frames starting at `index` in `region` as used or free
+    fn mark_range(&mut self, region: usize, index: usize, count: usize, used: bool) {
+        let bitmap_start = self.regions[region].bitmap_start;
+        for i in index..index + count {
+            let word = &mut self.bitmap[bitmap_start + i / BITS_PER_WORD];
+            let bit = 1 << (i % BITS_PER_WORD);
+            if used {
+                assert!(*word & bit == 0, "[Frame Allocator]: Frame allocated twice");
+                *word |= bit;
+            } else {
+                assert!(*word & bit != 0, "[Frame Allocator]: Frame freed twice");
+                *word &= !bit;
+            }
+        }
+        if used {
+            self.regions[region].free -= count;
+        } else {
+            self.regions[region].free += count;
+        }
+    }
+    /// Returns the frame at `index` in `region`
+    fn frame_at(&self, region: usize, index: usize) -> PhysFrame {
+        let addr = self.regions[region].start + index as u64 * FRAME_SIZE;
+        PhysFrame::containing_address(PhysAddr::new(addr))
+    }
+}
+impl Default for BitmapFrameAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+/// Align the given address `addr` upwards to alignment `align`, a power of two.
+fn align_up(addr: u64, align: u64) -> u64 {
+    (addr + align - 1) & !(align - 1)
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        self.allocate_single()
+    }
+}
+unsafe impl FrameAllocator<Size2MiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        let count = (Size2MiB::SIZE / FRAME_SIZE) as usize;
+        let frame = self.allocate_contiguous(count, Size2MiB::SIZE)?;
+        PhysFrame::from_start_address(frame.start_address()).ok()
+    }
+}
+unsafe impl FrameAllocator<Size1GiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size1GiB>> {
+        let count = (Size1GiB::SIZE / FRAME_SIZE) as usize;
+        let frame = self.allocate_contiguous(count, Size1GiB::SIZE)?;
+        PhysFrame::from_start_address(frame.start_address()).ok()
+    }
+}
+impl<S: PageSize> FrameDeallocator<S> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<S>) {
+        let start = PhysFrame::containing_address(frame.start_address());
+        self.deallocate_contiguous(start, (S::SIZE / FRAME_SIZE) as usize);
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,118 @@
+use super::*;
+use alloc::{vec, vec::Vec};
+
+/// Physical address of the first fake region, the frames are never accessed
+const REGION_BASE: u64 = 0x100_0000;
+
+/// Allocator over fake regions of `sizes` frames, separated by holes
+fn allocator(sizes: &[usize]) -> BitmapFrameAllocator {
+    let mut allocator = BitmapFrameAllocator::default();
+    let mut start = REGION_BASE;
+    let mut words = 0;
+    for (region, &frames) in sizes.iter().enumerate() {
+        allocator.regions[region] = FrameRegion {
+            start,
+            frames,
+            free: frames,
+            bitmap_start: words,
+            next_hint: 0,
+        };
+        words += frames.div_ceil(BITS_PER_WORD);
+        start = align_up(start + (frames as u64 + 1) * FRAME_SIZE, 0x10_0000);
+    }
+    allocator.region_count = sizes.len();
+    allocator.bitmap = Vec::leak(vec![0; words]);
+    allocator.clear_bitmap();
+    allocator
+}
+/// Allocate a single 4 KiB frame
+fn allocate(allocator: &mut BitmapFrameAllocator) -> Option<PhysFrame> {
+    FrameAllocator::<Size4KiB>::allocate_frame(allocator)
+}
+/// Index of `frame` in the first fake region
+fn index(frame: PhysFrame) -> u64 {
+    (frame.start_address().as_u64() - REGION_BASE) / FRAME_SIZE
+}
+
+#[test_case]
+fn test_allocate_and_free() {
+    let mut allocator = allocator(&[128]);
+    let frame = allocate(&mut allocator).unwrap();
+    assert!(allocator.is_allocated(frame));
+    assert_eq!(allocator.free_frames(), 127);
+    unsafe { allocator.deallocate_frame(frame) };
+    assert!(!allocator.is_allocated(frame));
+    assert_eq!(allocator.free_frames(), 128);
+}
+#[test_case]
+fn test_double_free() {
+    let mut allocator = allocator(&[128]);
+    let frame = allocator.allocate_contiguous(4, FRAME_SIZE).unwrap();
+    assert_eq!(allocator.try_deallocate_contiguous(frame, 4), Ok(()));
+    assert_eq!(
+        allocator.try_deallocate_contiguous(frame, 1),
+        Err(FrameError::DoubleFree)
+    );
+    // A run that is partly free is left untouched
+    let frame = allocator.allocate_contiguous(2, FRAME_SIZE).unwrap();
+    assert_eq!(
+        allocator.try_deallocate_contiguous(frame, 3),
+        Err(FrameError::DoubleFree)
+    );
+    assert!(allocator.is_allocated(frame));
+    assert_eq!(allocator.free_frames(), 126);
+    let outside = PhysFrame::containing_address(PhysAddr::new(0));
+    assert_eq!(
+        allocator.try_deallocate_contiguous(outside, 1),
+        Err(FrameError::NotManaged)
+    );
+    assert_eq!(
+        allocator.try_deallocate_contiguous(frame, 200),
+        Err(FrameError::CrossesRegion)
+    );
+}
+#[test_case]
+fn test_exhaustion() {
+    // Not a multiple of 64, the bits past the end must never be handed out
+    let mut allocator = allocator(&[100]);
+    let frames: Vec<_> = (0..100)
+        .map(|_| allocate(&mut allocator).unwrap())
+        .collect();
+    assert!(frames.iter().all(|&frame| index(frame) < 100));
+    assert_eq!(allocator.free_frames(), 0);
+    assert_eq!(allocate(&mut allocator), None);
+    assert_eq!(allocator.allocate_contiguous(2, FRAME_SIZE), None);
+    unsafe { allocator.deallocate_frame(frames[42]) };
+    assert_eq!(allocate(&mut allocator), Some(frames[42]));
+}
+#[test_case]
+fn test_contiguous_across_words() {
+    let mut allocator = allocator(&[256]);
+    for _ in 0..60 {
+        allocate(&mut allocator).unwrap();
+    }
+    // Frames 60 to 69 straddle the first and second bitmap words
+    let run = allocator.allocate_contiguous(10, FRAME_SIZE).unwrap();
+    assert_eq!(index(run), 60);
+    // The next run aligned to 8 frames starts after the used ones
+    let aligned = allocator.allocate_contiguous(8, 8 * FRAME_SIZE).unwrap();
+    assert_eq!(index(aligned), 72);
+    // A run spanning whole words
+    let long = allocator.allocate_contiguous(130, FRAME_SIZE).unwrap();
+    assert_eq!(index(long), 80);
+    assert_eq!(allocator.allocate_contiguous(50, FRAME_SIZE), None);
+    allocator.deallocate_contiguous(run, 10);
+    assert_eq!(allocator.allocate_contiguous(10, FRAME_SIZE), Some(run));
+}
+#[test_case]
+fn test_contiguous_does_not_cross_regions() {
+    let mut allocator = allocator(&[64, 64]);
+    let first = allocator.allocate_contiguous(40, FRAME_SIZE).unwrap();
+    // Only the second region has 40 free frames left
+    let second = allocator.allocate_contiguous(40, FRAME_SIZE).unwrap();
+    assert_eq!(index(first), 0);
+    assert_eq!(
+        second.start_address(),
+        allocator.regions()[1].start_address()
+    );
+}
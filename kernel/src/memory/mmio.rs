@@ -0,0 +1,99 @@
+//! Mapping of physical device memory and firmware tables
+//!
+//! `ioremap` maps MMIO registers uncached, `memremap` maps normal memory such as
+//! ACPI tables cached. Both return an `MmioRegion` handle that unmaps the range
+//! when dropped.
+use super::vmm::{with_kernel_space, RegionKind, VmmError};
+use core::{mem, ptr};
+use x86_64::{structures::paging::PageTableFlags, PhysAddr, VirtAddr};
+
+/// A mapped range of physical memory accessed through bounds checked reads and writes
+#[derive(Debug)]
+pub struct MmioRegion {
+    base: VirtAddr,
+    phys: PhysAddr,
+    size: u64,
+}
+
+/// Map the physical MMIO range `[phys, phys + size)` uncached into kernel space
+pub fn ioremap(phys: PhysAddr, size: u64) -> Result<MmioRegion, VmmError> {
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::NO_CACHE
+        | PageTableFlags::WRITE_THROUGH
+        | PageTableFlags::NO_EXECUTE;
+    map(phys, size, flags)
+}
+/// Map the physical range `[phys, phys + size)` of normal memory read only into kernel space
+///
+/// Used for firmware tables, which may be cached.
+pub fn memremap(phys: PhysAddr, size: u64) -> Result<MmioRegion, VmmError> {
+    let flags = PageTableFlags::PRESENT | PageTableFlags::NO_EXECUTE;
+    map(phys, size, flags)
+}
+/// Map a physical range with the given flags
+fn map(phys: PhysAddr, size: u64, flags: PageTableFlags) -> Result<MmioRegion, VmmError> {
+    let base =
+        with_kernel_space(|space| space.map_physical("mmio", RegionKind::Mmio, phys, size, flags))?;
+    Ok(MmioRegion { base, phys, size })
+}
+
+impl MmioRegion {
+    /// Virtual address of the start of the region
+    pub fn base(&self) -> VirtAddr {
+        self.base
+    }
+    /// Physical address of the start of the region
+    pub fn phys(&self) -> PhysAddr {
+        self.phys
+    }
+    /// Size of the region in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+    /// Volatile read of a register at `offset` bytes from the start of the region
+    pub fn read<T: Copy>(&self, offset: u64) -> T {
+        let ptr = self.ptr::<T>(offset);
+        assert!(ptr.is_aligned(), "[MMIO]: Unaligned register access");
+        unsafe { ptr::read_volatile(ptr) }
+    }
+    /// Volatile write of a register at `offset` bytes from the start of the region
+    pub fn write<T: Copy>(&self, offset: u64, value: T) {
+        let ptr = self.ptr::<T>(offset) as *mut T;
+        assert!(ptr.is_aligned(), "[MMIO]: Unaligned register access");
+        unsafe { ptr::write_volatile(ptr, value) }
+    }
+    /// Read a possibly unaligned value at `offset` bytes from the start of the region
+    ///
+    /// Meant for packed firmware tables, not for device registers.
+    pub fn read_unaligned<T: Copy>(&self, offset: u64) -> T {
+        unsafe { ptr::read_unaligned(self.ptr::<T>(offset)) }
+    }
+    /// The whole region as a byte slice
+    ///
+    /// Meant for firmware tables mapped with `memremap`, device registers must
+    /// go through `read` and `write`.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.base.as_ptr(), self.size as usize) }
+    }
+    /// Pointer to a value of type `T` at `offset`, panics if it does not fit in the region
+    fn ptr<T>(&self, offset: u64) -> *const T {
+        let end = offset.checked_add(mem::size_of::<T>() as u64);
+        assert!(
+            end.is_some_and(|end| end <= self.size),
+            "[MMIO]: Access at offset {:#x} outside of a {:#x} byte region",
+            offset,
+            self.size
+        );
+        (self.base + offset).as_ptr()
+    }
+}
+
+impl Drop for MmioRegion {
+    fn drop(&mut self) {
+        let page_start = self.base.align_down(4096u64);
+        if let Err(error) = with_kernel_space(|space| space.unmap(page_start)) {
+            log::warn!("Failed to unmap {:?}: {:?}", self.base, error);
+        }
+    }
+}
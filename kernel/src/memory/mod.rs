@@ -1,12 +1,18 @@
 //! Memory management module
-use limine::memory_map::{Entry, EntryType};
+use limine::memory_map::Entry;
 use limine::request::MemoryMapRequest;
+use spin::Mutex;
 use x86_64::{
     structures::paging::{
         FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PhysFrame, Size4KiB,
     },
     PhysAddr, VirtAddr,
 };
+
+pub mod frame_allocator;
+pub mod mmio;
+pub mod vmm;
+pub use frame_allocator::{BitmapFrameAllocator, FrameError, FrameRegion};
 #[used]
 #[link_section = ".requests"]
 static MEMORY_MAP_REQUEST: MemoryMapRequest = MemoryMapRequest::new();
@@ -15,6 +21,12 @@ lazy_static! {
     static ref MEMORY_REGIONS: &'static [&'static Entry] =
         MEMORY_MAP_REQUEST.get_response().unwrap().entries();
 }
+/// The global physical frame allocator
+///
+/// Must be initialized with `BitmapFrameAllocator::init` before use. Only lock
+/// it with interrupts disabled: the heap grows with them disabled and would
+/// spin forever on a lock held by a preempted thread.
+pub static FRAME_ALLOCATOR: Mutex<BitmapFrameAllocator> = Mutex::new(BitmapFrameAllocator::new());
 /// Initialize a new OffsetPageTable
 pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
     let level_4_table = active_level_4_table(physical_memory_offset);
@@ -37,76 +49,6 @@ pub unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static
     // return a mutable reference to it
     &mut *page_table_ptr
 }
-/// A FrameAllocator that returns usable frames from the bootloader's memory map.
-pub struct BootInfoFrameAllocator {
-    // memory_map: &'static [&'static Entry],
-    next: usize,
-}
-impl BootInfoFrameAllocator {
-    // TO DO : understand the return type and the function
-    /// Returns an iterator over the usable frames specified in the memory map.
-    fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
-        // get usable regions from memory map
-        let regions = MEMORY_REGIONS.iter();
-        // let temp = regions.clone();
-        // for region in temp.into_iter() {
-        //     let tp = match region.entry_type {
-        //         EntryType::USABLE => "USABLE",
-        //         EntryType::RESERVED => "RESERVED",
-        //         EntryType::ACPI_RECLAIMABLE => "ACPI_RECLAIMABLE",
-        //         EntryType::ACPI_NVS => "ACPI_NVS",
-        //         EntryType::BAD_MEMORY => "BAD",
-        //         EntryType::BOOTLOADER_RECLAIMABLE => "BOOTLOADER_RECLAIMABLE",
-        //         EntryType::KERNEL_AND_MODULES => "KERNEL_AND_MODULES",
-        //         EntryType::FRAMEBUFFER => "FRAMEBUFFER",
-        //         _ => "UNKNOWN",
-        //     };
-        //     serial_println!(
-        //         "region: {:x} entry type: {:?} len: {:?}",
-        //         region.base,
-        //         tp,
-        //         region.length
-        //     );
-        // }
-
-        let usable_regions = regions.filter(|r| r.entry_type == EntryType::USABLE);
-
-        // map each region to its address range
-        let addr_ranges = usable_regions.map(|r| r.base..(r.base + r.length));
-        // serial_println!(
-        //     "addr_ranges: {:?}",
-        //     addr_ranges.map(|x| x.start + " " + x.end)
-        // );
-        // transform to an iterator of frame start addresses
-        let frame_addresses = addr_ranges.flat_map(|r| r.step_by(4096));
-        // serial_println!(
-        //     "frame_addresses: {:?}",
-        //     frame_addresses.map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
-        // );
-        // create `PhysFrame` types from the start addresses
-        frame_addresses.map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
-    }
-    /// Create a new FrameAllocator from the passed memory map.
-    pub unsafe fn init() -> Self {
-        BootInfoFrameAllocator {
-            // memory_map: MEMORY_REGIONS.,
-            next: 0,
-        }
-    }
-}
-/// Implement the FrameAllocator trait for BootInfoFrameAllocator
-///
-/// This implementation is unsafe because the caller must guarantee that the allocator is used correctly.
-/// Otherwise, undefined behavior can happen.
-unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
-    fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        let frame = self.usable_frames().nth(self.next);
-        self.next += 1;
-        frame
-    }
-}
-
-// TO DO : This implementation is not quite optimal since it recreates the usable_frame allocator on every allocation. It would be better to directly store the iterator as a struct field instead. Then we wouldn’t need the nth method and could just call next on every allocation. The problem with this approach is that it’s not possible to store an impl Trait type in a struct field currently. It might work someday when named existential types are fully implemented. (https://github.com/rust-lang/rfcs/pull/2071)
 /// Create an example mapping for the given page to frame 0xb8000.
 pub fn create_example_mapping(
     page: Page,
@@ -0,0 +1,540 @@
+//! Address spaces and the virtual regions they are made of
+use super::{range_allocator::VirtualRangeAllocator, VmmError};
+use crate::memory::FRAME_ALLOCATOR;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::{
+    structures::paging::{
+        FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags,
+        PhysFrame, Size4KiB, Translate,
+    },
+    PhysAddr, VirtAddr,
+};
+/// Size of a page in bytes
+const PAGE_SIZE: u64 = 4096;
+/// First level 4 entry of the kernel (higher) half
+const KERNEL_HALF_START: usize = 256;
+
+/// What a virtual region is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Heap,
+    Stack,
+    Mmio,
+    Framebuffer,
+    Other,
+}
+/// Where the memory behind a virtual region comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backing {
+    /// Frames are allocated by the VMM and returned on unmap
+    Anonymous,
+    /// A fixed physical range that is not owned by the VMM
+    Physical(PhysAddr),
+    /// Only the virtual range is tracked, the pages are managed by their owner
+    Reserved,
+}
+
+/// A named range of virtual memory inside an address space
+#[derive(Debug, Clone, Copy)]
+pub struct VirtualRegion {
+    name: &'static str,
+    kind: RegionKind,
+    start: VirtAddr,
+    size: u64,
+    guard: u64, // Unmapped bytes kept free right below the region
+    flags: PageTableFlags,
+    backing: Backing,
+}
+impl VirtualRegion {
+    /// Create a region description for an existing mapping
+    pub fn new(
+        name: &'static str,
+        kind: RegionKind,
+        start: VirtAddr,
+        size: u64,
+        flags: PageTableFlags,
+        backing: Backing,
+    ) -> Self {
+        VirtualRegion {
+            name,
+            kind,
+            start,
+            size,
+            guard: 0,
+            flags,
+            backing,
+        }
+    }
+    /// Name of the region
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+    /// What the region is used for
+    pub fn kind(&self) -> RegionKind {
+        self.kind
+    }
+    /// First address of the region
+    pub fn start(&self) -> VirtAddr {
+        self.start
+    }
+    /// Size of the region in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+    /// Address right after the end of the region
+    pub fn end(&self) -> VirtAddr {
+        self.start + self.size
+    }
+    /// Page table flags used for the region
+    pub fn flags(&self) -> PageTableFlags {
+        self.flags
+    }
+    /// Where the memory behind the region comes from
+    pub fn backing(&self) -> Backing {
+        self.backing
+    }
+    /// Returns true if `addr` lies in the region
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        addr >= self.start && addr < self.end()
+    }
+    /// First address reserved by the region, including its guard
+    fn reserved_start(&self) -> u64 {
+        self.start.as_u64() - self.guard
+    }
+    /// Pages covered by the region
+    fn pages(&self) -> impl Iterator<Item = Page> {
+        let first = Page::containing_address(self.start);
+        let last = Page::containing_address(self.start + (self.size - 1));
+        Page::range_inclusive(first, last)
+    }
+}
+
+/// A set of page tables together with the regions mapped through them
+pub struct AddressSpace {
+    level_4_frame: PhysFrame,
+    physical_memory_offset: VirtAddr,
+    regions: BTreeMap<u64, VirtualRegion>,
+    ranges: VirtualRangeAllocator,
+    /// The page tables were allocated for this address space and are freed with it
+    owns_tables: bool,
+    /// Lower half level 4 entries pointing to tables of another address space
+    shared_entries: Vec<usize>,
+}
+
+impl AddressSpace {
+    /// Wrap the currently active page tables
+    ///
+    /// Dynamic allocations are served from the virtual window `[window_start, window_start + window_size)`.
+    /// # Safety
+    /// This function is unsafe because the caller must guarantee that the physical memory offset is
+    /// correct and that only one `AddressSpace` is created for the active page tables.
+    pub unsafe fn active(
+        physical_memory_offset: VirtAddr,
+        window_start: VirtAddr,
+        window_size: u64,
+    ) -> Self {
+        use x86_64::registers::control::Cr3;
+        let (level_4_frame, _) = Cr3::read();
+        AddressSpace {
+            level_4_frame,
+            physical_memory_offset,
+            regions: BTreeMap::new(),
+            ranges: VirtualRangeAllocator::new(window_start, window_size),
+            owns_tables: false,
+            shared_entries: Vec::new(),
+        }
+    }
+    /// Create a fresh address space that shares the kernel mappings of `self`
+    ///
+    /// The kernel half and the listed lower half level 4 entries (e.g. the heap) are shared,
+    /// everything else starts out unmapped.
+    pub fn new_from_kernel(
+        &self,
+        shared_entries: &[usize],
+        window_start: VirtAddr,
+        window_size: u64,
+    ) -> Result<Self, VmmError> {
+        let level_4_frame = allocate_zeroed_table(self.physical_memory_offset)?;
+        let source = unsafe { self.table(self.level_4_frame) };
+        let table = unsafe { self.table(level_4_frame) };
+        for index in (KERNEL_HALF_START..512).chain(shared_entries.iter().copied()) {
+            table[index] = source[index].clone();
+        }
+        Ok(AddressSpace {
+            level_4_frame,
+            physical_memory_offset: self.physical_memory_offset,
+            regions: BTreeMap::new(),
+            ranges: VirtualRangeAllocator::new(window_start, window_size),
+            owns_tables: true,
+            shared_entries: shared_entries.to_vec(),
+        })
+    }
+    /// Allocate a level 3 table for every empty kernel half entry
+    ///
+    /// Address spaces created later copy the kernel half, so the entries have to exist
+    /// up front for new kernel mappings to be visible everywhere.
+    pub fn populate_kernel_half(&mut self) -> Result<(), VmmError> {
+        let table = unsafe { self.table(self.level_4_frame) };
+        for entry in table.iter_mut().skip(KERNEL_HALF_START) {
+            if entry.is_unused() {
+                let frame = allocate_zeroed_table(self.physical_memory_offset)?;
+                entry.set_frame(frame, PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+            }
+        }
+        Ok(())
+    }
+    /// Frame holding the level 4 table of the address space
+    pub fn level_4_frame(&self) -> PhysFrame {
+        self.level_4_frame
+    }
+    /// Regions of the address space ordered by start address
+    pub fn regions(&self) -> impl Iterator<Item = &VirtualRegion> {
+        self.regions.values()
+    }
+    /// Find the region containing `addr`
+    pub fn find_region(&self, addr: VirtAddr) -> Option<&VirtualRegion> {
+        self.regions
+            .range(..=addr.as_u64())
+            .next_back()
+            .map(|(_, region)| region)
+            .filter(|region| region.contains(addr))
+    }
+    /// Record a mapping that was set up outside of the VMM
+    pub fn track(&mut self, region: VirtualRegion) -> Result<(), VmmError> {
+        self.check_free(region.reserved_start(), region.end().as_u64())?;
+        if self.ranges.contains(region.start) && !self.ranges.reserve(region.start, region.size) {
+            return Err(VmmError::Overlap);
+        }
+        self.regions.insert(region.start.as_u64(), region);
+        Ok(())
+    }
+    /// Map `size` bytes of fresh memory at the fixed address `start`
+    pub fn map(
+        &mut self,
+        name: &'static str,
+        kind: RegionKind,
+        start: VirtAddr,
+        size: u64,
+        flags: PageTableFlags,
+    ) -> Result<(), VmmError> {
+        if !start.is_aligned(PAGE_SIZE) || size == 0 {
+            return Err(VmmError::InvalidRange);
+        }
+        let region = VirtualRegion::new(name, kind, start, size, flags, Backing::Anonymous);
+        self.check_free(start.as_u64(), region.end().as_u64())?;
+        if self.ranges.contains(start) && !self.ranges.reserve(start, size) {
+            return Err(VmmError::Overlap);
+        }
+        if let Err(error) = self.map_region(&region) {
+            if self.ranges.contains(start) {
+                self.ranges.deallocate(start, size);
+            }
+            return Err(error);
+        }
+        self.regions.insert(start.as_u64(), region);
+        Ok(())
+    }
+    /// Back `[start, start + size)` of a reserved region with fresh memory
+    ///
+    /// For owners of reserved regions that map their pages on demand, like the heap.
+    /// Does not allocate from the heap.
+    pub fn map_reserved(&mut self, start: VirtAddr, size: u64) -> Result<(), VmmError> {
+        if !start.is_aligned(PAGE_SIZE) || size == 0 {
+            return Err(VmmError::InvalidRange);
+        }
+        let reserved = *self.find_region(start).ok_or(VmmError::RegionNotFound)?;
+        if reserved.backing != Backing::Reserved || size > reserved.end() - start {
+            return Err(VmmError::InvalidRange);
+        }
+        let pages = VirtualRegion::new(
+            reserved.name,
+            reserved.kind,
+            start,
+            size,
+            reserved.flags,
+            Backing::Anonymous,
+        );
+        self.map_region(&pages)
+    }
+    /// Map `size` bytes of fresh memory at an address picked by the VMM
+    ///
+    /// `guard` bytes below the region are left unmapped so that running
+    /// off the start of the region faults instead of corrupting memory.
+    pub fn allocate(
+        &mut self,
+        name: &'static str,
+        kind: RegionKind,
+        size: u64,
+        guard: u64,
+        flags: PageTableFlags,
+    ) -> Result<VirtAddr, VmmError> {
+        let region = VirtualRegion::new(
+            name,
+            kind,
+            VirtAddr::zero(),
+            size,
+            flags,
+            Backing::Anonymous,
+        );
+        self.allocate_region(region, guard)
+    }
+    /// Map the physical range `[phys, phys + size)` at an address picked by the VMM
+    ///
+    /// Returns the virtual address corresponding to `phys`, which does not have to be page aligned.
+    pub fn map_physical(
+        &mut self,
+        name: &'static str,
+        kind: RegionKind,
+        phys: PhysAddr,
+        size: u64,
+        flags: PageTableFlags,
+    ) -> Result<VirtAddr, VmmError> {
+        let phys_start = phys.align_down(PAGE_SIZE);
+        let offset = phys - phys_start;
+        let size = offset + size;
+        let region = VirtualRegion::new(
+            name,
+            kind,
+            VirtAddr::zero(),
+            size,
+            flags,
+            Backing::Physical(phys_start),
+        );
+        Ok(self.allocate_region(region, 0)? + offset)
+    }
+    /// Remove the region starting at `start` and unmap its pages
+    ///
+    /// Frames of anonymous regions are returned to the frame allocator.
+    pub fn unmap(&mut self, start: VirtAddr) -> Result<VirtualRegion, VmmError> {
+        let region = self
+            .regions
+            .remove(&start.as_u64())
+            .ok_or(VmmError::RegionNotFound)?;
+        if region.backing != Backing::Reserved {
+            self.unmap_pages(&region, region.pages().count());
+        }
+        let reserved_start = VirtAddr::new(region.reserved_start());
+        if self.ranges.contains(reserved_start) {
+            self.ranges
+                .deallocate(reserved_start, region.size + region.guard);
+        }
+        Ok(region)
+    }
+    /// Change the page table flags of the region starting at `start`
+    ///
+    /// Every page is checked first, nothing is changed if one is not mapped.
+    pub fn protect(&mut self, start: VirtAddr, flags: PageTableFlags) -> Result<(), VmmError> {
+        let region = *self
+            .regions
+            .get(&start.as_u64())
+            .ok_or(VmmError::RegionNotFound)?;
+        if region.backing != Backing::Reserved {
+            let mut mapper = self.mapper();
+            if region
+                .pages()
+                .any(|page| mapper.translate_page(page).is_err())
+            {
+                return Err(VmmError::NotMapped);
+            }
+            for page in region.pages() {
+                let flush =
+                    unsafe { mapper.update_flags(page, flags) }.map_err(|_| VmmError::NotMapped)?;
+                flush.flush();
+            }
+        }
+        if let Some(region) = self.regions.get_mut(&start.as_u64()) {
+            region.flags = flags;
+        }
+        Ok(())
+    }
+    /// Translate a virtual address to the physical address it is mapped to
+    pub fn translate(&mut self, addr: VirtAddr) -> Option<PhysAddr> {
+        self.mapper().translate_addr(addr)
+    }
+    /// Make this address space the active one
+    /// # Safety
+    /// This function is unsafe because the caller must guarantee that the address space maps
+    /// the code, stack and data currently in use.
+    pub unsafe fn switch_to(&self) {
+        use x86_64::registers::control::Cr3;
+        let (_, flags) = Cr3::read();
+        Cr3::write(self.level_4_frame, flags);
+    }
+    /// Pick a virtual range for `region`, map it and record it
+    fn allocate_region(
+        &mut self,
+        mut region: VirtualRegion,
+        guard: u64,
+    ) -> Result<VirtAddr, VmmError> {
+        if region.size == 0 {
+            return Err(VmmError::InvalidRange);
+        }
+        let guard = guard.next_multiple_of(PAGE_SIZE);
+        let size = region.size.next_multiple_of(PAGE_SIZE);
+        let reserved_start = self
+            .ranges
+            .allocate(size + guard, PAGE_SIZE)
+            .ok_or(VmmError::OutOfVirtualSpace)?;
+        region.start = reserved_start + guard;
+        region.guard = guard;
+        if let Err(error) = self.map_region(&region) {
+            self.ranges.deallocate(reserved_start, size + guard);
+            return Err(error);
+        }
+        self.regions.insert(region.start.as_u64(), region);
+        Ok(region.start)
+    }
+    /// Map every page of `region`, undoing the work done so far on failure
+    fn map_region(&mut self, region: &VirtualRegion) -> Result<(), VmmError> {
+        let mut mapped = 0;
+        let mut result = Ok(());
+        without_interrupts(|| {
+            let mut mapper = self.mapper();
+            let mut frame_allocator = FRAME_ALLOCATOR.lock();
+            for (index, page) in region.pages().enumerate() {
+                let frame = match region.backing {
+                    Backing::Anonymous => match frame_allocator.allocate_frame() {
+                        Some(frame) => frame,
+                        None => {
+                            result = Err(VmmError::FrameAllocationFailed);
+                            break;
+                        }
+                    },
+                    Backing::Physical(phys) => {
+                        PhysFrame::containing_address(phys + index as u64 * PAGE_SIZE)
+                    }
+                    Backing::Reserved => break,
+                };
+                match unsafe { mapper.map_to(page, frame, region.flags, &mut *frame_allocator) } {
+                    Ok(flush) => flush.flush(),
+                    Err(_) => {
+                        if region.backing == Backing::Anonymous {
+                            unsafe { frame_allocator.deallocate_frame(frame) };
+                        }
+                        result = Err(VmmError::MapFailed);
+                        break;
+                    }
+                }
+                mapped += 1;
+            }
+        });
+        if result.is_err() {
+            self.unmap_pages(region, mapped);
+        }
+        result
+    }
+    /// Unmap the first `count` pages of `region`
+    fn unmap_pages(&mut self, region: &VirtualRegion, count: usize) {
+        let mut mapper = self.mapper();
+        without_interrupts(|| {
+            let mut frame_allocator = FRAME_ALLOCATOR.lock();
+            for page in region.pages().take(count) {
+                if let Ok((frame, flush)) = mapper.unmap(page) {
+                    flush.flush();
+                    if region.backing == Backing::Anonymous {
+                        unsafe { frame_allocator.deallocate_frame(frame) };
+                    }
+                }
+            }
+        });
+    }
+    /// Check that no tracked region overlaps `[start, end)`
+    fn check_free(&self, start: u64, end: u64) -> Result<(), VmmError> {
+        let overlaps = self
+            .regions
+            .range(..end)
+            .next_back()
+            .is_some_and(|(_, region)| region.end().as_u64() > start);
+        let next_guard = self
+            .regions
+            .range(start..)
+            .next()
+            .is_some_and(|(_, region)| region.reserved_start() < end);
+        if overlaps || next_guard {
+            Err(VmmError::Overlap)
+        } else {
+            Ok(())
+        }
+    }
+    /// Mapper over the page tables of the address space
+    fn mapper(&mut self) -> OffsetPageTable<'_> {
+        let table = unsafe { self.table(self.level_4_frame) };
+        unsafe { OffsetPageTable::new(table, self.physical_memory_offset) }
+    }
+    /// Free the lower half tables below `table`, `level` is the level of `table`
+    fn free_tables(
+        &self,
+        table: PhysFrame,
+        level: u8,
+        frame_allocator: &mut impl FrameDeallocator<Size4KiB>,
+    ) {
+        let entries = unsafe { self.table(table) };
+        for entry in entries.iter() {
+            let flags = entry.flags();
+            if level > 1
+                && flags.contains(PageTableFlags::PRESENT)
+                && !flags.contains(PageTableFlags::HUGE_PAGE)
+            {
+                self.free_tables(
+                    PhysFrame::containing_address(entry.addr()),
+                    level - 1,
+                    frame_allocator,
+                );
+            }
+        }
+        unsafe { frame_allocator.deallocate_frame(table) };
+    }
+    /// Access the page table stored in `frame`
+    /// # Safety
+    /// The caller must guarantee that `frame` holds a page table and that no other
+    /// reference to it is alive.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn table(&self, frame: PhysFrame) -> &'static mut PageTable {
+        let virt = self.physical_memory_offset + frame.start_address().as_u64();
+        &mut *virt.as_mut_ptr()
+    }
+}
+impl Drop for AddressSpace {
+    /// Unmap every region and free the lower half tables that are not shared
+    fn drop(&mut self) {
+        use x86_64::registers::control::Cr3;
+        if !self.owns_tables {
+            return;
+        }
+        assert_ne!(
+            Cr3::read().0,
+            self.level_4_frame,
+            "dropping the active address space"
+        );
+        let starts: Vec<u64> = self.regions.keys().copied().collect();
+        for start in starts {
+            let _ = self.unmap(VirtAddr::new(start));
+        }
+        let level_4 = unsafe { self.table(self.level_4_frame) };
+        without_interrupts(|| {
+            let mut frame_allocator = FRAME_ALLOCATOR.lock();
+            for (index, entry) in level_4.iter().enumerate().take(KERNEL_HALF_START) {
+                if entry.flags().contains(PageTableFlags::PRESENT)
+                    && !self.shared_entries.contains(&index)
+                {
+                    self.free_tables(
+                        PhysFrame::containing_address(entry.addr()),
+                        3,
+                        &mut *frame_allocator,
+                    );
+                }
+            }
+            unsafe { frame_allocator.deallocate_frame(self.level_4_frame) };
+        });
+    }
+}
+/// Allocate a frame and zero it so it can be used as a page table
+fn allocate_zeroed_table(physical_memory_offset: VirtAddr) -> Result<PhysFrame, VmmError> {
+    let frame: PhysFrame<Size4KiB> = without_interrupts(|| FRAME_ALLOCATOR.lock().allocate_frame())
+        .ok_or(VmmError::FrameAllocationFailed)?;
+    let virt = physical_memory_offset + frame.start_address().as_u64();
+    unsafe { (*virt.as_mut_ptr::<PageTable>()).zero() };
+    Ok(frame)
+}
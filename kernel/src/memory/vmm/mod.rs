@@ -0,0 +1,126 @@
+//! Virtual memory manager
+//!
+//! Owns the kernel page tables through the `KERNEL_ADDRESS_SPACE` global and keeps
+//! track of what every mapped range of virtual memory is used for.
+use crate::allocator::{heap_stats, HEAP_START};
+use crate::drivers::framebuffer::FRAMEBUFFER;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::{structures::paging::PageTableFlags, VirtAddr};
+
+pub mod address_space;
+pub mod range_allocator;
+
+pub use address_space::{AddressSpace, Backing, RegionKind, VirtualRegion};
+pub use range_allocator::VirtualRangeAllocator;
+
+/// Start of the kernel window used for dynamic mappings (stacks, MMIO, ...)
+pub const KERNEL_VMAP_START: u64 = 0xFFFF_D000_0000_0000;
+/// Size of the kernel window used for dynamic mappings
+pub const KERNEL_VMAP_SIZE: u64 = 0x100_0000_0000; // 1 TiB
+/// Start of the window used for dynamic mappings of new address spaces
+pub const USER_VMAP_START: u64 = 0x0000_0000_0040_0000;
+/// Size of the window used for dynamic mappings of new address spaces
+///
+/// Ends below the level 4 entry holding the kernel heap.
+pub const USER_VMAP_SIZE: u64 = 0x0000_4000_0000_0000 - USER_VMAP_START;
+
+/// Virtual memory manager error type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmmError {
+    Overlap,
+    InvalidRange,
+    RegionNotFound,
+    NotMapped,
+    OutOfVirtualSpace,
+    FrameAllocationFailed,
+    MapFailed,
+    /// The address space is locked, possibly by the caller itself
+    Busy,
+}
+
+lazy_static! {
+    /// The address space of the kernel, wrapping the page tables set up by the bootloader
+    pub static ref KERNEL_ADDRESS_SPACE: Mutex<AddressSpace> = {
+        let phys_base = crate::HHDM_REQUEST.get_response().unwrap().offset();
+        Mutex::new(unsafe {
+            AddressSpace::active(
+                VirtAddr::new(phys_base),
+                VirtAddr::new(KERNEL_VMAP_START),
+                KERNEL_VMAP_SIZE,
+            )
+        })
+    };
+}
+/// Set once `init` tracked the heap, creating the kernel address space allocates
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// Run `f` on the kernel address space
+///
+/// Interrupts stay disabled while the lock is held, so the heap only finds it
+/// locked when the holder itself allocates, see `map_heap`.
+pub fn with_kernel_space<T>(f: impl FnOnce(&mut AddressSpace) -> T) -> T {
+    without_interrupts(|| f(&mut KERNEL_ADDRESS_SPACE.lock()))
+}
+/// Map fresh memory over `[start, start + size)` of the heap region
+///
+/// Called by the allocator with its lock held, the VMM may allocate while
+/// holding its own lock so it is only tried. The heap grows ahead of time
+/// to keep those allocations from running dry.
+pub fn map_heap(start: VirtAddr, size: u64) -> Result<(), VmmError> {
+    if !INITIALIZED.load(Ordering::Acquire) {
+        return Err(VmmError::RegionNotFound);
+    }
+    let mut space = KERNEL_ADDRESS_SPACE.try_lock().ok_or(VmmError::Busy)?;
+    space.map_reserved(start, size)
+}
+/// Initialize the virtual memory manager
+///
+/// Must be called after the heap is initialized.
+pub fn init() {
+    with_kernel_space(init_kernel_space);
+    INITIALIZED.store(true, Ordering::Release);
+}
+fn init_kernel_space(space: &mut AddressSpace) {
+    space
+        .populate_kernel_half()
+        .expect("[VMM]: Failed to allocate the kernel page tables");
+    // The heap grows through `map_heap`, only its range is reserved here
+    let heap = VirtualRegion::new(
+        "heap",
+        RegionKind::Heap,
+        VirtAddr::new(HEAP_START as u64),
+        heap_stats().limit as u64,
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+        Backing::Reserved,
+    );
+    space.track(heap).expect("[VMM]: Failed to track the heap");
+    // The framebuffer is mapped by the bootloader
+    let framebuffer = VirtualRegion::new(
+        "framebuffer",
+        RegionKind::Framebuffer,
+        VirtAddr::from_ptr(FRAMEBUFFER.get_address()),
+        FRAMEBUFFER.get_pitch() * FRAMEBUFFER.get_height(),
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+        Backing::Reserved,
+    );
+    space
+        .track(framebuffer)
+        .expect("[VMM]: Failed to track the framebuffer");
+}
+/// Create a new address space sharing the kernel mappings
+pub fn new_address_space() -> Result<AddressSpace, VmmError> {
+    let heap_entry = VirtAddr::new(HEAP_START as u64).p4_index();
+    with_kernel_space(|space| {
+        space.new_from_kernel(
+            &[usize::from(heap_entry)],
+            VirtAddr::new(USER_VMAP_START),
+            USER_VMAP_SIZE,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests;
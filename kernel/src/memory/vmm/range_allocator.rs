@@ -0,0 +1,130 @@
+//! Virtual address range allocator
+use alloc::collections::BTreeMap;
+use x86_64::VirtAddr;
+
+/// Size of a page in bytes
+const PAGE_SIZE: u64 = 4096;
+
+/// Hands out page aligned ranges of a fixed virtual window
+///
+/// Free ranges are kept in a map from start address to size and are merged
+/// with their neighbours when a range is returned.
+#[derive(Debug)]
+pub struct VirtualRangeAllocator {
+    start: u64,
+    end: u64,
+    free: BTreeMap<u64, u64>,
+}
+
+impl VirtualRangeAllocator {
+    /// Create an allocator that manages the window `[start, start + size)`
+    pub fn new(start: VirtAddr, size: u64) -> Self {
+        let start = start.align_up(PAGE_SIZE).as_u64();
+        let size = size & !(PAGE_SIZE - 1);
+        let mut free = BTreeMap::new();
+        if size > 0 {
+            free.insert(start, size);
+        }
+        VirtualRangeAllocator {
+            start,
+            end: start + size,
+            free,
+        }
+    }
+    /// Allocate `size` bytes aligned to `align` bytes (a power of two)
+    ///
+    /// The size is rounded up to whole pages. Uses a first fit search.
+    pub fn allocate(&mut self, size: u64, align: u64) -> Option<VirtAddr> {
+        let size = size.checked_next_multiple_of(PAGE_SIZE)?;
+        let align = align.max(PAGE_SIZE);
+        if size == 0 || !align.is_power_of_two() {
+            return None;
+        }
+        let (hole_start, hole_size, alloc_start) =
+            self.free.iter().find_map(|(&start, &len)| {
+                let alloc_start = (start + align - 1) & !(align - 1);
+                let padding = alloc_start - start;
+                if len >= padding && len - padding >= size {
+                    Some((start, len, alloc_start))
+                } else {
+                    None
+                }
+            })?;
+        // Split the hole into the padding before and the rest after the allocation
+        self.free.remove(&hole_start);
+        if alloc_start > hole_start {
+            self.free.insert(hole_start, alloc_start - hole_start);
+        }
+        let alloc_end = alloc_start + size;
+        let hole_end = hole_start + hole_size;
+        if hole_end > alloc_end {
+            self.free.insert(alloc_end, hole_end - alloc_end);
+        }
+        Some(VirtAddr::new(alloc_start))
+    }
+    /// Reserve a specific range so that it is never handed out
+    ///
+    /// Returns false if part of the range is already in use.
+    pub fn reserve(&mut self, start: VirtAddr, size: u64) -> bool {
+        let start = start.align_down(PAGE_SIZE).as_u64();
+        let end = match (start + size).checked_next_multiple_of(PAGE_SIZE) {
+            Some(end) => end,
+            None => return false,
+        };
+        // Find the free hole containing the whole range
+        let hole = self
+            .free
+            .range(..=start)
+            .next_back()
+            .map(|(&hole_start, &len)| (hole_start, hole_start + len));
+        match hole {
+            Some((hole_start, hole_end)) if hole_end >= end => {
+                self.free.remove(&hole_start);
+                if start > hole_start {
+                    self.free.insert(hole_start, start - hole_start);
+                }
+                if hole_end > end {
+                    self.free.insert(end, hole_end - end);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+    /// Return a range previously handed out by `allocate` or `reserve`
+    pub fn deallocate(&mut self, start: VirtAddr, size: u64) {
+        let mut start = start.align_down(PAGE_SIZE).as_u64();
+        let mut size = size.next_multiple_of(PAGE_SIZE);
+        assert!(
+            start >= self.start && start + size <= self.end,
+            "[VMM]: Freed a range outside the allocator window"
+        );
+        // Merge with the previous hole if it ends where this range starts
+        if let Some((&prev_start, &prev_size)) = self.free.range(..start).next_back() {
+            assert!(prev_start + prev_size <= start, "[VMM]: Range freed twice");
+            if prev_start + prev_size == start {
+                self.free.remove(&prev_start);
+                start = prev_start;
+                size += prev_size;
+            }
+        }
+        // Merge with the next hole if it starts where this range ends
+        if let Some((&next_start, &next_size)) = self.free.range(start..).next() {
+            assert!(start + size <= next_start, "[VMM]: Range freed twice");
+            if start + size == next_start {
+                self.free.remove(&next_start);
+                size += next_size;
+            }
+        }
+        self.free.insert(start, size);
+    }
+    /// Returns true if `addr` lies in the window managed by the allocator
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        let addr = addr.as_u64();
+        addr >= self.start && addr < self.end
+    }
+    /// Number of free bytes left in the window
+    pub fn free_bytes(&self) -> u64 {
+        self.free.values().sum()
+    }
+}
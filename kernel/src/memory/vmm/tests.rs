@@ -0,0 +1,125 @@
+use super::*;
+use crate::memory::FRAME_ALLOCATOR;
+use x86_64::structures::paging::{
+    mapper::TranslateResult, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTable, PhysFrame,
+    Size4KiB, Translate,
+};
+
+const PAGE: u64 = 4096;
+/// Start of the window of the range allocator tests, never mapped
+const WINDOW: u64 = 0x1000_0000;
+
+#[test_case]
+fn test_range_allocate() {
+    let mut ranges = VirtualRangeAllocator::new(VirtAddr::new(WINDOW), 16 * PAGE);
+    assert_eq!(ranges.allocate(PAGE, PAGE), Some(VirtAddr::new(WINDOW)));
+    // Rounded up to a whole page
+    assert_eq!(
+        ranges.allocate(100, PAGE),
+        Some(VirtAddr::new(WINDOW + PAGE))
+    );
+    // The hole before the aligned start stays free
+    assert_eq!(
+        ranges.allocate(PAGE, 4 * PAGE),
+        Some(VirtAddr::new(WINDOW + 4 * PAGE))
+    );
+    assert_eq!(
+        ranges.allocate(2 * PAGE, PAGE),
+        Some(VirtAddr::new(WINDOW + 2 * PAGE))
+    );
+    assert_eq!(ranges.free_bytes(), 11 * PAGE);
+    assert_eq!(ranges.allocate(PAGE, 3 * PAGE), None);
+    assert_eq!(ranges.allocate(0, PAGE), None);
+}
+#[test_case]
+fn test_range_free_coalesces() {
+    let mut ranges = VirtualRangeAllocator::new(VirtAddr::new(WINDOW), 3 * PAGE);
+    let first = ranges.allocate(PAGE, PAGE).unwrap();
+    let second = ranges.allocate(PAGE, PAGE).unwrap();
+    let third = ranges.allocate(PAGE, PAGE).unwrap();
+    ranges.deallocate(first, PAGE);
+    ranges.deallocate(third, PAGE);
+    // Two separate holes, no room for two pages
+    assert_eq!(ranges.allocate(2 * PAGE, PAGE), None);
+    // Merged with both neighbours
+    ranges.deallocate(second, PAGE);
+    assert_eq!(ranges.allocate(3 * PAGE, PAGE), Some(first));
+}
+#[test_case]
+fn test_range_exhaustion() {
+    let mut ranges = VirtualRangeAllocator::new(VirtAddr::new(WINDOW), 4 * PAGE);
+    assert_eq!(ranges.allocate(5 * PAGE, PAGE), None);
+    let all = ranges.allocate(4 * PAGE, PAGE).unwrap();
+    assert_eq!(ranges.free_bytes(), 0);
+    assert_eq!(ranges.allocate(PAGE, PAGE), None);
+    assert!(!ranges.reserve(all + PAGE, PAGE));
+    ranges.deallocate(all, 4 * PAGE);
+    assert_eq!(ranges.free_bytes(), 4 * PAGE);
+}
+#[test_case]
+fn test_range_reserve() {
+    let mut ranges = VirtualRangeAllocator::new(VirtAddr::new(WINDOW), 4 * PAGE);
+    assert!(ranges.reserve(VirtAddr::new(WINDOW + PAGE), 2 * PAGE));
+    assert!(!ranges.reserve(VirtAddr::new(WINDOW + 2 * PAGE), PAGE));
+    assert_eq!(ranges.allocate(2 * PAGE, PAGE), None);
+    assert_eq!(ranges.allocate(PAGE, PAGE), Some(VirtAddr::new(WINDOW)));
+    assert_eq!(
+        ranges.allocate(PAGE, PAGE),
+        Some(VirtAddr::new(WINDOW + 3 * PAGE))
+    );
+    assert!(ranges.contains(VirtAddr::new(WINDOW + 3 * PAGE)));
+    assert!(!ranges.contains(VirtAddr::new(WINDOW + 4 * PAGE)));
+}
+#[test_case]
+fn test_protect_is_all_or_nothing() {
+    let mut space = new_address_space().unwrap();
+    let start = VirtAddr::new(USER_VMAP_START);
+    let writable = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    space
+        .map("protect test", RegionKind::Other, start, 3 * PAGE, writable)
+        .unwrap();
+    // Unmap the last page behind the back of the VMM
+    let offset = VirtAddr::new(crate::HHDM_REQUEST.get_response().unwrap().offset());
+    let table = offset + space.level_4_frame().start_address().as_u64();
+    let mut mapper = unsafe { OffsetPageTable::new(&mut *table.as_mut_ptr::<PageTable>(), offset) };
+    let (frame, flush) = mapper
+        .unmap(Page::<Size4KiB>::containing_address(start + 2 * PAGE))
+        .unwrap();
+    flush.flush();
+    without_interrupts(|| unsafe { FRAME_ALLOCATOR.lock().deallocate_frame(frame) });
+
+    assert_eq!(
+        space.protect(start, PageTableFlags::PRESENT),
+        Err(VmmError::NotMapped)
+    );
+    // The pages checked before the unmapped one kept their flags
+    for page in 0..2 {
+        match mapper.translate(start + page * PAGE) {
+            TranslateResult::Mapped { flags, .. } => {
+                assert!(flags.contains(PageTableFlags::WRITABLE))
+            }
+            _ => panic!("page {} not mapped", page),
+        }
+    }
+    space.unmap(start).unwrap();
+}
+#[test_case]
+fn test_drop_frees_frames() {
+    let mut space = new_address_space().unwrap();
+    let start = VirtAddr::new(USER_VMAP_START);
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    space
+        .map("drop test", RegionKind::Other, start, PAGE, flags)
+        .unwrap();
+    let level_4 = space.level_4_frame();
+    let page = PhysFrame::containing_address(space.translate(start).unwrap());
+    let is_allocated = |frame| without_interrupts(|| FRAME_ALLOCATOR.lock().is_allocated(frame));
+    assert!(is_allocated(level_4) && is_allocated(page));
+    drop(space);
+    assert!(!is_allocated(level_4));
+    assert!(!is_allocated(page));
+    // The kernel mappings shared with the dropped space are untouched
+    assert!(
+        with_kernel_space(|kernel| kernel.translate(VirtAddr::new(HEAP_START as u64))).is_some()
+    );
+}
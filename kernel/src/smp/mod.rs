@@ -0,0 +1,227 @@
+//! Symmetric multiprocessing
+//!
+//! Limine parks every application processor (AP) and starts it at the address
+//! written in its SMP entry. The BSP starts the APs one after the other: each
+//! one loads its own GDT and TSS and the shared IDT, enables its LAPIC,
+//! measures its timer, reports itself online and halts. APs run no threads or
+//! tasks yet, the IOAPIC routes every IRQ to the BSP.
+//!
+//! Once they are started the online CPUs are checked against the MADT.
+use crate::boot_params::BootParam;
+use crate::drivers::acpi::madt::MADT_TABLE;
+use crate::drivers::apic::local_apic;
+use crate::gdt::{self, CpuTables};
+use crate::time::{Duration, Instant};
+use crate::timer::lapic;
+use crate::SMP_REQUEST;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::lazy_static;
+use limine::smp::Cpu;
+use log::{info, warn};
+use spin::Mutex;
+use x86_64::instructions::interrupts::{self, without_interrupts};
+
+/// Start the application processors at boot, `smp.enable=off` leaves them parked
+pub static ENABLE: BootParam<bool> =
+    BootParam::new("smp.enable", "start the application processors", true);
+
+/// Time an AP gets to report itself online
+const START_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Bring-up state of a processor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuState {
+    /// Still parked by the bootloader
+    Parked,
+    /// Sent to the AP entry point, not online yet
+    Starting,
+    /// Running, in its idle loop for an AP
+    Online,
+    /// Could not be set up or did not come online in time
+    Failed,
+}
+/// A processor listed in the SMP response of the bootloader
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuStatus {
+    /// ACPI processor UID
+    pub processor_uid: u32,
+    /// Local APIC ID
+    pub apic_id: u32,
+    pub bsp: bool,
+    pub state: CpuState,
+    /// Measured LAPIC timer frequency with a divider of 1, `None` until calibrated
+    pub lapic_ticks_per_ms: Option<u32>,
+}
+struct CpuEntry {
+    status: CpuStatus,
+    /// GDT and TSS handed to the AP when it starts
+    tables: Option<&'static CpuTables>,
+}
+
+lazy_static! {
+    /// Every processor of the SMP response, the BSP included
+    static ref CPUS: Mutex<Vec<CpuEntry>> = Mutex::new(Vec::new());
+}
+/// Set before the first AP starts, until then only the BSP runs
+static AP_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Start every AP of the SMP response and check the online CPUs against the MADT
+pub fn init() {
+    let Some(response) = SMP_REQUEST.get_response() else {
+        warn!("SMP: no response from the bootloader, running on the BSP only");
+        return;
+    };
+    let bsp_apic_id = response.bsp_lapic_id();
+    without_interrupts(|| {
+        let mut cpus = CPUS.lock();
+        cpus.extend(response.cpus().iter().map(|cpu| {
+            let bsp = cpu.lapic_id == bsp_apic_id;
+            CpuEntry {
+                status: CpuStatus {
+                    processor_uid: cpu.id,
+                    apic_id: cpu.lapic_id,
+                    bsp,
+                    state: if bsp {
+                        CpuState::Online
+                    } else {
+                        CpuState::Parked
+                    },
+                    lapic_ticks_per_ms: None,
+                },
+                tables: None,
+            }
+        }));
+    });
+    if !ENABLE.get() {
+        info!(
+            "SMP: disabled, {} APs left parked",
+            response.cpus().len().saturating_sub(1)
+        );
+        return;
+    }
+    for cpu in response.cpus() {
+        if cpu.lapic_id != bsp_apic_id {
+            start(cpu);
+        }
+    }
+    verify();
+}
+/// Whether the running CPU is the BSP
+///
+/// Cheap until an AP starts, reads the APIC base MSR afterwards.
+pub fn is_bsp() -> bool {
+    !AP_STARTED.load(Ordering::Acquire) || local_apic::is_bsp()
+}
+/// Every processor of the SMP response, the BSP included
+pub fn cpus() -> Vec<CpuStatus> {
+    without_interrupts(|| {
+        CPUS.lock()
+            .iter()
+            .map(|entry| match entry.status.bsp {
+                // The BSP timer is calibrated after SMP init, see `main`
+                true => CpuStatus {
+                    lapic_ticks_per_ms: lapic::ticks_per_ms(),
+                    ..entry.status
+                },
+                false => entry.status,
+            })
+            .collect()
+    })
+}
+/// Number of CPUs online, the BSP included
+pub fn online_count() -> usize {
+    without_interrupts(|| {
+        CPUS.lock()
+            .iter()
+            .filter(|entry| entry.status.state == CpuState::Online)
+            .count()
+    })
+}
+/// APIC IDs of the enabled processors of the MADT that are not online
+pub fn missing_cpus() -> Vec<u32> {
+    let cpus = cpus();
+    MADT_TABLE
+        .cpus()
+        .into_iter()
+        .filter(|listed| listed.enabled)
+        .filter(|listed| {
+            !cpus
+                .iter()
+                .any(|cpu| cpu.apic_id == listed.apic_id && cpu.state == CpuState::Online)
+        })
+        .map(|listed| listed.apic_id)
+        .collect()
+}
+
+/// Run `f` on the entry of the CPU with APIC ID `apic_id`
+fn with_entry<R>(apic_id: u32, f: impl FnOnce(&mut CpuEntry) -> R) -> Option<R> {
+    without_interrupts(|| {
+        CPUS.lock()
+            .iter_mut()
+            .find(|entry| entry.status.apic_id == apic_id)
+            .map(f)
+    })
+}
+/// Start `cpu` and wait until it is online or the timeout expires
+fn start(cpu: &Cpu) {
+    let tables = match gdt::new_ap_tables() {
+        Ok(tables) => tables,
+        Err(error) => {
+            warn!("SMP: no GDT for CPU {}: {:?}", cpu.lapic_id, error);
+            with_entry(cpu.lapic_id, |entry| entry.status.state = CpuState::Failed);
+            return;
+        }
+    };
+    with_entry(cpu.lapic_id, |entry| {
+        entry.tables = Some(tables);
+        entry.status.state = CpuState::Starting;
+    });
+    AP_STARTED.store(true, Ordering::Release);
+    cpu.goto_address.write(ap_entry);
+    let deadline = Instant::now() + START_TIMEOUT;
+    while with_entry(cpu.lapic_id, |entry| entry.status.state) == Some(CpuState::Starting) {
+        if Instant::now() >= deadline {
+            with_entry(cpu.lapic_id, |entry| {
+                // Checked again under the lock, the AP may just have come online
+                if entry.status.state == CpuState::Starting {
+                    entry.status.state = CpuState::Failed;
+                }
+            });
+            warn!("SMP: CPU {} did not come online", cpu.lapic_id);
+            break;
+        }
+        core::hint::spin_loop();
+    }
+}
+/// Log the online CPUs and warn about the ones of the MADT that are missing
+fn verify() {
+    let listed = MADT_TABLE.cpus().iter().filter(|cpu| cpu.enabled).count();
+    for apic_id in missing_cpus() {
+        warn!("SMP: CPU {} is listed in the MADT but not online", apic_id);
+    }
+    info!("SMP: {} of {} CPUs online", online_count(), listed);
+}
+/// Entry point of an AP, Limine jumps here with interrupts disabled and a 64 KiB stack
+///
+/// The AP runs on the page tables of the BSP, which the kernel extends in place.
+unsafe extern "C" fn ap_entry(cpu: &Cpu) -> ! {
+    let tables = with_entry(cpu.lapic_id, |entry| entry.tables)
+        .flatten()
+        .expect("AP started without a GDT");
+    gdt::init_ap(tables);
+    crate::interrupts::init_idt();
+    local_apic::init();
+    let ticks_per_ms = lapic::measure_ap_ticks_per_ms();
+    // A late AP still counts, it replaces the `Failed` of the timeout
+    with_entry(cpu.lapic_id, |entry| {
+        entry.status.state = CpuState::Online;
+        entry.status.lapic_ticks_per_ms = Some(ticks_per_ms);
+    });
+    loop {
+        interrupts::enable_and_hlt();
+    }
+}
+
+#[cfg(test)]
+mod tests;
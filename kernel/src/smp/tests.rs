@@ -0,0 +1,30 @@
+use super::*;
+
+#[test_case]
+fn test_madt_cpus_online() {
+    // The test runner boots with `-smp 4`
+    assert_eq!(cpus().len(), 4);
+    assert_eq!(missing_cpus(), Vec::new());
+    assert_eq!(online_count(), cpus().len());
+}
+#[test_case]
+fn test_runs_on_bsp() {
+    assert!(is_bsp());
+    let bsp: Vec<_> = cpus().into_iter().filter(|cpu| cpu.bsp).collect();
+    assert_eq!(bsp.len(), 1);
+    assert_eq!(
+        bsp[0].apic_id,
+        local_apic::LOCAL_APIC.apic_id(),
+        "tests run on the BSP"
+    );
+}
+#[test_case]
+fn test_aps_calibrated() {
+    let aps: Vec<_> = cpus().into_iter().filter(|cpu| !cpu.bsp).collect();
+    assert_eq!(aps.len(), 3);
+    for cpu in &aps {
+        let ticks = cpu.lapic_ticks_per_ms.unwrap();
+        // Same bounds as the BSP calibration
+        assert!(ticks > 1_000 && ticks < 100_000_000, "{:?}", cpu);
+    }
+}
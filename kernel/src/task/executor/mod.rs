@@ -0,0 +1,618 @@
+//! Task executor module
+//!
+//! Tasks are spawned with `Executor::spawn` before the executor runs, or from
+//! anywhere through a `Spawner`, including other tasks, kernel threads and
+//! interrupt handlers. Every queue grows as needed instead of filling up: the
+//! wakers only ever queue a task once, and room for every task is reserved
+//! when it is spawned, so waking never allocates.
+//!
+//! What every task is doing is kept in a table shared with the spawners, so
+//! the tasks can be listed with `ps` even while one of them never returns.
+use super::scheduler::{self, Nice, Policy, RunQueue};
+use super::{Task, TaskId, TaskInfo, TaskState};
+use crate::serial_println;
+use crate::time::{Duration, Instant};
+use alloc::{
+    borrow::Cow,
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Weak},
+    task::Wake,
+    vec::Vec,
+};
+use conquer_once::spin::OnceCell;
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, Waker},
+};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// Spawner of the executor started with `Executor::run`
+static SPAWNER: OnceCell<Spawner> = OnceCell::uninit();
+
+/// A future spawned through a `Spawner`, waiting to be picked up by the executor
+struct Spawned {
+    id: TaskId,
+    name: Cow<'static, str>,
+    nice: Nice,
+    spawned: Instant,
+    future: Pin<Box<dyn Future<Output = ()> + Send>>,
+}
+/// State shared by the executor with its spawners and wakers
+///
+/// The locks are only taken with interrupts disabled, so they can be used
+/// from interrupt handlers and by preempted kernel threads.
+struct Shared {
+    /// Tasks woken since the last pass, each task is queued at most once
+    woken: Mutex<VecDeque<TaskId>>,
+    /// Tasks spawned through a `Spawner` since the last pass
+    spawned: Mutex<VecDeque<Spawned>>,
+    /// Tasks to drop at the next pass
+    cancelled: Mutex<Vec<TaskId>>,
+    /// Every task of the executor
+    info: Mutex<BTreeMap<TaskId, TaskInfo>>,
+    /// The executor was dropped, new tasks are dropped right away
+    closed: AtomicBool,
+}
+impl Shared {
+    /// Update the entry of the task `id`, returns false if there is none
+    fn update(&self, id: TaskId, f: impl FnOnce(&mut TaskInfo)) -> bool {
+        without_interrupts(|| self.info.lock().get_mut(&id).map(f).is_some())
+    }
+    /// Copy of the entries, so nothing is locked while they are printed
+    fn snapshot(&self) -> Vec<TaskInfo> {
+        without_interrupts(|| self.info.lock().values().cloned().collect())
+    }
+    /// Ask the executor to drop the task `id`, returns false if there is no such task
+    fn cancel(&self, id: TaskId) -> bool {
+        let (found, spawned) = without_interrupts(|| {
+            // Not picked up by the executor yet, drop it right here
+            let mut spawned = self.spawned.lock();
+            if let Some(index) = spawned.iter().position(|task| task.id == id) {
+                return (true, spawned.remove(index));
+            }
+            if !self.info.lock().contains_key(&id) {
+                return (false, None);
+            }
+            self.cancelled.lock().push(id);
+            (true, None)
+        });
+        // Dropped outside of the locks, this wakes the handle
+        drop(spawned);
+        found
+    }
+}
+
+/// Task executor that runs tasks on a single thread
+pub struct Executor {
+    tasks: BTreeMap<TaskId, Task>,
+    shared: Arc<Shared>,
+    /// Tasks ready to be polled, in the order of the scheduling policy
+    ready: RunQueue<TaskId>,
+    waker_cache: BTreeMap<TaskId, Arc<TaskWaker>>,
+}
+
+impl Executor {
+    /// Create a new Executor
+    pub fn new() -> Self {
+        Executor {
+            tasks: BTreeMap::new(),
+            shared: Arc::new(Shared {
+                woken: Mutex::new(VecDeque::new()),
+                spawned: Mutex::new(VecDeque::new()),
+                cancelled: Mutex::new(Vec::new()),
+                info: Mutex::new(BTreeMap::new()),
+                closed: AtomicBool::new(false),
+            }),
+            ready: RunQueue::new(scheduler::POLICY.get()),
+            waker_cache: BTreeMap::new(),
+        }
+    }
+    /// A handle to spawn tasks on this executor from anywhere
+    pub fn spawner(&self) -> Spawner {
+        Spawner {
+            shared: self.shared.clone(),
+        }
+    }
+    /// Change the order in which ready tasks are polled
+    pub fn set_policy(&mut self, policy: Policy) {
+        self.ready.set_policy(policy);
+    }
+    pub fn policy(&self) -> Policy {
+        self.ready.policy()
+    }
+    /// What every task that did not complete yet is doing
+    pub fn tasks(&self) -> Vec<TaskInfo> {
+        self.shared.snapshot()
+    }
+    /// Spawn a new task to be executed by the executor
+    pub fn spawn(&mut self, task: Task) {
+        // Set the task ID
+        let task_id = task.id;
+        let nice = task.nice;
+        let info = TaskInfo::new(&task);
+        // If the task ID already exists, panic
+        // because if the task ID already exists, it means
+        // that there is a bug in our program
+        if self.tasks.insert(task.id, task).is_some() {
+            panic!("task with same ID already in tasks");
+        }
+        // Wakers run in interrupt handlers, the woken queue must never grow there
+        let count = self.tasks.len();
+        without_interrupts(|| {
+            self.shared.woken.lock().reserve(count);
+            self.shared.info.lock().insert(task_id, info);
+        });
+        // Queue the task to be polled a first time
+        self.ready.push(task_id, nice);
+    }
+    /// Drop the task `id` without polling it again, returns false if there is no such task
+    ///
+    /// Its `JoinHandle` returns `Err(JoinError::Cancelled)`.
+    pub fn cancel(&mut self, id: TaskId) -> bool {
+        let Some(task) = self.tasks.remove(&id) else {
+            return false;
+        };
+        self.ready.remove(id);
+        self.waker_cache.remove(&id);
+        without_interrupts(|| self.shared.info.lock().remove(&id));
+        drop(task);
+        true
+    }
+    /// Take the tasks spawned, cancelled and woken since the last call
+    fn collect_ready(&mut self) {
+        let (spawned, cancelled, woken) = without_interrupts(|| {
+            (
+                core::mem::take(&mut *self.shared.spawned.lock()),
+                core::mem::take(&mut *self.shared.cancelled.lock()),
+                self.shared.woken.lock().len(),
+            )
+        });
+        for spawned in spawned {
+            self.spawn(Task {
+                id: spawned.id,
+                future: spawned.future,
+                name: spawned.name,
+                nice: spawned.nice,
+                spawned: spawned.spawned,
+            });
+        }
+        for id in cancelled {
+            self.cancel(id);
+        }
+        // Only the tasks queued before are taken, so this can not loop forever
+        for _ in 0..woken {
+            let Some(task_id) = without_interrupts(|| self.shared.woken.lock().pop_front()) else {
+                break;
+            };
+            // Let the waker queue the task again once it is polled
+            if let Some(waker) = self.waker_cache.get(&task_id) {
+                waker.queued.store(false, Ordering::Release);
+            }
+            if let Some(task) = self.tasks.get(&task_id) {
+                if !self.ready.contains(task_id) {
+                    self.shared.update(task_id, |info| {
+                        info.stats.record_wakeup();
+                        info.state = TaskState::Ready;
+                    });
+                    self.ready.push(task_id, task.nice);
+                }
+            }
+        }
+    }
+    /// Run the executor, executing tasks until the queue is empty
+    ///
+    /// This implementation is more efficient than the simple executor
+    /// because it will put the CPU to sleep when there are no tasks to run
+    /// or when all tasks are waiting for something to happen (e.g. I/O)
+    fn run_ready_tasks(&mut self) {
+        loop {
+            // Move the spawned and woken tasks to the run queue
+            self.collect_ready();
+            // Take the next task according to the policy
+            let Some(task_id) = self.ready.pop() else {
+                break;
+            };
+            // Create a new scope to limit the lifetime of the mutable borrows
+            let Self {
+                tasks,
+                shared,
+                waker_cache,
+                ..
+            } = self;
+            // Get the task from the tasks map
+            // If the task is not found, skip it
+            let task = match tasks.get_mut(&task_id) {
+                Some(task) => task,
+                None => continue,
+            };
+            // Get the waker for the task
+            // If the waker is not found, insert a new one
+            // therefore, we can wake the task later without
+            // having to look up the waker again
+            let waker = waker_cache
+                .entry(task_id)
+                .or_insert_with(|| TaskWaker::new(task_id, shared.clone()));
+            // Create a new context from the waker
+            let waker = Waker::from(waker.clone());
+            let mut context = Context::from_waker(&waker);
+            // Poll the task
+            // If the task is ready, remove it from the tasks map
+            // and the waker cache, else, keep it in the task queue
+            // to be polled again later
+            let start = Instant::now();
+            shared.update(task_id, |info| {
+                info.state = TaskState::Running;
+                info.last_polled = Some(start);
+            });
+            let poll = task.poll(&mut context);
+            let elapsed = start.elapsed();
+            match poll {
+                Poll::Ready(()) => {
+                    tasks.remove(&task_id);
+                    waker_cache.remove(&task_id);
+                    without_interrupts(|| shared.info.lock().remove(&task_id));
+                }
+                Poll::Pending => {
+                    shared.update(task_id, |info| {
+                        info.stats.record_run(elapsed);
+                        info.state = TaskState::Waiting;
+                    });
+                }
+            }
+        }
+    }
+    /// Run the executor
+    /// This will run all tasks until they are all completed
+    /// and then put the CPU to sleep until there are new tasks
+    /// to run
+    pub fn run(&mut self) -> ! {
+        // Make `spawn` and `ps` use this executor
+        let _ = SPAWNER.try_init_once(|| self.spawner());
+        loop {
+            self.run_ready_tasks();
+            self.sleep_if_idle();
+        }
+    }
+    /// Put the CPU to sleep until there are new tasks to run
+    fn sleep_if_idle(&self) {
+        use x86_64::instructions::interrupts::{self, enable_and_hlt};
+        // Disable interrupts to prevent race conditions
+        interrupts::disable();
+        // Check if the task queues are empty
+        let idle = self.ready.is_empty()
+            && self.shared.woken.lock().is_empty()
+            && self.shared.spawned.lock().is_empty()
+            && self.shared.cancelled.lock().is_empty();
+        if idle && super::thread::ready_count() > 0 {
+            // Let the kernel threads run instead of halting
+            super::thread::yield_now();
+            interrupts::enable();
+        } else if idle {
+            // If it is, enable interrupts and put the CPU to sleep
+            enable_and_hlt();
+        } else {
+            // If there are tasks to run, only enable interrupts
+            interrupts::enable();
+        }
+    }
+}
+impl Drop for Executor {
+    fn drop(&mut self) {
+        // Tasks spawned from now on are dropped by the spawner
+        let spawned = without_interrupts(|| {
+            let mut queue = self.shared.spawned.lock();
+            self.shared.closed.store(true, Ordering::Relaxed);
+            self.shared.info.lock().clear();
+            core::mem::take(&mut *queue)
+        });
+        drop(spawned);
+    }
+}
+
+/// Handle to spawn tasks on an executor, can be cloned and sent anywhere
+///
+/// Spawning allocates the task, which is fine in interrupt handlers since
+/// the heap is only locked with interrupts disabled.
+#[derive(Clone)]
+pub struct Spawner {
+    shared: Arc<Shared>,
+}
+impl Spawner {
+    /// Run `future` on the executor, its output is returned by the `JoinHandle`
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.builder().spawn(future)
+    }
+    /// Spawn a task with a name or a priority
+    pub fn builder(&self) -> Builder<'_> {
+        Builder {
+            spawner: self,
+            name: Cow::Borrowed("unnamed"),
+            nice: Nice::DEFAULT,
+        }
+    }
+    /// Drop the task `id` without polling it again, returns false if there is no such task
+    ///
+    /// The task is dropped by the executor at its next pass, or right away if
+    /// it was not picked up yet.
+    pub fn cancel(&self, id: TaskId) -> bool {
+        self.shared.cancel(id)
+    }
+    /// What every task that did not complete yet is doing
+    pub fn tasks(&self) -> Vec<TaskInfo> {
+        self.shared.snapshot()
+    }
+    /// Write a `ps` style table of the tasks
+    pub fn dump(&self, writer: &mut impl fmt::Write) -> fmt::Result {
+        writeln!(writer, "{}", PS_HEADER)?;
+        for info in self.tasks() {
+            writeln!(writer, "{}", TaskInfoDisplay(&info))?;
+        }
+        Ok(())
+    }
+}
+impl fmt::Debug for Spawner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Spawner").finish_non_exhaustive()
+    }
+}
+
+/// Options of a task spawned through a `Spawner`
+pub struct Builder<'a> {
+    spawner: &'a Spawner,
+    name: Cow<'static, str>,
+    nice: Nice,
+}
+impl Builder<'_> {
+    /// Set the name shown when the tasks are listed
+    pub fn name(mut self, name: impl Into<Cow<'static, str>>) -> Self {
+        self.name = name.into();
+        self
+    }
+    /// Set the priority of the task for the priority policy
+    pub fn nice(mut self, nice: Nice) -> Self {
+        self.nice = nice;
+        self
+    }
+    /// Run `future` on the executor, its output is returned by the `JoinHandle`
+    pub fn spawn<F>(self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let shared = &self.spawner.shared;
+        let id = TaskId::new();
+        let (future, handle) = join(id, future, Arc::downgrade(shared));
+        let spawned = Spawned {
+            id,
+            name: self.name,
+            nice: self.nice,
+            spawned: Instant::now(),
+            future: Box::pin(future),
+        };
+        let rejected = without_interrupts(|| {
+            let mut queue = shared.spawned.lock();
+            if shared.closed.load(Ordering::Relaxed) {
+                return Some(spawned);
+            }
+            queue.push_back(spawned);
+            None
+        });
+        // Dropped outside of the lock, this wakes the handle
+        drop(rejected);
+        handle
+    }
+}
+
+/// Spawn `future` on the executor running the kernel
+///
+/// Panics if `Executor::run` was not called yet.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    SPAWNER
+        .try_get()
+        .expect("no executor is running")
+        .spawn(future)
+}
+/// Print the tasks of the executor running the kernel over serial
+///
+/// Can be called from interrupt handlers, to look at a task that keeps the
+/// executor busy.
+pub fn ps() {
+    let Ok(spawner) = SPAWNER.try_get() else {
+        serial_println!("No executor is running");
+        return;
+    };
+    serial_println!("{}", PS_HEADER);
+    for info in spawner.tasks() {
+        serial_println!("{}", TaskInfoDisplay(&info));
+    }
+}
+
+const PS_HEADER: &str = "   ID STATE    NICE    POLLS    BUSY ms      AGE ms     LAST ms NAME";
+/// Prints a task as a line of the `ps` table
+struct TaskInfoDisplay<'a>(&'a TaskInfo);
+impl fmt::Display for TaskInfoDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let info = self.0;
+        write!(
+            f,
+            "{:>5} {:<8} {:>4} {:>8} {} {} ",
+            info.id,
+            info.state,
+            info.nice,
+            info.stats.runs,
+            Millis(info.stats.runtime),
+            Millis(info.age()),
+        )?;
+        match info.since_polled() {
+            Some(since) => write!(f, "{}", Millis(since))?,
+            None => write!(f, "{:>11}", "-")?,
+        }
+        write!(f, " {}", info.name)
+    }
+}
+/// Prints a duration as milliseconds with 3 decimals, 11 characters wide
+struct Millis(Duration);
+impl fmt::Display for Millis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let micros = self.0.as_micros();
+        write!(f, "{:>7}.{:03}", micros / 1000, micros % 1000)
+    }
+}
+
+/// Why a `JoinHandle` has no value to return
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinError {
+    /// The task was cancelled, or dropped with its executor, before completing
+    Cancelled,
+}
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Cancelled => write!(f, "task was cancelled"),
+        }
+    }
+}
+
+struct JoinState<T> {
+    value: Option<T>,
+    /// The task completed or was dropped
+    done: bool,
+    /// Task awaiting the handle
+    waker: Option<Waker>,
+}
+/// Owned permission to await the output of a spawned task
+///
+/// Dropping the handle detaches the task, it keeps running.
+pub struct JoinHandle<T> {
+    id: TaskId,
+    state: Arc<Mutex<JoinState<T>>>,
+    shared: Weak<Shared>,
+}
+impl<T> JoinHandle<T> {
+    pub fn task_id(&self) -> TaskId {
+        self.id
+    }
+    pub fn is_finished(&self) -> bool {
+        without_interrupts(|| self.state.lock().done)
+    }
+    /// Drop the task without polling it again, returns false if it already finished
+    pub fn cancel(&self) -> bool {
+        match self.shared.upgrade() {
+            Some(shared) => shared.cancel(self.id),
+            None => false,
+        }
+    }
+}
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        without_interrupts(|| {
+            let mut state = self.state.lock();
+            if let Some(value) = state.value.take() {
+                Poll::Ready(Ok(value))
+            } else if state.done {
+                Poll::Ready(Err(JoinError::Cancelled))
+            } else {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+    }
+}
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinHandle").field("id", &self.id).finish()
+    }
+}
+/// Marks the task as done when the task is dropped, with or without a value
+struct Completion<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        let waker = without_interrupts(|| {
+            let mut state = self.state.lock();
+            state.done = true;
+            state.waker.take()
+        });
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+/// Wrap `future` so its output is handed to the returned `JoinHandle`
+fn join<F>(
+    id: TaskId,
+    future: F,
+    shared: Weak<Shared>,
+) -> (impl Future<Output = ()>, JoinHandle<F::Output>)
+where
+    F: Future,
+{
+    let state = Arc::new(Mutex::new(JoinState {
+        value: None,
+        done: false,
+        waker: None,
+    }));
+    let completion = Completion {
+        state: state.clone(),
+    };
+    let task = async move {
+        let value = future.await;
+        without_interrupts(|| completion.state.lock().value = Some(value));
+        // Dropping `completion` wakes the handle
+    };
+    (task, JoinHandle { id, state, shared })
+}
+
+/// Task waker that can wake up tasks
+struct TaskWaker {
+    task_id: TaskId,
+    /// The task is in the woken queue, waking it again does nothing
+    queued: AtomicBool,
+    shared: Arc<Shared>,
+}
+
+impl TaskWaker {
+    /// Wake up the task associated with this waker
+    ///
+    /// Never allocates, the queue has room for every task of the executor.
+    fn wake_task(&self) {
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            without_interrupts(|| self.shared.woken.lock().push_back(self.task_id));
+        }
+    }
+    /// Create a new TaskWaker
+    fn new(task_id: TaskId, shared: Arc<Shared>) -> Arc<TaskWaker> {
+        Arc::new(TaskWaker {
+            task_id,
+            queued: AtomicBool::new(false),
+            shared,
+        })
+    }
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_task();
+    }
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.wake_task();
+    }
+}
+
+#[cfg(test)]
+mod tests;
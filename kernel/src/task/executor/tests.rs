@@ -0,0 +1,137 @@
+use super::*;
+use crate::task::thread;
+use alloc::{string::String, vec::Vec};
+use core::sync::atomic::AtomicU64;
+
+#[test_case]
+fn test_join_handle_returns_output() {
+    let mut executor = Executor::new();
+    let handle = executor.spawner().spawn(async { 6 * 7 });
+    assert!(!handle.is_finished());
+    executor.run_ready_tasks();
+    assert!(handle.is_finished());
+    let result = Arc::new(Mutex::new(None));
+    let output = result.clone();
+    executor.spawn(Task::new(async move {
+        *output.lock() = Some(handle.await);
+    }));
+    executor.run_ready_tasks();
+    assert_eq!(*result.lock(), Some(Ok(42)));
+}
+#[test_case]
+fn test_spawn_from_task() {
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    let handle = executor.spawner().spawn(async move {
+        let inner = spawner.spawn(async { 1 });
+        inner.await.unwrap() + 1
+    });
+    executor.run_ready_tasks();
+    assert!(handle.is_finished());
+}
+#[test_case]
+fn test_queue_grows() {
+    static DONE: AtomicU64 = AtomicU64::new(0);
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    // Way more than the old fixed capacity of 100
+    let handles: Vec<_> = (0..500)
+        .map(|_| {
+            spawner.spawn(async {
+                // Go through the woken queue once
+                let mut yielded = false;
+                core::future::poll_fn(|context| {
+                    if yielded {
+                        return Poll::Ready(());
+                    }
+                    yielded = true;
+                    context.waker().wake_by_ref();
+                    Poll::Pending
+                })
+                .await;
+                DONE.fetch_add(1, Ordering::Relaxed);
+            })
+        })
+        .collect();
+    executor.run_ready_tasks();
+    assert_eq!(DONE.load(Ordering::Relaxed), 500);
+    assert!(handles.iter().all(JoinHandle::is_finished));
+}
+#[test_case]
+fn test_spawn_from_thread() {
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    let handle = thread::spawn_thread(move || spawner.spawn(async { 7 }))
+        .join()
+        .unwrap();
+    executor.run_ready_tasks();
+    assert!(handle.is_finished());
+}
+#[test_case]
+fn test_dropped_executor_cancels() {
+    let executor = Executor::new();
+    let spawner = executor.spawner();
+    let pending = spawner.spawn(core::future::pending::<()>());
+    drop(executor);
+    let late = spawner.spawn(async {});
+    assert!(pending.is_finished());
+    assert!(late.is_finished());
+    let mut executor = Executor::new();
+    let result = Arc::new(Mutex::new(None));
+    let output = result.clone();
+    executor.spawn(Task::new(async move {
+        *output.lock() = Some(late.await);
+    }));
+    executor.run_ready_tasks();
+    assert_eq!(*result.lock(), Some(Err(JoinError::Cancelled)));
+}
+#[test_case]
+fn test_task_info() {
+    let mut executor = Executor::new();
+    let handle = executor
+        .spawner()
+        .builder()
+        .name("forever")
+        .nice(Nice::new(5))
+        .spawn(core::future::pending::<()>());
+    executor.spawn(Task::new(async {}).with_name("done"));
+    executor.run_ready_tasks();
+    let tasks = executor.tasks();
+    assert_eq!(tasks.len(), 1);
+    let info = &tasks[0];
+    assert_eq!(info.id, handle.task_id());
+    assert_eq!(info.name, "forever");
+    assert_eq!(info.nice, Nice::new(5));
+    assert_eq!(info.state, TaskState::Waiting);
+    assert_eq!(info.stats.runs, 1);
+    assert!(info.last_polled.is_some());
+    let mut table = String::new();
+    executor.spawner().dump(&mut table).unwrap();
+    assert!(table.starts_with(PS_HEADER));
+    assert!(table.contains("waiting"));
+    assert!(table.contains("forever"));
+}
+#[test_case]
+fn test_cancel() {
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    let running = spawner.spawn(core::future::pending::<()>());
+    executor.run_ready_tasks();
+    let queued = spawner.spawn(core::future::pending::<()>());
+    // Not picked up by the executor yet, dropped right away
+    assert!(queued.cancel());
+    assert!(queued.is_finished());
+    assert!(running.cancel());
+    assert!(!running.is_finished());
+    executor.run_ready_tasks();
+    assert!(running.is_finished());
+    assert!(executor.tasks().is_empty());
+    assert!(!running.cancel());
+    let result = Arc::new(Mutex::new(None));
+    let output = result.clone();
+    executor.spawn(Task::new(async move {
+        *output.lock() = Some(running.await);
+    }));
+    executor.run_ready_tasks();
+    assert_eq!(*result.lock(), Some(Err(JoinError::Cancelled)));
+}
@@ -1,5 +1,5 @@
 //! Keyboard task module
-use crate::{print, println};
+use crate::print;
 use conquer_once::spin::OnceCell;
 use core::{
     pin::Pin,
@@ -10,23 +10,30 @@ use futures_util::{
     stream::{Stream, StreamExt},
     task::AtomicWaker,
 };
+use log::warn;
 use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
 /// The scancode queue for keyboard input
 static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+/// Scancode of F12 being pressed, lists the tasks over serial
+const PS_SCANCODE: u8 = 0x58;
 /// Add a scancode to the scancode queue
 pub(crate) fn add_scancode(scancode: u8) {
+    // Handled here so it works even while a task keeps the executor busy
+    if scancode == PS_SCANCODE {
+        super::executor::ps();
+    }
     // Try to get the scancode queue
     if let Ok(queue) = SCANCODE_QUEUE.try_get() {
         if let Err(_) = queue.push(scancode) {
             // If the scancode queue is full, print a warning
-            println!("WARNING: scancode queue full; dropping keyboard input");
+            warn!("scancode queue full; dropping keyboard input");
         } else {
             // Else, wake the keyboard task
             WAKER.wake();
         }
     } else {
         // If the scancode queue is not initialized, print a warning
-        println!("WARNING: scancode queue uninitialized");
+        warn!("scancode queue uninitialized");
     }
 }
 /// The scancode stream
@@ -1,21 +1,31 @@
 // TO DO : https://os.phil-opp.com/async-await/#possible-extensions
 //! Task module
 
-use alloc::boxed::Box;
+use crate::time::{Duration, Instant};
+use alloc::{borrow::Cow, boxed::Box};
 use core::{
+    fmt,
     future::Future,
     pin::Pin,
     sync::atomic::{AtomicU64, Ordering},
     task::{Context, Poll},
 };
+use scheduler::{Nice, SchedStats};
 
 pub mod executor;
 pub mod keyboard;
+pub mod scheduler;
 pub mod simple_executor;
+pub mod sync;
+pub mod thread;
+pub mod timer;
 /// A task that can be executed by the executor
 pub struct Task {
     id: TaskId,
     future: Pin<Box<dyn Future<Output = ()>>>,
+    name: Cow<'static, str>,
+    nice: Nice,
+    spawned: Instant,
 }
 
 impl Task {
@@ -24,17 +34,96 @@ impl Task {
         Task {
             id: TaskId::new(),
             future: Box::pin(future),
+            name: Cow::Borrowed("unnamed"),
+            nice: Nice::DEFAULT,
+            spawned: Instant::now(),
         }
     }
+    /// Set the name shown when the tasks are listed
+    pub fn with_name(mut self, name: impl Into<Cow<'static, str>>) -> Task {
+        self.name = name.into();
+        self
+    }
+    /// Set the priority of the task for the priority policy
+    pub fn with_nice(mut self, nice: Nice) -> Task {
+        self.nice = nice;
+        self
+    }
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn nice(&self) -> Nice {
+        self.nice
+    }
     /// Poll the task
     fn poll(&mut self, context: &mut Context) -> Poll<()> {
         self.future.as_mut().poll(context)
     }
 }
 
+/// What a task of the executor is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    /// Waiting in the run queue
+    Ready,
+    /// Being polled
+    Running,
+    /// Waiting to be woken
+    Waiting,
+}
+impl fmt::Display for TaskState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TaskState::Ready => "ready",
+            TaskState::Running => "running",
+            TaskState::Waiting => "waiting",
+        };
+        f.pad(name)
+    }
+}
+
+/// What the executor knows about one of its tasks
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub id: TaskId,
+    pub name: Cow<'static, str>,
+    pub nice: Nice,
+    pub state: TaskState,
+    /// When the task was created
+    pub spawned: Instant,
+    /// When the latest poll started, `None` before the first one
+    pub last_polled: Option<Instant>,
+    /// `runs` counts the polls and `runtime` the time spent in them
+    pub stats: SchedStats,
+}
+impl TaskInfo {
+    fn new(task: &Task) -> Self {
+        TaskInfo {
+            id: task.id,
+            name: task.name.clone(),
+            nice: task.nice,
+            state: TaskState::Ready,
+            spawned: task.spawned,
+            last_polled: None,
+            stats: SchedStats::default(),
+        }
+    }
+    /// Time since the task was created
+    pub fn age(&self) -> Duration {
+        self.spawned.elapsed()
+    }
+    /// Time since the latest poll started, how long it has been polled while running
+    pub fn since_polled(&self) -> Option<Duration> {
+        self.last_polled.map(|start| start.elapsed())
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 /// An identifier for a task
-struct TaskId(u64);
+pub struct TaskId(u64);
 
 impl TaskId {
     /// Create a new TaskId with a unique identifier
@@ -43,4 +132,12 @@ impl TaskId {
         static NEXT_ID: AtomicU64 = AtomicU64::new(0);
         TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
     }
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
 }
@@ -0,0 +1,172 @@
+//! Scheduling policies shared by the executor and the kernel threads
+//!
+//! Both keep whatever is ready to run in a `RunQueue`, which decides the order
+//! according to its `Policy`. With the priority policy an entry gains one nice
+//! level for every `AGING_ROUNDS` picks it was passed over, so even the least
+//! important work eventually runs.
+use crate::boot_params::BootParam;
+use crate::time::Duration;
+use alloc::collections::VecDeque;
+use core::fmt;
+use ferrum_parse::cmdline::FromParam;
+use ferrum_parse::ParseError;
+
+/// Policy of the run queues, `sched.policy=rr` or `sched.policy=priority`
+pub static POLICY: BootParam<Policy> =
+    BootParam::new("sched.policy", "scheduling policy", Policy::Priority);
+
+/// Picks passed over that are worth one nice level
+pub const AGING_ROUNDS: u64 = 4;
+
+/// How a run queue orders its entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// First in first out, nice values are ignored
+    RoundRobin,
+    /// Lowest nice value first, with aging against starvation
+    Priority,
+}
+impl FromParam<'_> for Policy {
+    fn from_param(value: Option<&str>) -> Result<Self, ParseError> {
+        match value {
+            Some("rr" | "round-robin") => Ok(Policy::RoundRobin),
+            Some("priority") => Ok(Policy::Priority),
+            _ => Err(ParseError::InvalidValue),
+        }
+    }
+}
+
+/// Unix style niceness, from -20 (most important) to 19
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Nice(i8);
+impl Nice {
+    pub const MIN: Nice = Nice(-20);
+    pub const MAX: Nice = Nice(19);
+    pub const DEFAULT: Nice = Nice(0);
+    /// Clamp `value` to the valid range
+    pub const fn new(value: i8) -> Self {
+        if value < Self::MIN.0 {
+            Self::MIN
+        } else if value > Self::MAX.0 {
+            Self::MAX
+        } else {
+            Nice(value)
+        }
+    }
+    pub const fn get(&self) -> i8 {
+        self.0
+    }
+}
+impl fmt::Display for Nice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// What the scheduler measured about a task or thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SchedStats {
+    /// Time spent running
+    pub runtime: Duration,
+    /// Times it was picked to run
+    pub runs: u64,
+    /// Times it was woken after waiting
+    pub wakeups: u64,
+}
+impl SchedStats {
+    /// Account for one run that lasted `duration`
+    pub fn record_run(&mut self, duration: Duration) {
+        self.runtime += duration;
+        self.runs += 1;
+    }
+    pub fn record_wakeup(&mut self) {
+        self.wakeups += 1;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry<K> {
+    key: K,
+    nice: Nice,
+    /// Value of `RunQueue::round` when the entry was queued
+    round: u64,
+}
+
+/// Work that is ready to run, ordered by a `Policy`
+#[derive(Debug)]
+pub struct RunQueue<K> {
+    policy: Policy,
+    entries: VecDeque<Entry<K>>,
+    /// Number of picks so far, measures how long entries wait
+    round: u64,
+}
+impl<K: Copy + PartialEq> RunQueue<K> {
+    pub const fn new(policy: Policy) -> Self {
+        RunQueue {
+            policy,
+            entries: VecDeque::new(),
+            round: 0,
+        }
+    }
+    pub fn policy(&self) -> Policy {
+        self.policy
+    }
+    pub fn set_policy(&mut self, policy: Policy) {
+        self.policy = policy;
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    pub fn contains(&self, key: K) -> bool {
+        self.entries.iter().any(|entry| entry.key == key)
+    }
+    /// Make room for `additional` more entries, `push` does not allocate until they are used
+    pub fn reserve(&mut self, additional: usize) {
+        self.entries.reserve(additional);
+    }
+    pub fn push(&mut self, key: K, nice: Nice) {
+        self.entries.push_back(Entry {
+            key,
+            nice,
+            round: self.round,
+        });
+    }
+    /// Take the entry to run next
+    pub fn pop(&mut self) -> Option<K> {
+        let index = match self.policy {
+            Policy::RoundRobin => 0,
+            Policy::Priority => self.best_index()?,
+        };
+        self.round += 1;
+        self.entries.remove(index).map(|entry| entry.key)
+    }
+    /// Remove `key` from the queue, returns false if it was not queued
+    pub fn remove(&mut self, key: K) -> bool {
+        match self.entries.iter().position(|entry| entry.key == key) {
+            Some(index) => self.entries.remove(index).is_some(),
+            None => false,
+        }
+    }
+    /// Effective priority of an entry with `nice` queued right now, lower runs first
+    pub fn priority_of(nice: Nice) -> i64 {
+        (nice.get() as i64 - Nice::MIN.get() as i64) * AGING_ROUNDS as i64
+    }
+    /// Effective priority of `entry`, the entry queued first wins ties
+    fn score(&self, entry: &Entry<K>) -> i64 {
+        Self::priority_of(entry.nice) - (self.round - entry.round) as i64
+    }
+    fn best_index(&self) -> Option<usize> {
+        // `min_by_key` keeps the first of equal elements, the oldest entry
+        self.entries
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, entry)| self.score(entry))
+            .map(|(index, _)| index)
+    }
+}
+
+#[cfg(test)]
+mod tests;
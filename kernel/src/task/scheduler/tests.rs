@@ -0,0 +1,78 @@
+use super::*;
+use alloc::vec::Vec;
+
+fn drain(queue: &mut RunQueue<u32>) -> Vec<u32> {
+    core::iter::from_fn(|| queue.pop()).collect()
+}
+
+#[test_case]
+fn test_round_robin_order() {
+    let mut queue = RunQueue::new(Policy::RoundRobin);
+    queue.push(1, Nice::new(10));
+    queue.push(2, Nice::MIN);
+    queue.push(3, Nice::DEFAULT);
+    assert_eq!(queue.len(), 3);
+    assert_eq!(drain(&mut queue), [1, 2, 3]);
+    assert!(queue.is_empty());
+}
+#[test_case]
+fn test_priority_order() {
+    let mut queue = RunQueue::new(Policy::Priority);
+    queue.push(1, Nice::new(10));
+    queue.push(2, Nice::MIN);
+    queue.push(3, Nice::DEFAULT);
+    queue.push(4, Nice::DEFAULT);
+    // Equal priorities keep their queuing order
+    assert_eq!(drain(&mut queue), [2, 3, 4, 1]);
+}
+#[test_case]
+fn test_aging_prevents_starvation() {
+    let mut queue = RunQueue::new(Policy::Priority);
+    queue.push(0, Nice::new(1));
+    // A busy entry one nice level better is requeued after every run
+    let mut picks = 0;
+    loop {
+        queue.push(1, Nice::DEFAULT);
+        if queue.pop() == Some(0) {
+            break;
+        }
+        picks += 1;
+        assert!(picks <= AGING_ROUNDS, "starved");
+    }
+    assert_eq!(picks, AGING_ROUNDS);
+}
+#[test_case]
+fn test_remove() {
+    let mut queue = RunQueue::new(Policy::Priority);
+    queue.push(1, Nice::DEFAULT);
+    queue.push(2, Nice::DEFAULT);
+    assert!(queue.contains(2));
+    assert!(queue.remove(2));
+    assert!(!queue.remove(2));
+    assert!(!queue.contains(2));
+    assert_eq!(drain(&mut queue), [1]);
+}
+#[test_case]
+fn test_nice_is_clamped() {
+    assert_eq!(Nice::new(-100), Nice::MIN);
+    assert_eq!(Nice::new(100), Nice::MAX);
+    assert_eq!(Nice::new(5).get(), 5);
+    assert!(Nice::MIN < Nice::DEFAULT);
+}
+#[test_case]
+fn test_policy_from_param() {
+    assert_eq!(Policy::from_param(Some("rr")), Ok(Policy::RoundRobin));
+    assert_eq!(Policy::from_param(Some("priority")), Ok(Policy::Priority));
+    assert!(Policy::from_param(Some("fair")).is_err());
+    assert!(Policy::from_param(None).is_err());
+}
+#[test_case]
+fn test_stats() {
+    let mut stats = SchedStats::default();
+    stats.record_run(Duration::from_millis(3));
+    stats.record_run(Duration::from_millis(4));
+    stats.record_wakeup();
+    assert_eq!(stats.runtime, Duration::from_millis(7));
+    assert_eq!(stats.runs, 2);
+    assert_eq!(stats.wakeups, 1);
+}
@@ -0,0 +1,172 @@
+//! Async synchronization primitives for kernel tasks
+//!
+//! Everything is built on `WaitQueue`: a waiter checks its condition,
+//! registers its `Waker` and checks again, so a notification between the two
+//! checks is never lost. Waking only pops from the queue, so notifying and the
+//! non blocking `try_*` operations can be used from interrupt handlers.
+//!
+//! The futures also work on kernel threads through `thread::block_on`.
+pub mod mpsc;
+pub mod mutex;
+pub mod notify;
+pub mod oneshot;
+pub mod rwlock;
+pub mod semaphore;
+
+use alloc::collections::VecDeque;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+struct WaitList {
+    next_key: u64,
+    /// Waiters in the order they registered
+    waiters: VecDeque<(u64, Waker)>,
+}
+
+/// Tasks waiting for a condition to become true
+///
+/// A notified waiter that is dropped before it could use the notification
+/// passes it on to the next waiter.
+pub struct WaitQueue {
+    // Only locked with interrupts disabled, never while a waker runs
+    list: Mutex<WaitList>,
+}
+impl WaitQueue {
+    pub const fn new() -> Self {
+        WaitQueue {
+            list: Mutex::new(WaitList {
+                next_key: 0,
+                waiters: VecDeque::new(),
+            }),
+        }
+    }
+    /// Number of registered waiters
+    pub fn len(&self) -> usize {
+        without_interrupts(|| self.list.lock().waiters.len())
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Wait until `condition` returns a value
+    ///
+    /// The condition is checked when the future is first polled and every time
+    /// the waiter is notified.
+    pub fn wait_until<T, F>(&self, condition: F) -> WaitUntil<'_, F>
+    where
+        F: FnMut() -> Option<T>,
+    {
+        WaitUntil {
+            queue: self,
+            condition,
+            key: None,
+        }
+    }
+    /// Wake the oldest waiter, returns false if there was none
+    pub fn notify_one(&self) -> bool {
+        let waiter = without_interrupts(|| self.list.lock().waiters.pop_front());
+        match waiter {
+            Some((_, waker)) => {
+                waker.wake();
+                true
+            }
+            None => false,
+        }
+    }
+    /// Wake every waiter, returns how many there were
+    pub fn notify_all(&self) -> usize {
+        let waiters = without_interrupts(|| core::mem::take(&mut self.list.lock().waiters));
+        let count = waiters.len();
+        for (_, waker) in waiters {
+            waker.wake();
+        }
+        count
+    }
+    /// Add or refresh a waiter, returns its key
+    fn register(&self, key: Option<u64>, waker: &Waker) -> u64 {
+        without_interrupts(|| {
+            let mut list = self.list.lock();
+            if let Some(key) = key {
+                if let Some((_, registered)) = list.waiters.iter_mut().find(|(k, _)| *k == key) {
+                    if !registered.will_wake(waker) {
+                        *registered = waker.clone();
+                    }
+                    return key;
+                }
+            }
+            let key = list.next_key;
+            list.next_key += 1;
+            list.waiters.push_back((key, waker.clone()));
+            key
+        })
+    }
+    /// Remove a waiter, returns false if it was notified already
+    fn unregister(&self, key: u64) -> bool {
+        without_interrupts(|| {
+            let mut list = self.list.lock();
+            match list.waiters.iter().position(|(k, _)| *k == key) {
+                Some(index) => list.waiters.remove(index).is_some(),
+                None => false,
+            }
+        })
+    }
+}
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Future returned by `WaitQueue::wait_until`
+pub struct WaitUntil<'a, F> {
+    queue: &'a WaitQueue,
+    condition: F,
+    /// Key of the waiter while registered or notified
+    key: Option<u64>,
+}
+impl<F> Unpin for WaitUntil<'_, F> {}
+impl<T, F> Future for WaitUntil<'_, F>
+where
+    F: FnMut() -> Option<T>,
+{
+    type Output = T;
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<T> {
+        if let Some(value) = (self.condition)() {
+            self.finish();
+            return Poll::Ready(value);
+        }
+        let key = self.queue.register(self.key, context.waker());
+        self.key = Some(key);
+        // A notification may have come before the waiter was registered
+        if let Some(value) = (self.condition)() {
+            self.finish();
+            return Poll::Ready(value);
+        }
+        Poll::Pending
+    }
+}
+impl<F> WaitUntil<'_, F> {
+    /// Leave the queue once the condition is met
+    fn finish(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.queue.unregister(key);
+        }
+    }
+}
+impl<F> Drop for WaitUntil<'_, F> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            // Notified but dropped before checking, let another waiter have it
+            if !self.queue.unregister(key) {
+                self.queue.notify_one();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
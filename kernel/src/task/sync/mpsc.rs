@@ -0,0 +1,202 @@
+//! Bounded multi-producer, single-consumer channel
+//!
+//! The buffer is allocated when the channel is created and never grows, so
+//! `Sender::try_send` can be used from interrupt handlers.
+use super::WaitQueue;
+use alloc::{collections::VecDeque, sync::Arc};
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+struct Inner<T> {
+    buffer: Mutex<VecDeque<T>>,
+    capacity: usize,
+    senders: AtomicUsize,
+    receiver_dropped: AtomicBool,
+    /// The receiver waits here for values
+    not_empty: WaitQueue,
+    /// Senders wait here for room
+    not_full: WaitQueue,
+}
+impl<T> Inner<T> {
+    /// Queue `value` if there is room
+    fn push(&self, value: T) -> Result<(), TrySendError<T>> {
+        if self.receiver_dropped.load(Ordering::Acquire) {
+            return Err(TrySendError::Closed(value));
+        }
+        without_interrupts(|| {
+            let mut buffer = self.buffer.lock();
+            if buffer.len() == self.capacity {
+                return Err(TrySendError::Full(value));
+            }
+            buffer.push_back(value);
+            Ok(())
+        })?;
+        self.not_empty.notify_one();
+        Ok(())
+    }
+    /// Take the oldest value
+    fn pop(&self) -> Result<T, TryRecvError> {
+        // Checked before taking the value, a sender may queue one and leave in between
+        let disconnected = self.senders.load(Ordering::Acquire) == 0;
+        match without_interrupts(|| self.buffer.lock().pop_front()) {
+            Some(value) => {
+                self.not_full.notify_one();
+                Ok(value)
+            }
+            None if disconnected => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+}
+
+/// Create a channel holding at most `capacity` values
+///
+/// Panics if `capacity` is zero.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "mpsc channel capacity must not be zero");
+    let inner = Arc::new(Inner {
+        buffer: Mutex::new(VecDeque::with_capacity(capacity)),
+        capacity,
+        senders: AtomicUsize::new(1),
+        receiver_dropped: AtomicBool::new(false),
+        not_empty: WaitQueue::new(),
+        not_full: WaitQueue::new(),
+    });
+    (
+        Sender {
+            inner: inner.clone(),
+        },
+        Receiver { inner },
+    )
+}
+
+/// The receiver was dropped, the value is given back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "receiver dropped")
+    }
+}
+/// Why `Sender::try_send` did not queue the value, which is given back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    /// The channel is at capacity
+    Full(T),
+    /// The receiver was dropped
+    Closed(T),
+}
+impl<T> TrySendError<T> {
+    pub fn into_inner(self) -> T {
+        match self {
+            TrySendError::Full(value) | TrySendError::Closed(value) => value,
+        }
+    }
+}
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => write!(f, "channel full"),
+            TrySendError::Closed(_) => write!(f, "receiver dropped"),
+        }
+    }
+}
+
+/// Sending half of a channel, can be cloned
+pub struct Sender<T> {
+    inner: Arc<Inner<T>>,
+}
+impl<T> Sender<T> {
+    /// Wait for room in the channel and queue `value`
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut value = Some(value);
+        self.inner
+            .not_full
+            .wait_until(|| {
+                let pending = value.take().expect("value already sent");
+                match self.inner.push(pending) {
+                    Ok(()) => Some(Ok(())),
+                    Err(TrySendError::Closed(pending)) => Some(Err(SendError(pending))),
+                    Err(TrySendError::Full(pending)) => {
+                        value = Some(pending);
+                        None
+                    }
+                }
+            })
+            .await
+    }
+    /// Queue `value` if there is room, never waits
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.inner.push(value)
+    }
+    /// Whether the receiver was dropped
+    pub fn is_closed(&self) -> bool {
+        self.inner.receiver_dropped.load(Ordering::Acquire)
+    }
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity
+    }
+}
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.inner.senders.fetch_add(1, Ordering::Relaxed);
+        Sender {
+            inner: self.inner.clone(),
+        }
+    }
+}
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.inner.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // The last sender is gone, let the receiver see the end
+            self.inner.not_empty.notify_all();
+        }
+    }
+}
+
+/// Receiving half of a channel
+pub struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+}
+impl<T> Receiver<T> {
+    /// Wait for the next value, `None` once every sender is dropped and the channel is empty
+    pub async fn recv(&mut self) -> Option<T> {
+        let inner = &self.inner;
+        inner
+            .not_empty
+            .wait_until(|| match inner.pop() {
+                Ok(value) => Some(Some(value)),
+                Err(TryRecvError::Disconnected) => Some(None),
+                Err(TryRecvError::Empty) => None,
+            })
+            .await
+    }
+    /// Take the next value if there is one, never waits
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.inner.pop()
+    }
+    /// Number of values waiting in the channel
+    pub fn len(&self) -> usize {
+        without_interrupts(|| self.inner.buffer.lock().len())
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.inner.receiver_dropped.store(true, Ordering::Release);
+        self.inner.not_full.notify_all();
+    }
+}
+/// Why `Receiver::try_recv` returned no value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    /// Every sender was dropped and the channel is empty
+    Disconnected,
+}
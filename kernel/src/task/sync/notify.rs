@@ -0,0 +1,84 @@
+//! Notifications and events between tasks
+use super::WaitQueue;
+use core::future::Future;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Wakes waiting tasks without passing any data
+///
+/// A `notify_one` without waiters is remembered and completes the next wait.
+pub struct Notify {
+    /// Stored by `notify_one` when nobody waits
+    permit: AtomicBool,
+    /// Incremented by `notify_all`
+    generation: AtomicU64,
+    waiters: WaitQueue,
+}
+impl Notify {
+    pub const fn new() -> Self {
+        Notify {
+            permit: AtomicBool::new(false),
+            generation: AtomicU64::new(0),
+            waiters: WaitQueue::new(),
+        }
+    }
+    /// Wait for `notify_one`, or a `notify_all` made after this call
+    pub fn notified(&self) -> impl Future<Output = ()> + '_ {
+        let generation = self.generation.load(Ordering::Acquire);
+        self.waiters.wait_until(move || {
+            let woken = self.generation.load(Ordering::Acquire) != generation
+                || self.permit.swap(false, Ordering::AcqRel);
+            woken.then_some(())
+        })
+    }
+    /// Wake one waiting task, or the next one to wait
+    pub fn notify_one(&self) {
+        self.permit.store(true, Ordering::Release);
+        self.waiters.notify_one();
+    }
+    /// Wake every task waiting right now
+    pub fn notify_all(&self) {
+        self.generation.fetch_add(1, Ordering::Release);
+        self.waiters.notify_all();
+    }
+}
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A flag tasks can wait on, stays set until cleared
+pub struct Event {
+    set: AtomicBool,
+    waiters: WaitQueue,
+}
+impl Event {
+    pub const fn new() -> Self {
+        Event {
+            set: AtomicBool::new(false),
+            waiters: WaitQueue::new(),
+        }
+    }
+    pub fn is_set(&self) -> bool {
+        self.set.load(Ordering::Acquire)
+    }
+    /// Set the flag and wake every waiting task
+    pub fn set(&self) {
+        self.set.store(true, Ordering::Release);
+        self.waiters.notify_all();
+    }
+    pub fn clear(&self) {
+        self.set.store(false, Ordering::Release);
+    }
+    /// Wait until the flag is set
+    pub async fn wait(&self) {
+        self.waiters
+            .wait_until(|| self.is_set().then_some(()))
+            .await
+    }
+}
+impl Default for Event {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,108 @@
+//! Channel sending a single value
+use alloc::sync::Arc;
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+};
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+struct Inner<T> {
+    value: Mutex<Option<T>>,
+    /// The sender sent its value or was dropped
+    sender_done: AtomicBool,
+    receiver_dropped: AtomicBool,
+    waker: AtomicWaker,
+}
+
+/// Create a channel for a single value
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let inner = Arc::new(Inner {
+        value: Mutex::new(None),
+        sender_done: AtomicBool::new(false),
+        receiver_dropped: AtomicBool::new(false),
+        waker: AtomicWaker::new(),
+    });
+    (
+        Sender {
+            inner: inner.clone(),
+        },
+        Receiver { inner },
+    )
+}
+
+/// The sender was dropped without sending a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sender dropped without sending")
+    }
+}
+
+/// Sending half of a oneshot channel, can be used from interrupt handlers
+pub struct Sender<T> {
+    inner: Arc<Inner<T>>,
+}
+impl<T> Sender<T> {
+    /// Send the value, gives it back if the receiver was dropped
+    pub fn send(self, value: T) -> Result<(), T> {
+        if self.is_closed() {
+            return Err(value);
+        }
+        without_interrupts(|| *self.inner.value.lock() = Some(value));
+        // Dropping the sender wakes the receiver
+        Ok(())
+    }
+    /// Whether the receiver was dropped
+    pub fn is_closed(&self) -> bool {
+        self.inner.receiver_dropped.load(Ordering::Acquire)
+    }
+}
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.inner.sender_done.store(true, Ordering::Release);
+        self.inner.waker.wake();
+    }
+}
+
+/// Receiving half of a oneshot channel, a future of the value
+pub struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+}
+impl<T> Receiver<T> {
+    /// Take the value if it was sent already
+    pub fn try_recv(&mut self) -> Option<Result<T, RecvError>> {
+        if let Some(value) = without_interrupts(|| self.inner.value.lock().take()) {
+            return Some(Ok(value));
+        }
+        // The value is stored before the sender is marked as done
+        if self.inner.sender_done.load(Ordering::Acquire) {
+            let value = without_interrupts(|| self.inner.value.lock().take());
+            return Some(value.ok_or(RecvError));
+        }
+        None
+    }
+}
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Some(result) = self.try_recv() {
+            return Poll::Ready(result);
+        }
+        self.inner.waker.register(context.waker());
+        match self.try_recv() {
+            Some(result) => Poll::Ready(result),
+            None => Poll::Pending,
+        }
+    }
+}
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.inner.receiver_dropped.store(true, Ordering::Release);
+    }
+}
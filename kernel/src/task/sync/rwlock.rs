@@ -0,0 +1,119 @@
+//! Reader-writer lock whose lock operations are futures
+use super::WaitQueue;
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Value of `state` while a writer holds the lock, otherwise the number of readers
+const WRITER: usize = usize::MAX;
+
+/// A lock held by any number of readers or by a single writer
+///
+/// Waiters are woken in the order they arrived, but a reader can still join
+/// the readers holding the lock while a writer waits.
+pub struct RwLock<T: ?Sized> {
+    state: AtomicUsize,
+    waiters: WaitQueue,
+    value: UnsafeCell<T>,
+}
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub const fn new(value: T) -> Self {
+        RwLock {
+            state: AtomicUsize::new(0),
+            waiters: WaitQueue::new(),
+            value: UnsafeCell::new(value),
+        }
+    }
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+impl<T: ?Sized> RwLock<T> {
+    /// Wait until no writer holds the lock and take a shared reference
+    pub async fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.waiters.wait_until(|| self.try_read()).await
+    }
+    /// Wait until nobody holds the lock and take a unique reference
+    pub async fn write(&self) -> RwLockWriteGuard<'_, T> {
+        self.waiters.wait_until(|| self.try_write()).await
+    }
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        let mut readers = self.state.load(Ordering::Relaxed);
+        // One less than WRITER so the count can not become WRITER
+        while readers < WRITER - 1 {
+            match self.state.compare_exchange_weak(
+                readers,
+                readers + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(RwLockReadGuard { lock: self }),
+                Err(current) => readers = current,
+            }
+        }
+        None
+    }
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        self.state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| RwLockWriteGuard { lock: self })
+    }
+    /// Number of readers holding the lock, `None` while a writer holds it
+    pub fn readers(&self) -> Option<usize> {
+        match self.state.load(Ordering::Relaxed) {
+            WRITER => None,
+            readers => Some(readers),
+        }
+    }
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+/// Shared access to the value of a `RwLock`
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        // Only writers wait while readers hold the lock
+        if self.lock.state.fetch_sub(1, Ordering::Release) == 1 {
+            self.lock.waiters.notify_one();
+        }
+    }
+}
+
+/// Unique access to the value of a `RwLock`
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.store(0, Ordering::Release);
+        // Every waiting reader can go, a waiting writer retries
+        self.lock.waiters.notify_all();
+    }
+}
@@ -0,0 +1,82 @@
+//! Counting semaphore
+use super::WaitQueue;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A pool of permits, tasks wait while not enough are available
+pub struct Semaphore {
+    permits: AtomicUsize,
+    waiters: WaitQueue,
+}
+impl Semaphore {
+    pub const fn new(permits: usize) -> Self {
+        Semaphore {
+            permits: AtomicUsize::new(permits),
+            waiters: WaitQueue::new(),
+        }
+    }
+    pub fn available_permits(&self) -> usize {
+        self.permits.load(Ordering::Relaxed)
+    }
+    /// Wait for a permit
+    pub async fn acquire(&self) -> SemaphorePermit<'_> {
+        self.acquire_many(1).await
+    }
+    /// Wait until `count` permits are available and take them all at once
+    pub async fn acquire_many(&self, count: usize) -> SemaphorePermit<'_> {
+        self.waiters
+            .wait_until(|| self.try_acquire_many(count))
+            .await
+    }
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.try_acquire_many(1)
+    }
+    pub fn try_acquire_many(&self, count: usize) -> Option<SemaphorePermit<'_>> {
+        let mut permits = self.permits.load(Ordering::Relaxed);
+        while let Some(left) = permits.checked_sub(count) {
+            match self.permits.compare_exchange_weak(
+                permits,
+                left,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    return Some(SemaphorePermit {
+                        semaphore: self,
+                        count,
+                    })
+                }
+                Err(current) => permits = current,
+            }
+        }
+        None
+    }
+    /// Return `count` permits to the pool, or add new ones
+    pub fn add_permits(&self, count: usize) {
+        self.permits.fetch_add(count, Ordering::Release);
+        // Waiters may want different counts, let all of them check
+        self.waiters.notify_all();
+    }
+}
+
+/// Permits taken from a `Semaphore`, given back when dropped
+#[must_use = "the permits are released right away if not used"]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    count: usize,
+}
+impl SemaphorePermit<'_> {
+    pub fn count(&self) -> usize {
+        self.count
+    }
+    /// Keep the permits out of the pool for good
+    pub fn forget(mut self) {
+        self.count = 0;
+    }
+}
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        if self.count > 0 {
+            self.semaphore.add_permits(self.count);
+        }
+    }
+}
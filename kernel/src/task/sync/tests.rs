@@ -0,0 +1,177 @@
+use super::mpsc::{self, TryRecvError, TrySendError};
+use super::mutex::Mutex as AsyncMutex;
+use super::notify::{Event, Notify};
+use super::oneshot::{self, RecvError};
+use super::rwlock::RwLock;
+use super::semaphore::Semaphore;
+use super::*;
+use crate::task::thread;
+use alloc::boxed::Box;
+use core::pin::pin;
+
+/// Poll `future` once with a waker that does nothing
+fn poll_once<F: Future>(future: Pin<&mut F>) -> Poll<F::Output> {
+    future.poll(&mut Context::from_waker(Waker::noop()))
+}
+
+#[test_case]
+fn test_wait_queue_notify_one() {
+    let queue = WaitQueue::new();
+    let mut first = pin!(queue.wait_until(|| None::<()>));
+    let mut second = pin!(queue.wait_until(|| None::<()>));
+    assert!(poll_once(first.as_mut()).is_pending());
+    assert!(poll_once(second.as_mut()).is_pending());
+    assert_eq!(queue.len(), 2);
+    assert!(queue.notify_one());
+    assert_eq!(queue.len(), 1);
+    assert_eq!(queue.notify_all(), 1);
+    assert!(!queue.notify_one());
+}
+#[test_case]
+fn test_wait_queue_passes_on_notification() {
+    let queue = WaitQueue::new();
+    let mut first = Box::pin(queue.wait_until(|| None::<()>));
+    let mut second = pin!(queue.wait_until(|| None::<()>));
+    assert!(poll_once(first.as_mut()).is_pending());
+    assert!(poll_once(second.as_mut()).is_pending());
+    queue.notify_one();
+    // The notified waiter goes away, the other one gets the notification
+    drop(first);
+    assert!(queue.is_empty());
+}
+#[test_case]
+fn test_mutex() {
+    let mutex = AsyncMutex::new(0);
+    let mut guard = thread::block_on(mutex.lock());
+    *guard += 1;
+    let mut waiting = pin!(mutex.lock());
+    assert!(poll_once(waiting.as_mut()).is_pending());
+    assert!(mutex.try_lock().is_none());
+    drop(guard);
+    match poll_once(waiting.as_mut()) {
+        Poll::Ready(guard) => assert_eq!(*guard, 1),
+        Poll::Pending => panic!("mutex not handed over"),
+    }
+    assert!(!mutex.is_locked());
+}
+#[test_case]
+fn test_rwlock() {
+    let lock = RwLock::new(5);
+    let first = thread::block_on(lock.read());
+    let second = lock.try_read().unwrap();
+    assert_eq!(*first + *second, 10);
+    assert_eq!(lock.readers(), Some(2));
+    let mut writer = pin!(lock.write());
+    assert!(poll_once(writer.as_mut()).is_pending());
+    drop(first);
+    assert!(poll_once(writer.as_mut()).is_pending());
+    drop(second);
+    match poll_once(writer.as_mut()) {
+        Poll::Ready(mut guard) => {
+            *guard = 6;
+            assert_eq!(lock.readers(), None);
+            assert!(lock.try_read().is_none());
+        }
+        Poll::Pending => panic!("writer not woken"),
+    }
+    assert_eq!(*lock.try_read().unwrap(), 6);
+}
+#[test_case]
+fn test_semaphore() {
+    let semaphore = Semaphore::new(2);
+    let permit = thread::block_on(semaphore.acquire());
+    assert_eq!(semaphore.available_permits(), 1);
+    let mut many = pin!(semaphore.acquire_many(2));
+    assert!(poll_once(many.as_mut()).is_pending());
+    drop(permit);
+    match poll_once(many.as_mut()) {
+        Poll::Ready(permit) => {
+            assert_eq!(permit.count(), 2);
+            permit.forget();
+        }
+        Poll::Pending => panic!("permits not handed over"),
+    }
+    assert_eq!(semaphore.available_permits(), 0);
+    semaphore.add_permits(1);
+    assert!(semaphore.try_acquire().is_some());
+}
+#[test_case]
+fn test_notify() {
+    let notify = Notify::new();
+    // Remembered without waiters
+    notify.notify_one();
+    thread::block_on(notify.notified());
+    let mut waiting = pin!(notify.notified());
+    assert!(poll_once(waiting.as_mut()).is_pending());
+    notify.notify_all();
+    assert!(poll_once(waiting.as_mut()).is_ready());
+    // Only the tasks already waiting see `notify_all`
+    let mut late = pin!(notify.notified());
+    assert!(poll_once(late.as_mut()).is_pending());
+}
+#[test_case]
+fn test_event() {
+    let event = Event::new();
+    let mut waiting = pin!(event.wait());
+    assert!(poll_once(waiting.as_mut()).is_pending());
+    event.set();
+    assert!(poll_once(waiting.as_mut()).is_ready());
+    assert!(event.is_set());
+    thread::block_on(event.wait());
+    event.clear();
+    assert!(!event.is_set());
+}
+#[test_case]
+fn test_oneshot() {
+    let (sender, receiver) = oneshot::channel();
+    let handle = thread::spawn_thread(move || sender.send(42));
+    assert_eq!(thread::block_on(receiver), Ok(42));
+    assert_eq!(handle.join(), Ok(Ok(())));
+
+    let (sender, receiver) = oneshot::channel::<u8>();
+    drop(sender);
+    assert_eq!(thread::block_on(receiver), Err(RecvError));
+
+    let (sender, receiver) = oneshot::channel();
+    drop(receiver);
+    assert!(sender.is_closed());
+    assert_eq!(sender.send(1), Err(1));
+}
+#[test_case]
+fn test_mpsc_bounded() {
+    let (sender, mut receiver) = mpsc::channel(2);
+    assert_eq!(sender.try_send(1), Ok(()));
+    assert_eq!(sender.try_send(2), Ok(()));
+    assert_eq!(sender.try_send(3), Err(TrySendError::Full(3)));
+    let mut waiting = pin!(sender.send(3));
+    assert!(poll_once(waiting.as_mut()).is_pending());
+    assert_eq!(receiver.try_recv(), Ok(1));
+    assert_eq!(poll_once(waiting.as_mut()), Poll::Ready(Ok(())));
+    assert_eq!(receiver.len(), 2);
+    assert_eq!(receiver.try_recv(), Ok(2));
+    assert_eq!(receiver.try_recv(), Ok(3));
+    assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+}
+#[test_case]
+fn test_mpsc_across_threads() {
+    let (sender, mut receiver) = mpsc::channel(4);
+    let producers: alloc::vec::Vec<_> = (0..2)
+        .map(|_| {
+            let sender = sender.clone();
+            thread::spawn_thread(move || {
+                for value in 0..50u32 {
+                    thread::block_on(sender.send(value)).unwrap();
+                }
+            })
+        })
+        .collect();
+    drop(sender);
+    let mut sum = 0;
+    while let Some(value) = thread::block_on(receiver.recv()) {
+        sum += value;
+    }
+    assert_eq!(sum, 2 * (0..50).sum::<u32>());
+    for producer in producers {
+        producer.join().unwrap();
+    }
+}
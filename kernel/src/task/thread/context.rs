@@ -0,0 +1,84 @@
+//! Register context switch between kernel threads
+use core::arch::global_asm;
+use x86_64::VirtAddr;
+
+/// What `switch_context` pops from the stack of a thread that never ran
+///
+/// The padding makes the stack 16 byte aligned when the trampoline calls the
+/// entry function.
+#[repr(C)]
+struct InitialFrame {
+    r15: u64,
+    r14: u64,
+    r13: u64,
+    r12: u64,
+    rbx: u64,
+    rbp: u64,
+    rip: u64,
+    padding: [u64; 2],
+}
+
+// switch_context(old_rsp: *mut u64, new_rsp: u64)
+//
+// Only the callee saved registers are kept, the caller saved ones are already
+// on the stack of the compiler generated call site. Always called with
+// interrupts disabled, every thread restores its own flags when it resumes.
+global_asm!(
+    ".global switch_context",
+    "switch_context:",
+    "    push rbp",
+    "    push rbx",
+    "    push r12",
+    "    push r13",
+    "    push r14",
+    "    push r15",
+    "    mov [rdi], rsp",
+    "    mov rsp, rsi",
+    "    pop r15",
+    "    pop r14",
+    "    pop r13",
+    "    pop r12",
+    "    pop rbx",
+    "    pop rbp",
+    "    ret",
+    // First instruction of every thread, entered by the `ret` above
+    ".global thread_trampoline",
+    "thread_trampoline:",
+    "    call {entry}",
+    "    ud2",
+    entry = sym super::thread_entry,
+);
+extern "C" {
+    fn switch_context(old_rsp: *mut u64, new_rsp: u64);
+    fn thread_trampoline();
+}
+
+/// Lay out the stack ending at `stack_top` so switching to it starts the thread
+///
+/// Returns the stack pointer to switch to.
+/// # Safety
+/// `stack_top` must be the 16 byte aligned end of a mapped, unused stack.
+pub(super) unsafe fn initial_stack(stack_top: VirtAddr) -> u64 {
+    let frame =
+        (stack_top.as_u64() - core::mem::size_of::<InitialFrame>() as u64) as *mut InitialFrame;
+    frame.write(InitialFrame {
+        r15: 0,
+        r14: 0,
+        r13: 0,
+        r12: 0,
+        rbx: 0,
+        rbp: 0, // Ends the backtraces
+        rip: thread_trampoline as unsafe extern "C" fn() as usize as u64,
+        padding: [0; 2],
+    });
+    frame as u64
+}
+/// Save the running thread's stack pointer in `old_rsp` and resume the thread whose stack pointer is `new_rsp`
+///
+/// Returns once another thread switches back to the saved one.
+/// # Safety
+/// Interrupts must be disabled, `old_rsp` must stay valid until the thread is
+/// resumed and `new_rsp` must come from `initial_stack` or an earlier switch.
+pub(super) unsafe fn switch(old_rsp: *mut u64, new_rsp: u64) {
+    switch_context(old_rsp, new_rsp);
+}
@@ -0,0 +1,614 @@
+//! Preemptive kernel threads
+//!
+//! Every thread runs on its own stack, allocated in the kernel VMM window with
+//! an unmapped guard page below it so an overflow faults instead of corrupting
+//! memory. A thread gives up the CPU when it blocks, yields or exits, and is
+//! preempted once its time slice is over: the end of the slice is one more
+//! deadline of the async timer queue, and the switch happens right after the
+//! timer interrupt is acknowledged.
+//!
+//! The code that booted the kernel becomes the main thread and keeps the boot
+//! stack. An idle thread halts the CPU while no other thread is ready.
+//!
+//! Ready threads are picked by a `RunQueue` following `scheduler::POLICY`, a
+//! thread made ready with a better priority than the running one preempts it.
+//!
+//! The scheduler is only locked with interrupts disabled, and threads are only
+//! woken through it, so `Waker`s of blocked threads can be used from interrupt
+//! context.
+//!
+//! There is a single scheduler and threads only run on the BSP. The functions
+//! acting on the running thread panic on an application processor, whose
+//! running code is not a thread of the scheduler.
+mod context;
+
+use crate::interrupts::exceptions;
+use crate::memory::vmm::{self, RegionKind, VmmError};
+use crate::task::scheduler::{self, Nice, Policy, RunQueue, SchedStats};
+use crate::task::timer;
+use crate::time::{Duration, Instant};
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, task::Wake, vec::Vec};
+use core::{
+    fmt,
+    future::Future,
+    mem, pin,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::interrupts::{self, without_interrupts};
+use x86_64::{structures::paging::PageTableFlags, VirtAddr};
+
+/// Usable size of the stack of a thread
+const STACK_SIZE: u64 = 64 * 1024;
+/// Unmapped bytes below every thread stack
+const GUARD_SIZE: u64 = 4096;
+/// Time a thread runs before the next ready one gets the CPU
+pub const TIME_SLICE: Duration = Duration::from_millis(10);
+
+/// Identifier of a kernel thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ThreadId(u64);
+impl ThreadId {
+    /// The thread that booted the kernel
+    pub const MAIN: ThreadId = ThreadId(0);
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        ThreadId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+impl fmt::Display for ThreadId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// What a thread is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadState {
+    /// Waiting in the run queue
+    Ready,
+    Running,
+    /// Waiting to be woken
+    Blocked,
+    /// Returned or killed, waiting to be joined
+    Exited,
+}
+
+/// Why `JoinHandle::join` has no value to return
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinError {
+    /// The thread was killed by a fault before returning
+    Killed,
+}
+
+/// A thread stack mapped in the kernel VMM window
+struct KernelStack {
+    start: VirtAddr,
+}
+impl KernelStack {
+    fn allocate() -> Result<Self, VmmError> {
+        let start = vmm::with_kernel_space(|space| {
+            space.allocate(
+                "thread stack",
+                RegionKind::Stack,
+                STACK_SIZE,
+                GUARD_SIZE,
+                PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+            )
+        })?;
+        Ok(KernelStack { start })
+    }
+    fn top(&self) -> VirtAddr {
+        self.start + STACK_SIZE
+    }
+}
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        let _ = vmm::with_kernel_space(|space| space.unmap(self.start));
+    }
+}
+
+struct Thread {
+    state: ThreadState,
+    /// Saved stack pointer while the thread is switched out
+    rsp: u64,
+    /// `None` for the main thread, which runs on the boot stack
+    stack: Option<KernelStack>,
+    /// Function run by the thread, taken when it starts
+    entry: Option<Box<dyn FnOnce() + Send>>,
+    /// Threads blocked in `join` on this one
+    joiners: Vec<ThreadId>,
+    /// The `JoinHandle` was dropped, nobody will collect the thread
+    detached: bool,
+    /// Woken while not blocked, the next block returns right away
+    wakeup: bool,
+    nice: Nice,
+    stats: SchedStats,
+    /// When the thread last got the CPU
+    last_run: Instant,
+}
+impl Thread {
+    fn new(state: ThreadState) -> Self {
+        Thread {
+            state,
+            rsp: 0,
+            stack: None,
+            entry: None,
+            joiners: Vec::new(),
+            detached: false,
+            wakeup: false,
+            nice: Nice::DEFAULT,
+            stats: SchedStats::default(),
+            last_run: Instant::now(),
+        }
+    }
+}
+
+struct Scheduler {
+    // Boxed so the saved stack pointers keep their address
+    threads: BTreeMap<ThreadId, Box<Thread>>,
+    ready: RunQueue<ThreadId>,
+    current: ThreadId,
+    idle: Option<ThreadId>,
+}
+impl Scheduler {
+    fn new() -> Self {
+        let mut threads = BTreeMap::new();
+        threads.insert(ThreadId::MAIN, Box::new(Thread::new(ThreadState::Running)));
+        Scheduler {
+            threads,
+            ready: RunQueue::new(scheduler::POLICY.get()),
+            current: ThreadId::MAIN,
+            idle: None,
+        }
+    }
+    fn thread(&mut self, id: ThreadId) -> &mut Thread {
+        self.threads.get_mut(&id).expect("unknown thread")
+    }
+    /// Add a ready thread to the run queue
+    ///
+    /// Never allocates, `spawn` reserves room for every thread.
+    fn enqueue(&mut self, id: ThreadId) {
+        let nice = self.thread(id).nice;
+        self.ready.push(id, nice);
+        if Some(self.current) == self.idle || self.outranks_current(nice) {
+            NEED_RESCHED.store(true, Ordering::Relaxed);
+        }
+        if Some(self.current) != self.idle {
+            let mut slice = SLICE_END.lock();
+            if slice.is_none() {
+                *slice = Some(Instant::now() + TIME_SLICE);
+            }
+        }
+    }
+    /// Make a blocked thread ready, or remember the wakeup if it is not blocked yet
+    fn wake(&mut self, id: ThreadId) {
+        let Some(thread) = self.threads.get_mut(&id) else {
+            return;
+        };
+        match thread.state {
+            ThreadState::Blocked => {
+                thread.state = ThreadState::Ready;
+                thread.stats.record_wakeup();
+                self.enqueue(id);
+            }
+            ThreadState::Ready | ThreadState::Running => thread.wakeup = true,
+            ThreadState::Exited => {}
+        }
+    }
+    /// Whether a thread with `nice` should take the CPU from the running one
+    fn outranks_current(&self, nice: Nice) -> bool {
+        let current = self.threads[&self.current].nice;
+        self.ready.policy() == Policy::Priority
+            && RunQueue::<ThreadId>::priority_of(nice) < RunQueue::<ThreadId>::priority_of(current)
+    }
+}
+
+lazy_static! {
+    static ref SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler::new());
+}
+/// End of the time slice of the running thread, `None` while no other thread is ready
+static SLICE_END: Mutex<Option<Instant>> = Mutex::new(None);
+/// Set by the timer interrupt when the running thread has to give up the CPU
+static NEED_RESCHED: AtomicBool = AtomicBool::new(false);
+
+/// Start the idle thread and let faults kill the thread causing them
+///
+/// Must be called after the VMM and the clock are initialized.
+pub fn init() {
+    let idle = ThreadId::new();
+    let thread = new_thread(Box::new(|| idle_loop()), ThreadState::Ready)
+        .expect("[THREAD]: Failed to allocate the idle thread stack");
+    without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        scheduler.threads.insert(idle, thread);
+        scheduler.idle = Some(idle);
+    });
+    exceptions::set_kill_task_handler(kill_current);
+}
+/// Allocate a thread that starts by running `entry`
+fn new_thread(
+    entry: Box<dyn FnOnce() + Send>,
+    state: ThreadState,
+) -> Result<Box<Thread>, VmmError> {
+    let stack = KernelStack::allocate()?;
+    let mut thread = Thread::new(state);
+    thread.rsp = unsafe { context::initial_stack(stack.top()) };
+    thread.stack = Some(stack);
+    thread.entry = Some(entry);
+    Ok(Box::new(thread))
+}
+
+/// Run `f` on a new kernel thread
+///
+/// Panics if no stack can be allocated for the thread.
+pub fn spawn_thread<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_thread_with_nice(Nice::DEFAULT, f)
+}
+/// Run `f` on a new kernel thread starting with the priority `nice`
+///
+/// Panics if no stack can be allocated for the thread.
+pub fn spawn_thread_with_nice<F, T>(nice: Nice, f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    reap();
+    let packet = Arc::new(Mutex::new(None));
+    let result = packet.clone();
+    let entry = Box::new(move || {
+        let value = f();
+        *result.lock() = Some(value);
+    });
+    let mut thread =
+        new_thread(entry, ThreadState::Ready).expect("failed to allocate a thread stack");
+    thread.nice = nice;
+    let id = ThreadId::new();
+    without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        scheduler.threads.insert(id, thread);
+        // Wakeups come from interrupt context, the run queue must never grow there
+        let count = scheduler.threads.len();
+        scheduler.ready.reserve(count);
+        scheduler.enqueue(id);
+        drop(scheduler);
+        preempt();
+    });
+    timer::rearm();
+    JoinHandle { id, packet }
+}
+
+/// Let the other ready threads run before continuing
+pub fn yield_now() {
+    assert_bsp("yield_now");
+    without_interrupts(schedule);
+}
+/// Block the running thread for `duration`
+pub fn sleep(duration: Duration) {
+    block_on(timer::sleep(duration));
+}
+/// Block the running thread until `future` completes
+///
+/// The thread sleeps while the future is pending and is woken by its `Waker`.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    assert_bsp("block_on");
+    let mut future = pin::pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(current())));
+    let mut context = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+        // A wakeup since the poll makes this return right away
+        without_interrupts(block_current);
+    }
+}
+/// End the running thread, its `JoinHandle` gets `Err(JoinError::Killed)` unless it returned
+///
+/// Panics when called from the main or idle thread.
+pub fn exit() -> ! {
+    assert_bsp("exit");
+    interrupts::disable();
+    {
+        let mut scheduler = SCHEDULER.lock();
+        let current = scheduler.current;
+        if current == ThreadId::MAIN || Some(current) == scheduler.idle {
+            panic!("thread {} cannot exit", current);
+        }
+        let thread = scheduler.thread(current);
+        thread.state = ThreadState::Exited;
+        for joiner in mem::take(&mut thread.joiners) {
+            scheduler.wake(joiner);
+        }
+    }
+    schedule();
+    unreachable!("exited thread was resumed");
+}
+/// Identifier of the running thread
+pub fn current() -> ThreadId {
+    assert_bsp("current");
+    without_interrupts(|| SCHEDULER.lock().current)
+}
+/// Panic unless running on the BSP, the only CPU running threads
+fn assert_bsp(function: &str) {
+    if !crate::smp::is_bsp() {
+        panic!("thread::{} called on an application processor", function);
+    }
+}
+/// State of the thread `id`, `None` once it was joined
+pub fn state(id: ThreadId) -> Option<ThreadState> {
+    without_interrupts(|| SCHEDULER.lock().threads.get(&id).map(|thread| thread.state))
+}
+/// Number of threads waiting in the run queue
+pub fn ready_count() -> usize {
+    without_interrupts(|| SCHEDULER.lock().ready.len())
+}
+/// Change the priority of the thread `id`, returns false if there is no such thread
+///
+/// Takes effect right away, the running thread is preempted if it no longer
+/// has the best priority.
+pub fn set_nice(id: ThreadId, nice: Nice) -> bool {
+    without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let Some(thread) = scheduler.threads.get_mut(&id) else {
+            return false;
+        };
+        let lowered = nice > thread.nice;
+        thread.nice = nice;
+        if id == scheduler.current {
+            if lowered && !scheduler.ready.is_empty() {
+                NEED_RESCHED.store(true, Ordering::Relaxed);
+            }
+        } else if scheduler.ready.remove(id) {
+            scheduler.enqueue(id);
+        }
+        drop(scheduler);
+        preempt();
+        true
+    })
+}
+/// Priority of the thread `id`
+pub fn nice(id: ThreadId) -> Option<Nice> {
+    without_interrupts(|| SCHEDULER.lock().threads.get(&id).map(|thread| thread.nice))
+}
+/// Scheduling statistics of the thread `id`, including the current run if it is running
+pub fn stats(id: ThreadId) -> Option<SchedStats> {
+    without_interrupts(|| {
+        let scheduler = SCHEDULER.lock();
+        let thread = scheduler.threads.get(&id)?;
+        let mut stats = thread.stats;
+        if id == scheduler.current {
+            stats.runtime += Instant::now() - thread.last_run;
+        }
+        Some(stats)
+    })
+}
+/// Policy used to pick the next thread
+pub fn policy() -> Policy {
+    without_interrupts(|| SCHEDULER.lock().ready.policy())
+}
+/// Change the policy used to pick the next thread
+pub fn set_policy(policy: Policy) {
+    without_interrupts(|| SCHEDULER.lock().ready.set_policy(policy));
+}
+
+/// Owned permission to wait for a thread and collect its result
+///
+/// Dropping the handle detaches the thread, it is freed once it exits.
+pub struct JoinHandle<T> {
+    id: ThreadId,
+    packet: Arc<Mutex<Option<T>>>,
+}
+impl<T> JoinHandle<T> {
+    pub fn thread_id(&self) -> ThreadId {
+        self.id
+    }
+    pub fn is_finished(&self) -> bool {
+        state(self.id) == Some(ThreadState::Exited)
+    }
+    /// Block until the thread exits and return the value of its function
+    pub fn join(self) -> Result<T, JoinError> {
+        assert_bsp("join");
+        loop {
+            let exited = without_interrupts(|| {
+                let mut scheduler = SCHEDULER.lock();
+                let current = scheduler.current;
+                let thread = scheduler.thread(self.id);
+                if thread.state == ThreadState::Exited {
+                    return true;
+                }
+                // Woken for another reason the thread is already registered
+                if !thread.joiners.contains(&current) {
+                    thread.joiners.push(current);
+                }
+                false
+            });
+            if exited {
+                break;
+            }
+            without_interrupts(block_current);
+        }
+        let value = self.packet.lock().take();
+        value.ok_or(JoinError::Killed)
+    }
+}
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        without_interrupts(|| {
+            if let Some(thread) = SCHEDULER.lock().threads.get_mut(&self.id) {
+                thread.detached = true;
+            }
+        });
+        reap();
+    }
+}
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinHandle").field("id", &self.id).finish()
+    }
+}
+
+/// Wakes a thread blocked in `block_on`
+struct ThreadWaker(ThreadId);
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+    fn wake_by_ref(self: &Arc<Self>) {
+        without_interrupts(|| SCHEDULER.lock().wake(self.0));
+        // Skipped when called from the timer interrupt, which re-arms the timer itself
+        timer::rearm();
+    }
+}
+
+/// End the time slice if it is over, called from the timer interrupts
+pub(crate) fn tick() {
+    let Some(mut slice) = SLICE_END.try_lock() else {
+        return;
+    };
+    if slice.is_some_and(|end| end <= Instant::now()) {
+        *slice = None;
+        NEED_RESCHED.store(true, Ordering::Relaxed);
+    }
+}
+/// Switch threads if the running one was asked to, called once an interrupt is acknowledged
+pub(crate) fn preempt() {
+    // Threads only run on the BSP
+    if crate::smp::is_bsp() && NEED_RESCHED.swap(false, Ordering::Relaxed) {
+        schedule();
+    }
+}
+/// End of the time slice of the running thread, a deadline for the timer queue
+pub(crate) fn slice_end() -> Option<Instant> {
+    SLICE_END.try_lock().and_then(|slice| *slice)
+}
+
+/// Switch to the next ready thread
+///
+/// Keeps running the current thread if nothing else is ready, falls back to the
+/// idle thread if the current one blocked or exited. Interrupts must be disabled.
+fn schedule() {
+    let switch = {
+        let mut scheduler = SCHEDULER.lock();
+        let current = scheduler.current;
+        let runnable = scheduler.thread(current).state == ThreadState::Running;
+        // The running thread competes with the ready ones, the idle thread is
+        // only run when the queue is empty
+        if runnable && Some(current) != scheduler.idle {
+            let nice = scheduler.thread(current).nice;
+            scheduler.ready.push(current, nice);
+        }
+        let next = match scheduler.ready.pop() {
+            Some(next) => next,
+            None if runnable => current,
+            None => scheduler.idle.expect("no thread to run and no idle thread"),
+        };
+        *SLICE_END.lock() = if scheduler.ready.is_empty() {
+            None
+        } else {
+            Some(Instant::now() + TIME_SLICE)
+        };
+        if next == current {
+            None
+        } else {
+            let now = Instant::now();
+            let thread = scheduler.thread(current);
+            thread.stats.record_run(now - thread.last_run);
+            if runnable {
+                thread.state = ThreadState::Ready;
+            }
+            let thread = scheduler.thread(next);
+            thread.state = ThreadState::Running;
+            thread.last_run = now;
+            scheduler.current = next;
+            let old_rsp = &mut scheduler.thread(current).rsp as *mut u64;
+            Some((old_rsp, scheduler.thread(next).rsp))
+        }
+    };
+    timer::rearm();
+    if let Some((old_rsp, new_rsp)) = switch {
+        unsafe { context::switch(old_rsp, new_rsp) };
+    }
+}
+/// Block the running thread until it is woken, interrupts must be disabled
+fn block_current() {
+    {
+        let mut scheduler = SCHEDULER.lock();
+        let current = scheduler.current;
+        let thread = scheduler.thread(current);
+        if mem::take(&mut thread.wakeup) {
+            return;
+        }
+        thread.state = ThreadState::Blocked;
+    }
+    schedule();
+}
+/// Free the exited threads nobody can join anymore
+///
+/// Runs with interrupts enabled, unmapping the stacks takes the VMM lock.
+fn reap() {
+    let dead: Vec<Box<Thread>> = without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let current = scheduler.current;
+        let ids: Vec<ThreadId> = scheduler
+            .threads
+            .iter()
+            .filter(|(id, thread)| {
+                thread.state == ThreadState::Exited && thread.detached && **id != current
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        ids.iter()
+            .filter_map(|id| scheduler.threads.remove(id))
+            .collect()
+    });
+    drop(dead);
+}
+/// First Rust code of every thread, entered from the trampoline with interrupts disabled
+extern "C" fn thread_entry() -> ! {
+    let entry = {
+        let mut scheduler = SCHEDULER.lock();
+        let current = scheduler.current;
+        scheduler.thread(current).entry.take()
+    };
+    interrupts::enable();
+    if let Some(entry) = entry {
+        entry();
+    }
+    exit();
+}
+/// Body of the idle thread
+fn idle_loop() -> ! {
+    loop {
+        reap();
+        interrupts::disable();
+        if SCHEDULER.lock().ready.is_empty() {
+            interrupts::enable_and_hlt();
+        } else {
+            schedule();
+            interrupts::enable();
+        }
+    }
+}
+/// Registered as kill handler of the exception dispatcher
+fn kill_current() -> ! {
+    if !crate::smp::is_bsp() {
+        panic!("fault on an application processor");
+    }
+    if current() == ThreadId::MAIN {
+        panic!("fault in the main thread");
+    }
+    exit();
+}
+
+#[cfg(test)]
+mod tests;
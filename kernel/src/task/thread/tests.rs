@@ -0,0 +1,94 @@
+use super::*;
+use crate::interrupts::exceptions::{fault_policy, set_fault_policy, Exception, FaultAction};
+use alloc::vec;
+
+#[test_case]
+fn test_join_returns_value() {
+    let handle = spawn_thread(|| 6 * 7);
+    assert_ne!(handle.thread_id(), current());
+    assert_eq!(handle.join(), Ok(42));
+}
+#[test_case]
+fn test_yield_interleaves() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let worker = |name: char, log: Arc<Mutex<Vec<char>>>| {
+        move || {
+            log.lock().push(name);
+            yield_now();
+            log.lock().push(name);
+        }
+    };
+    let first = spawn_thread(worker('a', log.clone()));
+    let second = spawn_thread(worker('b', log.clone()));
+    first.join().unwrap();
+    second.join().unwrap();
+    assert_eq!(*log.lock(), vec!['a', 'b', 'a', 'b']);
+}
+#[test_case]
+fn test_sleep() {
+    let elapsed = spawn_thread(|| {
+        let start = Instant::now();
+        sleep(Duration::from_millis(20));
+        start.elapsed()
+    })
+    .join()
+    .unwrap();
+    assert!(elapsed >= Duration::from_millis(20), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(200), "{:?}", elapsed);
+}
+#[test_case]
+fn test_preemption() {
+    static STOP: AtomicBool = AtomicBool::new(false);
+    static SPINS: AtomicU64 = AtomicU64::new(0);
+    let spinner = spawn_thread(|| {
+        while !STOP.load(Ordering::Relaxed) {
+            SPINS.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+    // The main thread never yields, only preemption lets the spinner run
+    let start = Instant::now();
+    while SPINS.load(Ordering::Relaxed) == 0 && start.elapsed() < Duration::from_secs(1) {
+        core::hint::spin_loop();
+    }
+    STOP.store(true, Ordering::Relaxed);
+    spinner.join().unwrap();
+    assert!(SPINS.load(Ordering::Relaxed) > 0);
+}
+#[test_case]
+fn test_fault_kills_thread() {
+    let previous = fault_policy(Exception::GeneralProtectionFault);
+    set_fault_policy(Exception::GeneralProtectionFault, FaultAction::KillTask);
+    let handle = spawn_thread(|| {
+        // Non canonical address, raises a general protection fault
+        unsafe { core::ptr::read_volatile(0xdead_beef_0000_0000 as *const u64) }
+    });
+    assert_eq!(handle.join(), Err(JoinError::Killed));
+    set_fault_policy(Exception::GeneralProtectionFault, previous);
+}
+#[test_case]
+fn test_better_priority_runs_first() {
+    static RAN: AtomicBool = AtomicBool::new(false);
+    let previous = policy();
+    set_policy(Policy::Priority);
+    let handle = spawn_thread_with_nice(Nice::MIN, || RAN.store(true, Ordering::Relaxed));
+    // The new thread preempted the spawner
+    assert!(RAN.load(Ordering::Relaxed));
+    assert_eq!(nice(handle.thread_id()), Some(Nice::MIN));
+    handle.join().unwrap();
+    set_policy(previous);
+}
+#[test_case]
+fn test_stats_count_runs() {
+    let handle = spawn_thread(|| {
+        yield_now();
+        sleep(Duration::from_millis(5));
+    });
+    let id = handle.thread_id();
+    while !handle.is_finished() {
+        yield_now();
+    }
+    let stats = stats(id).unwrap();
+    assert!(stats.runs >= 2, "{:?}", stats);
+    assert!(stats.wakeups >= 1, "{:?}", stats);
+    handle.join().unwrap();
+}
@@ -0,0 +1,272 @@
+//! Async timers
+//!
+//! Pending timers sit in a min-heap ordered by deadline. The LAPIC timer
+//! interrupt (the PIT tick when the LAPIC timer is not calibrated) pops the
+//! expired ones and wakes their tasks, so a sleeping task never keeps the
+//! executor busy. In tickless mode the LAPIC timer is re-armed for the
+//! earliest deadline whenever the top of the heap changes.
+//!
+//! The interrupt side never allocates nor frees: a timer removes its own heap
+//! entry when dropped, so popping an entry only releases a `Waker` clone.
+use crate::task::thread;
+use crate::time::{Duration, Instant};
+use crate::timer::lapic::LAPICTimer;
+use alloc::collections::BinaryHeap;
+use core::{
+    cmp::Ordering,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{self, AtomicU64},
+    task::{Context, Poll, Waker},
+};
+use futures_util::stream::Stream;
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+lazy_static! {
+    /// Registered timers, the earliest deadline on top
+    static ref TIMERS: Mutex<BinaryHeap<TimerEntry>> = Mutex::new(BinaryHeap::new());
+}
+
+/// A task waiting for a deadline
+struct TimerEntry {
+    deadline: Instant,
+    id: u64,
+    waker: Waker,
+}
+impl PartialEq for TimerEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for TimerEntry {}
+impl PartialOrd for TimerEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for TimerEntry {
+    // Reversed so the `BinaryHeap` pops the earliest deadline first
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.deadline, other.id).cmp(&(self.deadline, self.id))
+    }
+}
+
+/// Wake the tasks whose deadline has passed, called from the timer interrupt
+pub(crate) fn wake_expired() {
+    // Task context only holds the lock with interrupts disabled, it can only
+    // be taken by another CPU and the next tick will catch up
+    let Some(mut timers) = TIMERS.try_lock() else {
+        return;
+    };
+    let now = Instant::now();
+    while timers.peek().is_some_and(|entry| entry.deadline <= now) {
+        if let Some(entry) = timers.pop() {
+            entry.waker.wake();
+        }
+    }
+    LAPICTimer::set_deadline(next_deadline(&timers));
+}
+/// Arm the LAPIC timer for the earliest pending deadline
+///
+/// Does nothing while the queue is locked, whoever holds it arms the timer
+/// when done.
+pub(crate) fn rearm() {
+    without_interrupts(|| {
+        if let Some(timers) = TIMERS.try_lock() {
+            LAPICTimer::set_deadline(next_deadline(&timers));
+        }
+    });
+}
+/// Earliest of the first timer and the end of the time slice of the running thread
+fn next_deadline(timers: &BinaryHeap<TimerEntry>) -> Option<Instant> {
+    let first = timers.peek().map(|entry| entry.deadline);
+    match (first, thread::slice_end()) {
+        (Some(first), Some(slice_end)) => Some(first.min(slice_end)),
+        (first, slice_end) => first.or(slice_end),
+    }
+}
+/// Number of timers waiting for their deadline
+pub fn pending_timers() -> usize {
+    without_interrupts(|| TIMERS.lock().len())
+}
+
+/// A future completing at a deadline, created by `sleep` and `sleep_until`
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Sleep {
+    deadline: Instant,
+    id: u64,
+    registered: bool,
+}
+impl Sleep {
+    fn new(deadline: Instant) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Sleep {
+            deadline,
+            id: NEXT_ID.fetch_add(1, atomic::Ordering::Relaxed),
+            registered: false,
+        }
+    }
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+    pub fn is_elapsed(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+    /// Move the deadline, the future can be polled again after completing
+    pub fn reset(&mut self, deadline: Instant) {
+        self.unregister();
+        self.deadline = deadline;
+    }
+    /// Add the timer to the heap, replacing the waker of a previous poll
+    fn register(&mut self, waker: &Waker) {
+        let entry = TimerEntry {
+            deadline: self.deadline,
+            id: self.id,
+            waker: waker.clone(),
+        };
+        without_interrupts(|| {
+            let mut timers = TIMERS.lock();
+            if self.registered {
+                timers.retain(|entry| entry.id != self.id);
+            }
+            timers.push(entry);
+            if timers.peek().is_some_and(|top| top.id == self.id) {
+                LAPICTimer::set_deadline(next_deadline(&timers));
+            }
+        });
+        self.registered = true;
+    }
+    fn unregister(&mut self) {
+        if self.registered {
+            without_interrupts(|| TIMERS.lock().retain(|entry| entry.id != self.id));
+            self.registered = false;
+        }
+    }
+}
+impl Future for Sleep {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if self.is_elapsed() {
+            self.unregister();
+            return Poll::Ready(());
+        }
+        self.register(context.waker());
+        // The deadline may have passed before the entry was pushed, the
+        // interrupt would only see it on the next tick
+        if self.is_elapsed() {
+            self.unregister();
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.unregister();
+    }
+}
+/// Wait for `duration` without blocking the other tasks
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep::new(Instant::now().checked_add(duration).unwrap_or(Instant::MAX))
+}
+/// Wait until `deadline` without blocking the other tasks
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep::new(deadline)
+}
+
+/// A stream of evenly spaced instants, created by `interval`
+///
+/// Ticks missed because the task was busy are skipped rather than delivered in a burst.
+#[derive(Debug)]
+pub struct Interval {
+    period: Duration,
+    sleep: Sleep,
+}
+impl Interval {
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+    /// Wait for the next tick and return its scheduled instant
+    pub async fn tick(&mut self) -> Instant {
+        futures_util::future::poll_fn(|context| self.poll_tick(context)).await
+    }
+    pub fn poll_tick(&mut self, context: &mut Context) -> Poll<Instant> {
+        if Pin::new(&mut self.sleep).poll(context).is_pending() {
+            return Poll::Pending;
+        }
+        let tick = self.sleep.deadline();
+        let now = Instant::now();
+        let mut next = tick + self.period;
+        if next <= now {
+            next = now + self.period;
+        }
+        self.sleep.reset(next);
+        Poll::Ready(tick)
+    }
+}
+impl Stream for Interval {
+    type Item = Instant;
+    fn poll_next(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<Instant>> {
+        self.poll_tick(context).map(Some)
+    }
+}
+/// Tick every `period`, the first tick completes immediately
+///
+/// Panics if `period` is zero.
+pub fn interval(period: Duration) -> Interval {
+    assert!(!period.is_zero(), "interval period must be non-zero");
+    Interval {
+        period,
+        sleep: Sleep::new(Instant::now()),
+    }
+}
+
+/// The deadline of a `timeout` passed before the future completed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed;
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "deadline has elapsed")
+    }
+}
+/// A future racing another against a deadline, created by `timeout`
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Timeout<F> {
+    future: F,
+    sleep: Sleep,
+}
+impl<F> Timeout<F> {
+    pub fn into_inner(self) -> F {
+        self.future
+    }
+}
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        // Safety: `future` is never moved out of the pinned `Timeout`, the
+        // `Sleep` is `Unpin`
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        if let Poll::Ready(output) = future.poll(context) {
+            return Poll::Ready(Ok(output));
+        }
+        Pin::new(&mut this.sleep)
+            .poll(context)
+            .map(|()| Err(Elapsed))
+    }
+}
+/// Run `future`, giving up with `Elapsed` if it takes longer than `duration`
+pub fn timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    Timeout {
+        future,
+        sleep: sleep(duration),
+    }
+}
+
+#[cfg(test)]
+mod tests;
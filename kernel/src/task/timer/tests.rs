@@ -0,0 +1,52 @@
+use super::*;
+use crate::task::{simple_executor::SimpleExecutor, Task};
+use alloc::rc::Rc;
+use core::cell::Cell;
+
+/// Run `future` to completion on the busy polling executor
+fn block_on(future: impl Future<Output = ()> + 'static) {
+    let mut executor = SimpleExecutor::new();
+    executor.spawn(Task::new(future));
+    executor.run();
+}
+
+#[test_case]
+fn test_sleep() {
+    let start = Instant::now();
+    block_on(sleep(Duration::from_millis(20)));
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(20), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(200), "{:?}", elapsed);
+    assert_eq!(pending_timers(), 0);
+}
+#[test_case]
+fn test_timeout() {
+    let result = Rc::new(Cell::new(None));
+    let output = result.clone();
+    block_on(async move {
+        let slow = timeout(Duration::from_millis(5), sleep(Duration::from_secs(10))).await;
+        let fast = timeout(Duration::from_secs(10), async { 42 }).await;
+        output.set(Some((slow, fast)));
+    });
+    assert_eq!(result.get(), Some((Err(Elapsed), Ok(42))));
+    // Both losing timers were dropped with their entries
+    assert_eq!(pending_timers(), 0);
+}
+#[test_case]
+fn test_interval() {
+    let ticks = Rc::new(Cell::new([None; 3]));
+    let output = ticks.clone();
+    block_on(async move {
+        let mut interval = interval(Duration::from_millis(5));
+        let mut ticks = [None; 3];
+        for tick in ticks.iter_mut() {
+            *tick = Some(interval.tick().await);
+        }
+        output.set(ticks);
+    });
+    let [Some(first), Some(second), Some(third)] = ticks.get() else {
+        panic!("interval did not tick");
+    };
+    assert!(second - first >= Duration::from_millis(5));
+    assert!(third - second >= Duration::from_millis(5));
+}
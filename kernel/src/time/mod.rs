@@ -0,0 +1,196 @@
+//! Monotonic clock
+//!
+//! `Instant` measures the time since boot without touching the hardware timers.
+//! It reads the TSC when the processor guarantees an invariant TSC, the HPET
+//! main counter when there is a 64 bit one, and otherwise (or before `init`) the
+//! millisecond tick counter of the PIT interrupt.
+//!
+//! `SystemTime` adds the calendar time read from the RTC at boot.
+use crate::boot_params::BootParam;
+use crate::timer::{self, hpet::HPET};
+use crate::utils::cpuid;
+use core::ops::{Add, AddAssign, Sub};
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+pub use core::time::Duration;
+use log::{info, warn};
+
+mod system_time;
+pub use system_time::{sync_with_rtc, SystemTime, SystemTimeError, UNIX_EPOCH};
+
+/// Allow the TSC as clock source, `clock.tsc=off` on the command line
+pub static USE_TSC: BootParam<bool> =
+    BootParam::new("clock.tsc", "use the invariant TSC as clock", true);
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+/// Length of the measurement of the TSC frequency
+const CALIBRATION_MILLIS: u64 = 50;
+
+/// Counter the clock is read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    /// Millisecond ticks of the PIT interrupt
+    Tick = 0,
+    Hpet = 1,
+    Tsc = 2,
+}
+
+static SOURCE: AtomicU8 = AtomicU8::new(ClockSource::Tick as u8);
+/// Value of the counter of the source when it was selected
+static BASE_COUNT: AtomicU64 = AtomicU64::new(0);
+/// Time since boot when the source was selected, keeps the clock monotonic across the switch
+static BASE_NANOS: AtomicU64 = AtomicU64::new(0);
+/// Measured TSC frequency in Hz, 0 if unknown
+static TSC_FREQUENCY: AtomicU64 = AtomicU64::new(0);
+
+/// Counter the clock is currently read from
+pub fn clock_source() -> ClockSource {
+    match SOURCE.load(Ordering::Acquire) {
+        2 => ClockSource::Tsc,
+        1 => ClockSource::Hpet,
+        _ => ClockSource::Tick,
+    }
+}
+/// TSC frequency in Hz, known once `init` ran on a processor with a TSC
+///
+/// Set even when the TSC is not invariant and thus not used as clock source.
+pub fn tsc_frequency() -> Option<u64> {
+    match TSC_FREQUENCY.load(Ordering::Relaxed) {
+        0 => None,
+        frequency => Some(frequency),
+    }
+}
+/// Current value of the time stamp counter
+pub fn read_tsc() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Nanoseconds since boot according to the current source
+fn now_nanos() -> u64 {
+    let source = clock_source();
+    let base_nanos = BASE_NANOS.load(Ordering::Relaxed);
+    let base_count = BASE_COUNT.load(Ordering::Relaxed);
+    match source {
+        ClockSource::Tick => timer::uptime_millis() * 1_000_000,
+        ClockSource::Hpet => match HPET.as_ref() {
+            Some(hpet) => {
+                base_nanos + hpet.ticks_to_nanos(hpet.ticks_between(base_count, hpet.counter()))
+            }
+            None => base_nanos,
+        },
+        ClockSource::Tsc => {
+            let ticks = read_tsc().saturating_sub(base_count);
+            let frequency = TSC_FREQUENCY.load(Ordering::Relaxed);
+            base_nanos + (ticks as u128 * NANOS_PER_SEC as u128 / frequency as u128) as u64
+        }
+    }
+}
+/// Switch to `source`, whose counter currently reads `count`
+fn select(source: ClockSource, count: u64) {
+    BASE_NANOS.store(now_nanos(), Ordering::Relaxed);
+    BASE_COUNT.store(count, Ordering::Relaxed);
+    SOURCE.store(source as u8, Ordering::Release);
+}
+/// Measure the TSC frequency against the reference timer
+fn calibrate_tsc() -> u64 {
+    let start = read_tsc();
+    timer::calibration_delay(CALIBRATION_MILLIS);
+    (read_tsc() - start) * 1000 / CALIBRATION_MILLIS
+}
+/// Pick the most precise clock source available and set the wall clock
+///
+/// Needs the HPET probed and the PIT ticking, see `lib::init`.
+pub fn init() {
+    if cpuid::has_tsc() {
+        let frequency = cpuid::tsc_frequency().unwrap_or_else(calibrate_tsc);
+        TSC_FREQUENCY.store(frequency, Ordering::Relaxed);
+    }
+    let hpet = HPET
+        .as_ref()
+        .filter(|hpet| hpet.capabilities().counter_64bit);
+    if USE_TSC.get() && tsc_frequency().is_some() && cpuid::has_invariant_tsc() {
+        select(ClockSource::Tsc, read_tsc());
+    } else if let Some(hpet) = hpet {
+        select(ClockSource::Hpet, hpet.counter());
+    }
+    info!(
+        "Clock source: {:?}, TSC at {} kHz (invariant: {})",
+        clock_source(),
+        tsc_frequency().unwrap_or(0) / 1000,
+        cpuid::has_invariant_tsc()
+    );
+    if crate::timer::rtc::is_present() {
+        match sync_with_rtc() {
+            Ok(now) => info!("Wall clock: {}", now),
+            Err(error) => warn!("Failed to read the RTC: {}", error),
+        }
+    }
+}
+
+/// A point in time since boot, never goes backwards
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant {
+    nanos: u64,
+}
+impl Instant {
+    /// The latest representable instant, a deadline that never comes
+    pub const MAX: Instant = Instant { nanos: u64::MAX };
+    pub fn now() -> Self {
+        Instant { nanos: now_nanos() }
+    }
+    /// Time since boot
+    pub fn since_boot(&self) -> Duration {
+        Duration::from_nanos(self.nanos)
+    }
+    /// Time since `earlier`, zero if `earlier` is later
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.nanos.saturating_sub(earlier.nanos))
+    }
+    /// Time since this instant
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        Some(Instant {
+            nanos: self.nanos.checked_add(nanos)?,
+        })
+    }
+    pub fn checked_sub(&self, duration: Duration) -> Option<Instant> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        Some(Instant {
+            nanos: self.nanos.checked_sub(nanos)?,
+        })
+    }
+}
+impl Add<Duration> for Instant {
+    type Output = Instant;
+    fn add(self, duration: Duration) -> Instant {
+        self.checked_add(duration)
+            .expect("overflow when adding duration to instant")
+    }
+}
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+    fn sub(self, duration: Duration) -> Instant {
+        self.checked_sub(duration)
+            .expect("overflow when subtracting duration from instant")
+    }
+}
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+/// Time since boot
+pub fn uptime() -> Duration {
+    Instant::now().since_boot()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,106 @@
+//! Wall-clock time
+//!
+//! The RTC is read once at boot and the monotonic clock is added to that boot
+//! time, so the wall clock has the resolution of `Instant` and never jumps
+//! while the kernel runs. Until `sync_with_rtc` the wall clock starts at the epoch.
+use super::{Duration, Instant};
+use crate::timer::rtc::{self, DateTime};
+use core::fmt;
+use core::ops::{Add, Sub};
+use core::sync::atomic::{AtomicU64, Ordering};
+use ferrum_parse::ParseError;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+/// Wall-clock time when the monotonic clock was zero, in nanoseconds since the epoch
+static BOOT_TIME_NANOS: AtomicU64 = AtomicU64::new(0);
+
+/// 1970-01-01 00:00:00 UTC
+pub const UNIX_EPOCH: SystemTime = SystemTime { nanos: 0 };
+
+/// A point in calendar time, in nanoseconds since the Unix epoch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SystemTime {
+    nanos: u64,
+}
+/// The other time was later than the one `duration_since` was called on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SystemTimeError(Duration);
+impl SystemTimeError {
+    /// How much later the other time was
+    pub fn duration(&self) -> Duration {
+        self.0
+    }
+}
+
+impl SystemTime {
+    pub const UNIX_EPOCH: SystemTime = UNIX_EPOCH;
+
+    pub fn now() -> Self {
+        let since_boot = Instant::now().since_boot().as_nanos() as u64;
+        SystemTime {
+            nanos: BOOT_TIME_NANOS.load(Ordering::Relaxed) + since_boot,
+        }
+    }
+    /// The time of a calendar date, dates before the epoch are clamped to it
+    pub fn from_datetime(date: &DateTime) -> Self {
+        let seconds = date.to_unix_seconds().max(0) as u64;
+        SystemTime {
+            nanos: seconds * NANOS_PER_SEC,
+        }
+    }
+    /// The calendar date, to the second
+    pub fn datetime(&self) -> DateTime {
+        DateTime::from_unix_seconds((self.nanos / NANOS_PER_SEC) as i64)
+    }
+    pub fn duration_since(&self, earlier: SystemTime) -> Result<Duration, SystemTimeError> {
+        match self.nanos.checked_sub(earlier.nanos) {
+            Some(nanos) => Ok(Duration::from_nanos(nanos)),
+            None => Err(SystemTimeError(Duration::from_nanos(
+                earlier.nanos - self.nanos,
+            ))),
+        }
+    }
+    pub fn elapsed(&self) -> Result<Duration, SystemTimeError> {
+        SystemTime::now().duration_since(*self)
+    }
+    pub fn checked_add(&self, duration: Duration) -> Option<SystemTime> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        Some(SystemTime {
+            nanos: self.nanos.checked_add(nanos)?,
+        })
+    }
+    pub fn checked_sub(&self, duration: Duration) -> Option<SystemTime> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        Some(SystemTime {
+            nanos: self.nanos.checked_sub(nanos)?,
+        })
+    }
+}
+impl Add<Duration> for SystemTime {
+    type Output = SystemTime;
+    fn add(self, duration: Duration) -> SystemTime {
+        self.checked_add(duration)
+            .expect("overflow when adding duration to system time")
+    }
+}
+impl Sub<Duration> for SystemTime {
+    type Output = SystemTime;
+    fn sub(self, duration: Duration) -> SystemTime {
+        self.checked_sub(duration)
+            .expect("overflow when subtracting duration from system time")
+    }
+}
+impl fmt::Display for SystemTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} UTC", self.datetime())
+    }
+}
+
+/// Set the wall clock from the RTC
+pub fn sync_with_rtc() -> Result<SystemTime, ParseError> {
+    let date = rtc::read_datetime()?;
+    let since_boot = Instant::now().since_boot().as_nanos() as u64;
+    let now = SystemTime::from_datetime(&date);
+    BOOT_TIME_NANOS.store(now.nanos.saturating_sub(since_boot), Ordering::Relaxed);
+    Ok(now)
+}
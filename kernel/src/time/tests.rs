@@ -0,0 +1,42 @@
+use super::*;
+
+#[test_case]
+fn test_clock_is_monotonic() {
+    let mut previous = Instant::now();
+    for _ in 0..1000 {
+        let now = Instant::now();
+        assert!(now >= previous);
+        previous = now;
+    }
+}
+#[test_case]
+fn test_clock_measures_delays() {
+    let start = Instant::now();
+    timer::calibration_delay(20);
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(19), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(200), "{:?}", elapsed);
+}
+#[test_case]
+fn test_instant_arithmetic() {
+    let start = Instant::now();
+    let later = start + Duration::from_millis(5);
+    assert_eq!(later - start, Duration::from_millis(5));
+    assert_eq!(start - later, Duration::ZERO);
+    assert_eq!(later - Duration::from_millis(5), start);
+    assert!(start.checked_add(Duration::MAX).is_none());
+}
+#[test_case]
+fn test_system_time() {
+    let now = SystemTime::now();
+    let since_epoch = now.duration_since(UNIX_EPOCH).unwrap();
+    // Later than 2020-01-01
+    assert!(since_epoch.as_secs() > 1_577_836_800, "{}", now);
+    let later = now + Duration::from_secs(90);
+    assert_eq!(later.duration_since(now), Ok(Duration::from_secs(90)));
+    assert_eq!(
+        now.duration_since(later).unwrap_err().duration(),
+        Duration::from_secs(90)
+    );
+    assert!(SystemTime::now() >= now);
+}
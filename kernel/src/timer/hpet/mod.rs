@@ -0,0 +1,243 @@
+//! High Precision Event Timer driver
+//!
+//! The HPET has a free running main counter and a few comparators that raise
+//! an interrupt when the counter reaches their value, once or periodically.
+//! It is found through the ACPI HPET table and is optional: `HPET` is `None`
+//! on machines without one or when booted with `hpet.enable=off`.
+use crate::boot_params::BootParam;
+use crate::drivers::acpi::{hpet::HPET as HpetTable, ACPI_TABLES};
+use crate::drivers::apic::io_apic;
+use crate::drivers::apic::local_apic::LOCAL_APIC;
+use crate::drivers::apic::routing::{route_gsi, RedirectionEntry};
+use crate::memory::mmio::{ioremap, MmioRegion};
+use ferrum_parse::acpi::{GenericAddress, HpetCapabilities};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use x86_64::PhysAddr;
+
+/// Use the HPET when present, `hpet.enable=off` on the command line falls back to the PIT
+pub static ENABLE: BootParam<bool> = BootParam::new("hpet.enable", "use the HPET", true);
+
+// Register offsets
+const CAPABILITIES: u64 = 0x000;
+const CONFIGURATION: u64 = 0x010;
+const MAIN_COUNTER: u64 = 0x0F0;
+const REGISTERS_SIZE: u64 = 0x400;
+const fn comparator_config(index: u8) -> u64 {
+    0x100 + 0x20 * index as u64
+}
+const fn comparator_value(index: u8) -> u64 {
+    0x108 + 0x20 * index as u64
+}
+// General configuration bits
+const ENABLE_CNF: u64 = 1 << 0;
+const LEG_RT_CNF: u64 = 1 << 1;
+// Comparator configuration bits
+const TN_INT_TYPE_LEVEL: u64 = 1 << 1;
+const TN_INT_ENB_CNF: u64 = 1 << 2;
+const TN_TYPE_PERIODIC: u64 = 1 << 3;
+const TN_PER_INT_CAP: u64 = 1 << 4;
+const TN_VAL_SET_CNF: u64 = 1 << 6;
+const TN_32MODE_CNF: u64 = 1 << 8;
+const TN_INT_ROUTE_SHIFT: u64 = 9;
+const TN_INT_ROUTE_MASK: u64 = 0x1F << TN_INT_ROUTE_SHIFT;
+const TN_FSB_EN_CNF: u64 = 1 << 14;
+
+/// HPET error type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HpetError {
+    NoSuchComparator,
+    PeriodicUnsupported,
+    /// The comparator cannot be routed to any IOAPIC input
+    NoRoute,
+    /// The period is shorter than the minimum tick of the table
+    PeriodTooShort,
+}
+/// How often a comparator fires
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerMode {
+    OneShot,
+    Periodic,
+}
+
+lazy_static! {
+    /// The HPET of the system, if there is one
+    pub static ref HPET: Option<Hpet> = if ENABLE.get() { Hpet::from_acpi() } else { None };
+}
+
+/// Probe the HPET and start its main counter
+pub fn init() {
+    lazy_static::initialize(&HPET);
+}
+
+/// A timer block with its main counter enabled
+pub struct Hpet {
+    registers: MmioRegion,
+    capabilities: HpetCapabilities,
+    /// Shortest period, in counter ticks, a periodic comparator may use
+    minimum_tick: u64,
+}
+impl Hpet {
+    /// Locate the timer block through the ACPI table, map it and start the main counter
+    fn from_acpi() -> Option<Hpet> {
+        let table: HpetTable = ACPI_TABLES.find()?;
+        if table.base_address.address_space_id != GenericAddress::SYSTEM_MEMORY {
+            warn!("HPET registers are not memory mapped");
+            return None;
+        }
+        let registers = ioremap(PhysAddr::new(table.base_address.address), REGISTERS_SIZE)
+            .map_err(|error| warn!("Failed to map the HPET: {:?}", error))
+            .ok()?;
+        let capabilities = HpetCapabilities::from_register(registers.read(CAPABILITIES))
+            .map_err(|error| warn!("Invalid HPET capabilities: {}", error))
+            .ok()?;
+        let hpet = Hpet {
+            registers,
+            capabilities,
+            minimum_tick: table.minimum_tick as u64,
+        };
+        // Comparators stay off until requested, the counter starts from zero
+        for index in 0..capabilities.comparator_count {
+            hpet.stop_timer(index);
+        }
+        let configuration = hpet.registers.read::<u64>(CONFIGURATION);
+        hpet.registers
+            .write(CONFIGURATION, configuration & !(ENABLE_CNF | LEG_RT_CNF));
+        hpet.registers.write(MAIN_COUNTER, 0u64);
+        hpet.registers
+            .write(CONFIGURATION, (configuration & !LEG_RT_CNF) | ENABLE_CNF);
+        info!(
+            "HPET at {:#x}: {} comparators, {} bit counter at {} kHz",
+            table.base_address.address,
+            capabilities.comparator_count,
+            if capabilities.counter_64bit { 64 } else { 32 },
+            capabilities.frequency() / 1000
+        );
+        Some(hpet)
+    }
+    /// Capabilities reported by the timer block
+    pub fn capabilities(&self) -> &HpetCapabilities {
+        &self.capabilities
+    }
+    /// Number of comparators
+    pub fn comparator_count(&self) -> u8 {
+        self.capabilities.comparator_count
+    }
+    /// Current value of the main counter
+    pub fn counter(&self) -> u64 {
+        self.registers.read::<u64>(MAIN_COUNTER) & self.counter_mask()
+    }
+    /// Ticks between two counter values, handles one wrap of a 32 bit counter
+    pub fn ticks_between(&self, start: u64, end: u64) -> u64 {
+        end.wrapping_sub(start) & self.counter_mask()
+    }
+    fn counter_mask(&self) -> u64 {
+        if self.capabilities.counter_64bit {
+            u64::MAX
+        } else {
+            u32::MAX as u64
+        }
+    }
+    /// Convert counter ticks to nanoseconds
+    pub fn ticks_to_nanos(&self, ticks: u64) -> u64 {
+        (ticks as u128 * self.capabilities.period_fs as u128 / 1_000_000) as u64
+    }
+    /// Convert nanoseconds to counter ticks, rounding up
+    pub fn nanos_to_ticks(&self, nanos: u64) -> u64 {
+        (nanos as u128 * 1_000_000).div_ceil(self.capabilities.period_fs as u128) as u64
+    }
+    /// Nanoseconds since the counter was started
+    pub fn nanos(&self) -> u64 {
+        self.ticks_to_nanos(self.counter())
+    }
+    /// Spin until `nanos` nanoseconds have passed
+    pub fn busy_wait(&self, nanos: u64) {
+        let ticks = self.nanos_to_ticks(nanos);
+        let start = self.counter();
+        while self.ticks_between(start, self.counter()) < ticks {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Make the comparator `index` raise `vector` on this CPU after `nanos`, once or periodically
+    ///
+    /// The handler must be registered for `vector` beforehand, see `interrupts::irq`.
+    pub fn start_timer(
+        &self,
+        index: u8,
+        mode: TimerMode,
+        nanos: u64,
+        vector: u8,
+    ) -> Result<(), HpetError> {
+        if index >= self.comparator_count() {
+            return Err(HpetError::NoSuchComparator);
+        }
+        let config = self.registers.read::<u64>(comparator_config(index));
+        if mode == TimerMode::Periodic && config & TN_PER_INT_CAP == 0 {
+            return Err(HpetError::PeriodicUnsupported);
+        }
+        let ticks = self.nanos_to_ticks(nanos).max(1);
+        if mode == TimerMode::Periodic && ticks < self.minimum_tick {
+            return Err(HpetError::PeriodTooShort);
+        }
+        let gsi = Self::pick_route((config >> 32) as u32).ok_or(HpetError::NoRoute)?;
+        self.stop_timer(index);
+        route_gsi(
+            gsi,
+            RedirectionEntry::new(vector, LOCAL_APIC.apic_id() as u8),
+        );
+
+        let mut config = config
+            & !(TN_INT_TYPE_LEVEL
+                | TN_TYPE_PERIODIC
+                | TN_32MODE_CNF
+                | TN_FSB_EN_CNF
+                | TN_INT_ROUTE_MASK);
+        config |= (gsi as u64) << TN_INT_ROUTE_SHIFT;
+        let target = self.counter().wrapping_add(ticks) & self.counter_mask();
+        match mode {
+            TimerMode::OneShot => {
+                self.registers.write(comparator_config(index), config);
+                self.registers.write(comparator_value(index), target);
+            }
+            TimerMode::Periodic => {
+                // With VAL_SET the first write sets the comparator, the second the period
+                config |= TN_TYPE_PERIODIC | TN_VAL_SET_CNF;
+                self.registers.write(comparator_config(index), config);
+                self.registers.write(comparator_value(index), target);
+                self.registers.write(comparator_value(index), ticks);
+            }
+        }
+        self.registers
+            .write(comparator_config(index), config | TN_INT_ENB_CNF);
+        Ok(())
+    }
+    /// Stop the comparator `index` from raising interrupts
+    pub fn stop_timer(&self, index: u8) {
+        if index >= self.comparator_count() {
+            return;
+        }
+        let config = self.registers.read::<u64>(comparator_config(index));
+        self.registers.write(
+            comparator_config(index),
+            config & !(TN_INT_ENB_CNF | TN_TYPE_PERIODIC),
+        );
+        let gsi = (config & TN_INT_ROUTE_MASK) >> TN_INT_ROUTE_SHIFT;
+        if config & TN_INT_ENB_CNF != 0 {
+            io_apic::set_gsi_mask(gsi as u32, true);
+        }
+    }
+    /// IOAPIC input to use out of the allowed ones, avoiding the ISA range
+    fn pick_route(allowed: u32) -> Option<u32> {
+        let allowed = match io_apic::gsi_count() {
+            count @ 0..=31 => allowed & ((1 << count) - 1),
+            _ => allowed,
+        };
+        let non_isa = allowed & !0xFFFF;
+        let candidates = if non_isa != 0 { non_isa } else { allowed };
+        (candidates != 0).then(|| candidates.trailing_zeros())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,45 @@
+use super::*;
+use crate::interrupts::irq::{
+    allocate_vector, free_vector, register_irq_handler, unregister_irq_handler, IrqReturn,
+};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+#[test_case]
+fn test_counter_runs() {
+    let Some(hpet) = HPET.as_ref() else {
+        return;
+    };
+    let start = hpet.counter();
+    hpet.busy_wait(1_000_000);
+    let elapsed = hpet.ticks_to_nanos(hpet.ticks_between(start, hpet.counter()));
+    assert!(elapsed >= 1_000_000);
+    assert!(elapsed < 50_000_000);
+}
+#[test_case]
+fn test_comparator_interrupts() {
+    static FIRED: AtomicU64 = AtomicU64::new(0);
+    let Some(hpet) = HPET.as_ref() else {
+        return;
+    };
+    let vector = allocate_vector().unwrap();
+    let handler = register_irq_handler(vector, |_| {
+        FIRED.fetch_add(1, Ordering::Relaxed);
+        IrqReturn::Handled
+    })
+    .unwrap();
+    hpet.start_timer(0, TimerMode::OneShot, 1_000_000, vector)
+        .unwrap();
+    hpet.busy_wait(20_000_000);
+    assert_eq!(FIRED.load(Ordering::Relaxed), 1);
+    hpet.start_timer(0, TimerMode::Periodic, 1_000_000, vector)
+        .unwrap();
+    hpet.busy_wait(20_000_000);
+    hpet.stop_timer(0);
+    assert!(FIRED.load(Ordering::Relaxed) > 5);
+    unregister_irq_handler(handler).unwrap();
+    free_vector(vector).unwrap();
+    assert_eq!(
+        hpet.start_timer(u8::MAX, TimerMode::OneShot, 1, vector),
+        Err(HpetError::NoSuchComparator)
+    );
+}
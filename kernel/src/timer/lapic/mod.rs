@@ -1,23 +1,109 @@
+//! Local APIC timer
+//!
+//! Once calibrated the timer drives the async timers of `task::timer`. In the
+//! default tickless mode it is only armed for the earliest pending deadline,
+//! in TSC-deadline mode when the processor supports it and one-shot mode
+//! otherwise, so an idle CPU gets no timer interrupts at all.
+//! `lapic.tickless=off` keeps a periodic 1 ms tick instead.
+use crate::boot_params::BootParam;
+use crate::drivers::apic::local_apic::{LAPICReg, LOCAL_APIC};
+use crate::interrupts::InterruptIndexAPIC;
+use crate::time::{self, Duration, Instant};
+use crate::utils::{cpuid, msr};
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+
+/// Calibrate the LAPIC timer against the HPET or PIT at boot, `lapic.calibrate=off` skips it
+pub static CALIBRATE: BootParam<bool> =
+    BootParam::new("lapic.calibrate", "calibrate the LAPIC timer at boot", true);
+/// Arm the timer for the next deadline only, `lapic.tickless=off` keeps a periodic tick
+pub static TICKLESS: BootParam<bool> = BootParam::new(
+    "lapic.tickless",
+    "arm the LAPIC timer for the next deadline only",
+    true,
+);
+
+const IA32_TSC_DEADLINE: u32 = 0x6E0;
+/// Value of `MODE` until the timer is calibrated
+const NOT_RUNNING: u8 = u8::MAX;
+/// Mode the timer was started in
+static MODE: AtomicU8 = AtomicU8::new(NOT_RUNNING);
+/// Timer ticks per millisecond with a divider of 1
+static TICKS_PER_MS: AtomicU32 = AtomicU32::new(0);
+
+/// Whether the LAPIC timer is calibrated and drives the async timers
+pub fn is_running() -> bool {
+    mode().is_some()
+}
+/// Mode the timer runs in, `None` until calibrated
+pub fn mode() -> Option<LAPICTimerMode> {
+    match MODE.load(Ordering::Acquire) {
+        0b00 => Some(LAPICTimerMode::OneShot),
+        0b01 => Some(LAPICTimerMode::Periodic),
+        0b10 => Some(LAPICTimerMode::TscDeadline),
+        _ => None,
+    }
+}
+/// Measured timer frequency with a divider of 1, `None` until calibrated
+pub fn ticks_per_ms() -> Option<u32> {
+    match TICKS_PER_MS.load(Ordering::Relaxed) {
+        0 => None,
+        ticks => Some(ticks),
+    }
+}
+
+/// Measure the LAPIC timer frequency and start it in the mode chosen on the command line
 pub fn lapic_calibrate() {
-    use super::pit::PIT;
-    use crate::drivers::apic::local_apic::{LAPICReg, LOCAL_APIC};
     let measure_duration: u32 = 10;
+    let ticks = count_ticks(measure_duration, super::calibration_delay);
+    log::debug!(
+        "LAPIC timer: {} ticks per {} ms ({} reference)",
+        ticks,
+        measure_duration,
+        if super::hpet::HPET.is_some() {
+            "HPET"
+        } else {
+            "PIT"
+        }
+    );
+    TICKS_PER_MS.store(ticks / measure_duration, Ordering::Relaxed);
+    let mode = if !TICKLESS.get() {
+        LAPICTimerMode::Periodic
+    } else if cpuid::has_tsc_deadline() && time::tsc_frequency().is_some() {
+        LAPICTimerMode::TscDeadline
+    } else {
+        LAPICTimerMode::OneShot
+    };
+    LAPICTimer::start(mode);
+    log::info!("LAPIC timer running in {:?} mode", mode);
+}
+/// Measure the timer frequency of an application processor in ticks per millisecond
+///
+/// APs take no timer interrupts, so the timer is left stopped and masked.
+/// Busy-waits on `Instant`, a PIT sleep would wait for interrupts only the BSP gets.
+pub fn measure_ap_ticks_per_ms() -> u32 {
+    let measure_duration: u32 = 10;
+    let ticks = count_ticks(measure_duration, |millis| {
+        let end = Instant::now() + Duration::from_millis(millis);
+        while Instant::now() < end {
+            core::hint::spin_loop();
+        }
+    });
+    ticks / measure_duration
+}
+/// Count the timer ticks with a divider of 1 while `delay` waits `millis` milliseconds
+fn count_ticks(millis: u32, delay: impl FnOnce(u64)) -> u32 {
     let max_ticks = 0xFFFFFFFF;
-    LAPICTimer::set_divide(LAPICTimerDivideValue::Div1);
-    LAPICTimer::set_ticks(max_ticks);
-    PIT::sleep(measure_duration as u64);
     LAPICTimer::set_active(false);
-    let ticks_raw = max_ticks - LAPICTimer::get_current_ticks();
-    let ticks = ticks_raw;
-    // serial_println!("Ticks per 10ms: {}", ticks);
-    LAPICTimer::set_lvt();
-    LAPICTimer::set_periodic(true);
+    LAPICTimer::set_mode(LAPICTimerMode::OneShot);
     LAPICTimer::set_divide(LAPICTimerDivideValue::Div1);
-    LAPICTimer::set_ticks(ticks);
-    LAPICTimer::set_active(false);
+    LAPICTimer::set_ticks(max_ticks);
+    delay(millis as u64);
+    let ticks = max_ticks - LAPICTimer::get_current_ticks();
+    LAPICTimer::set_ticks(0);
+    ticks
 }
-#[derive(Debug, Copy, Clone)]
-enum LAPICTimerDivideValue {
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LAPICTimerDivideValue {
     Div2 = 0b0000,
     Div4 = 0b0001,
     Div8 = 0b0010,
@@ -27,24 +113,27 @@ enum LAPICTimerDivideValue {
     Div128 = 0b1010,
     Div1 = 0b1011,
 }
-use crate::drivers::apic::local_apic::{LAPICReg, LOCAL_APIC};
-use crate::interrupts::InterruptIndexAPIC;
-use crate::serial_println;
+/// Timer mode, bits 17-18 of the timer LVT
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LAPICTimerMode {
+    /// Counts down once from the initial count
+    OneShot = 0b00,
+    /// Reloads the initial count when reaching zero
+    Periodic = 0b01,
+    /// Fires when the TSC reaches the value of the `IA32_TSC_DEADLINE` MSR
+    TscDeadline = 0b10,
+}
 pub struct LAPICTimer {}
 impl LAPICTimer {
-    fn set_lvt() {
+    pub fn set_lvt() {
         LOCAL_APIC.write_register(LAPICReg::TimerLVT, InterruptIndexAPIC::LAPICTimer.as_u32());
     }
-    fn set_periodic(periodic: bool) {
+    pub fn set_mode(mode: LAPICTimerMode) {
         let mut lvt = LOCAL_APIC.read_register(LAPICReg::TimerLVT);
-        if periodic {
-            lvt = lvt | (1 << 17);
-        } else {
-            lvt = lvt & !(1 << 17);
-        }
+        lvt = (lvt & !(0b11 << 17)) | ((mode as u32) << 17);
         LOCAL_APIC.write_register(LAPICReg::TimerLVT, lvt);
     }
-    fn set_active(active: bool) {
+    pub fn set_active(active: bool) {
         let mut lvt = LOCAL_APIC.read_register(LAPICReg::TimerLVT);
         if !active {
             lvt = lvt | (1 << 16);
@@ -53,34 +142,80 @@ impl LAPICTimer {
         }
         LOCAL_APIC.write_register(LAPICReg::TimerLVT, lvt);
     }
-    fn set_divide(divide: LAPICTimerDivideValue) {
+    pub fn set_divide(divide: LAPICTimerDivideValue) {
         let mut lvt = LOCAL_APIC.read_register(LAPICReg::TimerDCnf);
         lvt = lvt & 0b1111_1000;
         lvt = lvt | divide as u32;
         LOCAL_APIC.write_register(LAPICReg::TimerDCnf, lvt);
     }
-    fn set_ticks(ticks: u32) {
+    /// Write the initial count, which restarts the countdown, 0 stops the timer
+    pub fn set_ticks(ticks: u32) {
         LOCAL_APIC.write_register(LAPICReg::TimerICnt, ticks);
     }
-    fn get_ticks() -> u32 {
+    pub fn get_current_ticks() -> u32 {
         LOCAL_APIC.read_register(LAPICReg::TimerCCnt)
     }
-    fn get_current_ticks() -> u32 {
-        LOCAL_APIC.read_register(LAPICReg::TimerCCnt)
+    /// Start the calibrated timer in `mode`
+    ///
+    /// The periodic mode ticks every millisecond, the other modes are armed
+    /// for the earliest pending async timer.
+    pub fn start(mode: LAPICTimerMode) {
+        let ticks_per_ms = ticks_per_ms().expect("LAPIC timer is not calibrated");
+        LAPICTimer::set_lvt();
+        LAPICTimer::set_mode(mode);
+        LAPICTimer::set_divide(LAPICTimerDivideValue::Div1);
+        MODE.store(mode as u8, Ordering::Release);
+        match mode {
+            LAPICTimerMode::Periodic => LAPICTimer::set_ticks(ticks_per_ms),
+            _ => crate::task::timer::rearm(),
+        }
     }
-    // eroare 30ms
+    /// Arm the timer to fire at `deadline`, `None` disarms it
+    ///
+    /// Does nothing in periodic mode or before calibration. A deadline in the
+    /// past fires right away.
+    pub fn set_deadline(deadline: Option<Instant>) {
+        let remaining = deadline.map(|deadline| deadline.duration_since(Instant::now()));
+        match mode() {
+            Some(LAPICTimerMode::TscDeadline) => {
+                let target = remaining.map_or(0, tsc_deadline);
+                msr::write_msr(IA32_TSC_DEADLINE, target as u32, (target >> 32) as u32);
+            }
+            Some(LAPICTimerMode::OneShot) => {
+                LAPICTimer::set_ticks(remaining.map_or(0, oneshot_ticks));
+            }
+            _ => {}
+        }
+    }
+    /// Halt until `millis` milliseconds have passed, other tasks do not run meanwhile
     pub fn sleep(millis: u64) {
-        use crate::interrupts::handlers::{LAPIC_TIMER_SLEEP_COUNTER, LAPIC_TIMER_SLEEP_FLAG};
-        use core::sync::atomic::Ordering;
-        LAPIC_TIMER_SLEEP_COUNTER.store(millis as i64, Ordering::Relaxed);
-        LAPIC_TIMER_SLEEP_FLAG.store(true, Ordering::Relaxed);
-        LAPICTimer::set_ticks(LAPICTimer::get_ticks());
-        LAPICTimer::set_periodic(true);
-        LAPICTimer::set_active(true);
-        while LAPIC_TIMER_SLEEP_COUNTER.load(Ordering::Relaxed) > 0 {
-            x86_64::instructions::hlt();
+        use core::{future::Future, pin::Pin, task::Context, task::Waker};
+        use x86_64::instructions::interrupts;
+        let mut sleep = crate::task::timer::sleep(Duration::from_millis(millis));
+        let mut context = Context::from_waker(Waker::noop());
+        loop {
+            // The timer interrupt must not fire between the poll and the halt
+            interrupts::disable();
+            if Pin::new(&mut sleep).poll(&mut context).is_ready() {
+                interrupts::enable();
+                break;
+            }
+            interrupts::enable_and_hlt();
         }
-        LAPIC_TIMER_SLEEP_FLAG.store(false, Ordering::Relaxed);
-        LAPICTimer::set_active(false);
     }
 }
+/// Initial count of a one-shot countdown of `duration`, clamped to the counter range
+fn oneshot_ticks(duration: Duration) -> u32 {
+    let ticks = duration.as_nanos() * TICKS_PER_MS.load(Ordering::Relaxed) as u128 / 1_000_000;
+    // A longer countdown fires early and is re-armed for the rest
+    ticks.clamp(1, u32::MAX as u128) as u32
+}
+/// TSC value `duration` from now, never 0 which would disarm the timer
+fn tsc_deadline(duration: Duration) -> u64 {
+    let frequency = time::tsc_frequency().unwrap_or(0) as u128;
+    let ticks = (duration.as_nanos() * frequency / 1_000_000_000).min(u64::MAX as u128) as u64;
+    time::read_tsc().saturating_add(ticks.max(1))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,34 @@
+use super::*;
+
+/// Calibrate once, the tests share the running timer
+fn calibrated() {
+    if !is_running() {
+        lapic_calibrate();
+    }
+}
+
+#[test_case]
+fn test_calibration() {
+    calibrated();
+    let ticks = ticks_per_ms().unwrap();
+    // Anything between 1 MHz and 100 GHz is plausible
+    assert!(ticks > 1_000 && ticks < 100_000_000, "{}", ticks);
+    assert!(mode().is_some());
+}
+#[test_case]
+fn test_sleep() {
+    calibrated();
+    let start = Instant::now();
+    LAPICTimer::sleep(20);
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(20), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(200), "{:?}", elapsed);
+}
+#[test_case]
+fn test_oneshot_ticks() {
+    calibrated();
+    let per_ms = ticks_per_ms().unwrap();
+    assert_eq!(oneshot_ticks(Duration::from_millis(1)), per_ms);
+    assert_eq!(oneshot_ticks(Duration::ZERO), 1);
+    assert_eq!(oneshot_ticks(Duration::from_secs(1_000_000)), u32::MAX);
+}
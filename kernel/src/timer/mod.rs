@@ -1,2 +1,32 @@
+pub mod hpet;
 pub mod lapic;
 pub mod pit;
+pub mod rtc;
+
+/// Milliseconds since the PIT started ticking, safe to call from interrupt context
+pub fn uptime_millis() -> u64 {
+    use crate::interrupts::handlers::PIT_COUNTER;
+    PIT_COUNTER.load(core::sync::atomic::Ordering::Relaxed)
+}
+/// Wait `millis` milliseconds on the most precise reference timer
+///
+/// Used to calibrate the other timers: the HPET when present, the PIT otherwise.
+pub fn calibration_delay(millis: u64) {
+    match hpet::HPET.as_ref() {
+        Some(hpet) => hpet.busy_wait(millis * 1_000_000),
+        None => pit::PIT::sleep(millis),
+    }
+}
+/// Stop the PIT tick when nothing depends on it anymore
+///
+/// It is needed by the tick clock and to drive the async timers until the
+/// LAPIC timer runs. `PIT::sleep` starts it again when it is the calibration
+/// reference. Once it is stopped an idle CPU only wakes up for its next deadline.
+pub fn stop_unused_ticks() {
+    use crate::time::{clock_source, ClockSource};
+    let tickless = lapic::mode().is_some_and(|mode| mode != lapic::LAPICTimerMode::Periodic);
+    if tickless && clock_source() != ClockSource::Tick {
+        pit::PIT::stop_millisecond_ticks();
+        log::debug!("PIT tick stopped");
+    }
+}
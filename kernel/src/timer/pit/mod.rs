@@ -48,16 +48,23 @@ impl PIT {
         outb(0x40, (self.reload >> 8) as u8);
         x86_64::instructions::interrupts::enable();
     }
+    /// Make channel 0 raise the timer interrupt every millisecond
+    pub fn start_millisecond_ticks() {
+        const DIVISOR: u32 = (BASE_FREQUENCY / 1000.0) as u32;
+        outb(0x43, 0b00110100);
+        outb(0x40, (DIVISOR & 0xFF).try_into().unwrap());
+        outb(0x40, (DIVISOR >> 8) as u8);
+        crate::drivers::apic::routing::mask_irq(0, false);
+    }
+    /// Mask the timer interrupt of channel 0, `sleep` starts it again
+    pub fn stop_millisecond_ticks() {
+        crate::drivers::apic::routing::mask_irq(0, true);
+    }
     // Error of 0.01ms
     pub fn sleep(millis: u64) {
         use crate::interrupts::handlers::{PIT_SLEEP_COUNTER, PIT_SLEEP_FLAG};
         use core::sync::atomic::Ordering;
-        {
-            const DIVISOR: u32 = (BASE_FREQUENCY / 1000.0) as u32;
-            outb(0x43, 0b00110100);
-            outb(0x40, (DIVISOR & 0xFF).try_into().unwrap());
-            outb(0x40, (DIVISOR >> 8) as u8);
-        }
+        PIT::start_millisecond_ticks();
         PIT_SLEEP_COUNTER.store(millis.try_into().unwrap(), Ordering::Relaxed);
         PIT_SLEEP_FLAG.store(true, Ordering::Relaxed);
         while PIT_SLEEP_COUNTER.load(Ordering::Relaxed) > 0 {
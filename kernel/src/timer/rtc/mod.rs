@@ -0,0 +1,273 @@
+//! CMOS real-time clock driver
+//!
+//! The RTC keeps the calendar time while the machine is off. Its registers are
+//! reached through an index port and a data port, and may be read while the
+//! chip updates them, so every date is read until two readings match. Besides
+//! the date it can raise IRQ 8 periodically, on an alarm or after every update;
+//! status register C must be read on every interrupt or the RTC stops raising them.
+use crate::drivers::acpi::fadt::FADT_TABLE;
+use crate::drivers::apic::{local_apic::LOCAL_APIC, routing::route_irq};
+use crate::interrupts::irq::{allocate_vector, register_irq_handler, IrqError, IrqReturn};
+use crate::utils::registers::{inb, outb};
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+pub use ferrum_parse::rtc::DateTime;
+use ferrum_parse::rtc::{encode_hour, encode_value, RtcRegisters};
+use ferrum_parse::ParseError;
+use log::{info, warn};
+use spin::{Mutex, RwLock};
+use x86_64::instructions::interrupts::without_interrupts;
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+/// Set in the index written to the address port to mask NMIs
+const NMI_DISABLE: u8 = 1 << 7;
+/// ISA IRQ of the RTC
+const RTC_IRQ: u8 = 8;
+
+// Register indices
+const SECONDS: u8 = 0x00;
+const SECONDS_ALARM: u8 = 0x01;
+const MINUTES: u8 = 0x02;
+const MINUTES_ALARM: u8 = 0x03;
+const HOURS: u8 = 0x04;
+const HOURS_ALARM: u8 = 0x05;
+const DAY: u8 = 0x07;
+const MONTH: u8 = 0x08;
+const YEAR: u8 = 0x09;
+const STATUS_A: u8 = 0x0A;
+const STATUS_B: u8 = 0x0B;
+const STATUS_C: u8 = 0x0C;
+const STATUS_D: u8 = 0x0D;
+/// Selected between accesses, with the NMI mask bit clear
+const IDLE_INDEX: u8 = STATUS_D;
+
+/// Status register A: the time registers are being updated
+const UPDATE_IN_PROGRESS: u8 = 1 << 7;
+/// Status register A: the periodic rate selection bits
+const RATE_MASK: u8 = 0x0F;
+/// Interrupt enable bits of status register B, and flags of status register C
+const PERIODIC: u8 = 1 << 6;
+const ALARM: u8 = 1 << 5;
+const UPDATE_ENDED: u8 = 1 << 4;
+/// Alarm register value matching any time
+const ALARM_DONT_CARE: u8 = 0xC0;
+/// Readings attempted before giving up on a consistent date
+const MAX_READ_ATTEMPTS: usize = 8;
+
+/// RTC error type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtcError {
+    /// The periodic frequency is not a power of two between 2 and 8192 Hz
+    InvalidRate,
+    InvalidTime,
+    /// The interrupts of the RTC are not routed, see `init`
+    NoInterrupts,
+}
+/// Interrupts raised by the RTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtcEvents {
+    pub periodic: bool,
+    pub alarm: bool,
+    pub update_ended: bool,
+}
+
+/// Serializes the index and data port accesses
+static CMOS: Mutex<()> = Mutex::new(());
+/// Last value written to the address port, bit 7 set while NMIs are masked
+static ADDRESS: AtomicU8 = AtomicU8::new(0);
+/// Vector the RTC interrupt is routed to, 0 when not routed
+static VECTOR: AtomicU8 = AtomicU8::new(0);
+static PERIODIC_COUNT: AtomicU64 = AtomicU64::new(0);
+static ALARM_COUNT: AtomicU64 = AtomicU64::new(0);
+/// Called from interrupt context for every RTC interrupt
+static EVENT_HANDLER: RwLock<Option<fn(RtcEvents)>> = RwLock::new(None);
+
+/// Select a register, the address port is write-only so the value is also kept in `ADDRESS`
+fn select(address: u8) {
+    ADDRESS.store(address, Ordering::Relaxed);
+    outb(CMOS_ADDRESS, address);
+}
+/// Read a CMOS register, the CMOS lock must be held
+///
+/// NMIs are masked only for the access and enabled again right after.
+fn read_register(index: u8) -> u8 {
+    select(NMI_DISABLE | index);
+    let value = inb(CMOS_DATA);
+    select(IDLE_INDEX);
+    value
+}
+/// Write a CMOS register, the CMOS lock must be held
+///
+/// NMIs are masked only for the access and enabled again right after.
+fn write_register(index: u8, value: u8) {
+    select(NMI_DISABLE | index);
+    outb(CMOS_DATA, value);
+    select(IDLE_INDEX);
+}
+/// Run `f` with exclusive access to the CMOS ports
+fn with_cmos<T>(f: impl FnOnce() -> T) -> T {
+    // The interrupt handler also reads the CMOS
+    without_interrupts(|| {
+        let _cmos = CMOS.lock();
+        f()
+    })
+}
+
+/// The RTC is at its legacy ports according to the FADT
+pub fn is_present() -> bool {
+    FADT_TABLE
+        .as_ref()
+        .is_none_or(|fadt| fadt.cmos_rtc_present())
+}
+/// Read the current date and time, in the time zone the RTC is set to (UTC on QEMU)
+pub fn read_datetime() -> Result<DateTime, ParseError> {
+    let century = FADT_TABLE.as_ref().and_then(|fadt| fadt.century_register());
+    let read = || {
+        while read_register(STATUS_A) & UPDATE_IN_PROGRESS != 0 {
+            core::hint::spin_loop();
+        }
+        RtcRegisters {
+            seconds: read_register(SECONDS),
+            minutes: read_register(MINUTES),
+            hours: read_register(HOURS),
+            day: read_register(DAY),
+            month: read_register(MONTH),
+            year: read_register(YEAR),
+            century: century.map(read_register),
+            status_b: read_register(STATUS_B),
+        }
+    };
+    // An update may still start between the check and the reads
+    let registers = with_cmos(|| {
+        let mut previous = read();
+        for _ in 0..MAX_READ_ATTEMPTS {
+            let current = read();
+            if current == previous {
+                return Some(current);
+            }
+            previous = current;
+        }
+        None
+    });
+    registers.ok_or(ParseError::InvalidValue)?.decode()
+}
+
+/// Route the RTC interrupt to this CPU, all its sources stay disabled
+pub fn init() {
+    if !is_present() {
+        warn!("No CMOS RTC according to the FADT");
+        return;
+    }
+    match init_interrupts() {
+        Ok(vector) => info!("RTC interrupts on vector {:#x}", vector),
+        Err(error) => warn!("Failed to set up the RTC interrupts: {:?}", error),
+    }
+}
+fn init_interrupts() -> Result<u8, IrqError> {
+    let vector = allocate_vector()?;
+    register_irq_handler(vector, rtc_interrupt_handler)?;
+    with_cmos(|| {
+        let status_b = read_register(STATUS_B);
+        write_register(STATUS_B, status_b & !(PERIODIC | ALARM | UPDATE_ENDED));
+        read_register(STATUS_C);
+    });
+    route_irq(RTC_IRQ, vector, LOCAL_APIC.apic_id() as u8);
+    VECTOR.store(vector, Ordering::Relaxed);
+    Ok(vector)
+}
+fn rtc_interrupt_handler(_vector: u8) -> IrqReturn {
+    // Reading status register C acknowledges the interrupt
+    let status = with_cmos(|| read_register(STATUS_C));
+    if status & (PERIODIC | ALARM | UPDATE_ENDED) == 0 {
+        return IrqReturn::NotHandled;
+    }
+    let events = RtcEvents {
+        periodic: status & PERIODIC != 0,
+        alarm: status & ALARM != 0,
+        update_ended: status & UPDATE_ENDED != 0,
+    };
+    if events.periodic {
+        PERIODIC_COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+    if events.alarm {
+        ALARM_COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+    if let Some(handler) = EVENT_HANDLER.try_read().and_then(|handler| *handler) {
+        handler(events);
+    }
+    IrqReturn::Handled
+}
+/// Set the function called, in interrupt context, for every RTC interrupt
+pub fn set_event_handler(handler: Option<fn(RtcEvents)>) {
+    without_interrupts(|| *EVENT_HANDLER.write() = handler);
+}
+/// Enable or disable one of the interrupt sources in status register B
+fn set_interrupt_enabled(source: u8, enabled: bool) -> Result<(), RtcError> {
+    if VECTOR.load(Ordering::Relaxed) == 0 {
+        return Err(RtcError::NoInterrupts);
+    }
+    with_cmos(|| {
+        let status_b = read_register(STATUS_B);
+        let status_b = if enabled {
+            status_b | source
+        } else {
+            status_b & !source
+        };
+        write_register(STATUS_B, status_b);
+    });
+    Ok(())
+}
+/// Raise the periodic interrupt `frequency` times per second, `None` stops it
+///
+/// The frequency must be a power of two from 2 to 8192 Hz.
+pub fn set_periodic(frequency: Option<u32>) -> Result<(), RtcError> {
+    let Some(frequency) = frequency else {
+        return set_interrupt_enabled(PERIODIC, false);
+    };
+    if !frequency.is_power_of_two() || !(2..=8192).contains(&frequency) {
+        return Err(RtcError::InvalidRate);
+    }
+    // The periodic frequency is 32768 >> (rate - 1)
+    let rate = 16 - frequency.trailing_zeros() as u8;
+    with_cmos(|| {
+        let status_a = read_register(STATUS_A);
+        write_register(STATUS_A, (status_a & !RATE_MASK) | rate);
+    });
+    set_interrupt_enabled(PERIODIC, true)
+}
+/// Raise the alarm interrupt every day at the given time, `None` fields match any value
+pub fn set_alarm(hour: Option<u8>, minute: Option<u8>, second: Option<u8>) -> Result<(), RtcError> {
+    if hour.is_some_and(|hour| hour >= 24)
+        || minute.is_some_and(|minute| minute >= 60)
+        || second.is_some_and(|second| second >= 60)
+    {
+        return Err(RtcError::InvalidTime);
+    }
+    with_cmos(|| {
+        let status_b = read_register(STATUS_B);
+        let encode =
+            |value: Option<u8>| value.map_or(ALARM_DONT_CARE, |v| encode_value(v, status_b));
+        write_register(
+            HOURS_ALARM,
+            hour.map_or(ALARM_DONT_CARE, |h| encode_hour(h, status_b)),
+        );
+        write_register(MINUTES_ALARM, encode(minute));
+        write_register(SECONDS_ALARM, encode(second));
+    });
+    set_interrupt_enabled(ALARM, true)
+}
+/// Disable the alarm interrupt
+pub fn clear_alarm() -> Result<(), RtcError> {
+    set_interrupt_enabled(ALARM, false)
+}
+/// Number of periodic interrupts received
+pub fn periodic_count() -> u64 {
+    PERIODIC_COUNT.load(Ordering::Relaxed)
+}
+/// Number of alarm interrupts received
+pub fn alarm_count() -> u64 {
+    ALARM_COUNT.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,25 @@
+use super::*;
+
+#[test_case]
+fn test_read_datetime() {
+    let first = read_datetime().unwrap();
+    assert!(first.year >= 2020, "{}", first);
+    let second = read_datetime().unwrap();
+    assert!(second >= first);
+}
+#[test_case]
+fn test_nmi_enabled_after_access() {
+    read_datetime().unwrap();
+    assert_eq!(ADDRESS.load(Ordering::Relaxed) & NMI_DISABLE, 0);
+    assert_eq!(ADDRESS.load(Ordering::Relaxed), IDLE_INDEX);
+}
+#[test_case]
+fn test_periodic_interrupts() {
+    let before = periodic_count();
+    set_periodic(Some(1024)).unwrap();
+    crate::timer::calibration_delay(50);
+    set_periodic(None).unwrap();
+    assert!(periodic_count() > before + 10);
+    assert_eq!(set_periodic(Some(1000)), Err(RtcError::InvalidRate));
+    assert_eq!(set_alarm(Some(24), None, None), Err(RtcError::InvalidTime));
+}
@@ -0,0 +1,169 @@
+//! Frame pointer based stack unwinding and symbolization
+//!
+//! The symbol table is the `nm -n -C` output of a previous link of the kernel,
+//! embedded by `build.rs` (see `kernel/GNUmakefile`). Each line holds an address,
+//! a symbol type and a name, sorted by address.
+use crate::{HHDM_REQUEST, KERNEL_ADDRESS_REQUEST};
+use core::arch::asm;
+use core::fmt;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::{PageTable, PageTableFlags};
+use x86_64::VirtAddr;
+
+/// Symbol table of the kernel, empty when built without the GNUmakefile
+static SYMBOLS: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/kernel.sym"));
+/// Address the kernel is linked at, see `linker.ld`
+const KERNEL_LINK_BASE: u64 = 0xFFFF_FFFF_8000_0000;
+/// Frames printed at most, in case the chain of frame pointers loops
+const MAX_FRAMES: usize = 32;
+
+/// A symbol found for an address
+#[derive(Debug, Clone, Copy)]
+pub struct Symbol {
+    pub name: &'static str,
+    /// Distance of the address from the start of the symbol
+    pub offset: u64,
+}
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}+{:#x}", self.name, self.offset)
+    }
+}
+
+/// Find the function containing the runtime address `addr`
+pub fn resolve(addr: u64) -> Option<Symbol> {
+    resolve_in(SYMBOLS, addr.wrapping_sub(load_offset()))
+}
+/// Find the symbol containing the link address `link_addr` in an `nm -n` table
+fn resolve_in(symbols: &'static [u8], link_addr: u64) -> Option<Symbol> {
+    let table = core::str::from_utf8(symbols).ok()?;
+    let mut found = None;
+    // The table is sorted, the last symbol starting at or before the address contains it
+    for line in table.lines() {
+        let mut fields = line.splitn(3, ' ');
+        let (Some(start), Some(_), Some(name)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let Ok(start) = u64::from_str_radix(start, 16) else {
+            continue;
+        };
+        if start > link_addr {
+            break;
+        }
+        found = Some(Symbol {
+            name,
+            offset: link_addr - start,
+        });
+    }
+    found
+}
+/// Difference between the address the kernel was loaded at and the one it was linked at
+fn load_offset() -> u64 {
+    KERNEL_ADDRESS_REQUEST
+        .get_response()
+        .map(|response| response.virtual_base().wrapping_sub(KERNEL_LINK_BASE))
+        .unwrap_or(0)
+}
+/// Whether `addr` is mapped in the active page tables
+///
+/// Walks the tables directly rather than through the kernel address space,
+/// whose lock may be held by the code that panicked.
+fn is_mapped(addr: u64) -> bool {
+    let Some(hhdm) = HHDM_REQUEST.get_response() else {
+        return false;
+    };
+    let Ok(addr) = VirtAddr::try_new(addr) else {
+        return false;
+    };
+    let indexes = [
+        addr.p4_index(),
+        addr.p3_index(),
+        addr.p2_index(),
+        addr.p1_index(),
+    ];
+    let mut table_address = Cr3::read().0.start_address();
+    for (level, index) in indexes.into_iter().enumerate() {
+        let table = unsafe { &*((hhdm.offset() + table_address.as_u64()) as *const PageTable) };
+        let entry = &table[index];
+        if !entry.flags().contains(PageTableFlags::PRESENT) {
+            return false;
+        }
+        // A huge page at level 3 or 2 maps the address, there is no table below
+        if level == 3 || (level > 0 && entry.flags().contains(PageTableFlags::HUGE_PAGE)) {
+            return true;
+        }
+        table_address = entry.addr();
+    }
+    true
+}
+
+/// Iterator over the return addresses of a chain of stack frames
+pub struct Backtrace {
+    rbp: u64,
+    frames: usize,
+}
+impl Backtrace {
+    /// Walk the stack starting from the frame of the caller
+    #[inline(always)]
+    pub fn capture() -> Self {
+        Backtrace::from_frame(current_frame())
+    }
+    /// Walk the stack starting from the frame pointer `rbp`
+    pub fn from_frame(rbp: u64) -> Self {
+        Backtrace { rbp, frames: 0 }
+    }
+}
+impl Iterator for Backtrace {
+    type Item = u64;
+    fn next(&mut self) -> Option<u64> {
+        // Frames live in the higher half, are aligned and only grow towards higher addresses
+        if self.frames >= MAX_FRAMES || self.rbp < 0xFFFF_8000_0000_0000 || self.rbp % 8 != 0 {
+            return None;
+        }
+        // A corrupt frame pointer must end the walk, not fault in the middle of a panic
+        if !is_mapped(self.rbp) || !is_mapped(self.rbp + 8) {
+            return None;
+        }
+        let (next_rbp, return_address) = unsafe {
+            let frame = self.rbp as *const u64;
+            (*frame, *frame.add(1))
+        };
+        if return_address == 0 {
+            return None;
+        }
+        self.rbp = if next_rbp > self.rbp { next_rbp } else { 0 };
+        self.frames += 1;
+        Some(return_address)
+    }
+}
+
+/// Printable backtrace, one frame per line
+pub struct BacktraceDisplay {
+    /// Address of the faulting instruction, printed before the saved frames
+    pub rip: Option<u64>,
+    pub rbp: u64,
+}
+impl fmt::Display for BacktraceDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Backtrace:")?;
+        let frames = self.rip.into_iter().chain(Backtrace::from_frame(self.rbp));
+        for (index, addr) in frames.enumerate() {
+            write!(f, "\n  #{:<2} {:#018x}", index, addr)?;
+            if let Some(symbol) = resolve(addr) {
+                write!(f, " {}", symbol)?;
+            }
+        }
+        Ok(())
+    }
+}
+/// Current frame pointer, to be used with `BacktraceDisplay`
+#[inline(always)]
+pub fn current_frame() -> u64 {
+    let rbp: u64;
+    unsafe { asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack)) };
+    rbp
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,37 @@
+use super::*;
+
+/// `nm -n -C` output of a small kernel
+static TEST_SYMBOLS: &[u8] = b"ffffffff80000000 T _start
+ffffffff80000040 T kernel::main
+ffffffff80000100 t core::fmt::write
+not a symbol line
+ffffffff80000200 T kernel::panic
+";
+
+#[test_case]
+fn test_resolve_symbol() {
+    let symbol = resolve_in(TEST_SYMBOLS, 0xFFFF_FFFF_8000_0000).unwrap();
+    assert_eq!((symbol.name, symbol.offset), ("_start", 0));
+    let symbol = resolve_in(TEST_SYMBOLS, 0xFFFF_FFFF_8000_0123).unwrap();
+    assert_eq!((symbol.name, symbol.offset), ("core::fmt::write", 0x23));
+    // Past the last start address, the last symbol is kept
+    let symbol = resolve_in(TEST_SYMBOLS, 0xFFFF_FFFF_8000_0210).unwrap();
+    assert_eq!((symbol.name, symbol.offset), ("kernel::panic", 0x10));
+}
+#[test_case]
+fn test_resolve_before_first_symbol() {
+    assert!(resolve_in(TEST_SYMBOLS, 0xFFFF_FFFF_7FFF_FFFF).is_none());
+    assert!(resolve_in(b"", 0xFFFF_FFFF_8000_0000).is_none());
+}
+#[test_case]
+fn test_capture_walks_stack() {
+    assert!(Backtrace::capture().count() > 0);
+}
+#[test_case]
+fn test_unmapped_frame_ends_walk() {
+    // Higher half and aligned but never mapped, reading it would fault
+    let rbp = 0xFFFF_9FFF_FFFF_F000;
+    assert!(!is_mapped(rbp));
+    assert_eq!(Backtrace::from_frame(rbp).count(), 0);
+    assert!(is_mapped(current_frame()));
+}
@@ -11,3 +11,42 @@ pub fn check_apic() -> bool {
     }
     feat & (1 << 9) != 0
 }
+/// Run `cpuid` for the given leaf and subleaf
+pub fn cpuid(leaf: u32, subleaf: u32) -> core::arch::x86_64::CpuidResult {
+    core::arch::x86_64::__cpuid_count(leaf, subleaf)
+}
+/// Highest basic leaf supported
+fn max_leaf() -> u32 {
+    cpuid(0, 0).eax
+}
+/// Highest extended leaf supported
+fn max_extended_leaf() -> u32 {
+    cpuid(0x8000_0000, 0).eax
+}
+/// The time stamp counter and `rdtsc` are available
+pub fn has_tsc() -> bool {
+    cpuid(1, 0).edx & (1 << 4) != 0
+}
+/// The local APIC timer supports the TSC deadline mode
+pub fn has_tsc_deadline() -> bool {
+    cpuid(1, 0).ecx & (1 << 24) != 0
+}
+/// The TSC runs at a constant rate in every power and frequency state
+pub fn has_invariant_tsc() -> bool {
+    max_extended_leaf() >= 0x8000_0007 && cpuid(0x8000_0007, 0).edx & (1 << 8) != 0
+}
+/// TSC frequency in Hz reported by the processor, when it enumerates it
+///
+/// Leaf 0x15 gives the ratio of the TSC to the core crystal clock, and on
+/// most processors the crystal frequency too.
+pub fn tsc_frequency() -> Option<u64> {
+    if max_leaf() < 0x15 {
+        return None;
+    }
+    let leaf = cpuid(0x15, 0);
+    let (denominator, numerator, crystal_hz) = (leaf.eax, leaf.ebx, leaf.ecx);
+    if denominator == 0 || numerator == 0 || crystal_hz == 0 {
+        return None;
+    }
+    Some(crystal_hz as u64 * numerator as u64 / denominator as u64)
+}
@@ -1,6 +1,7 @@
 //! Utils crate responsable with panic handlers, test utils
 //! and vm management
 
+pub mod backtrace;
 pub mod cpuid;
 pub mod custom_types;
 pub mod msr;
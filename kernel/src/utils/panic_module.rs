@@ -1,28 +1,26 @@
 //! Responsable with panic implementation
 
-#[allow(unused_imports)]
-use super::{exit_qemu, QemuExitCode};
-#[allow(unused_imports)]
-use crate::{print, println, serial_print, serial_println};
-#[allow(unused_imports)]
+use super::backtrace::{current_frame, BacktraceDisplay};
+use super::{exit_qemu, test_utils, QemuExitCode};
+use crate::{println, serial_println};
 use core::panic::PanicInfo;
 
 // This function is called on panic.
-#[cfg(not(test))]
 #[panic_handler]
 /// Panic function implementation
 pub fn panic(info: &PanicInfo) -> ! {
-    println!("{}", info);
-    crate::hlt_loop();
-}
-
-// our panic handler in test mode
-#[cfg(test)]
-#[panic_handler]
-/// Panic function implementation
-pub fn panic(info: &PanicInfo) -> ! {
-    serial_println!("[failed]\n");
-    serial_println!("Error: {}\n", info);
-    exit_qemu(QemuExitCode::Failed);
+    let backtrace = BacktraceDisplay {
+        rip: None,
+        rbp: current_frame(),
+    };
+    // A panic while tests run fails the current test and ends the test kernel
+    if test_utils::is_testing() {
+        serial_println!("[failed]\n");
+        serial_println!("Error: {}\n{}\n", info, backtrace);
+        exit_qemu(QemuExitCode::Failed);
+        crate::hlt_loop();
+    }
+    println!("{}\n{}", info, backtrace);
+    serial_println!("{}\n{}", info, backtrace);
     crate::hlt_loop();
 }
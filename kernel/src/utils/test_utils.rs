@@ -1,7 +1,17 @@
 use ansi_rgb::{green_cyan, Foreground};
 
 use super::qemu_utils::*;
+use crate::interrupts::{irq, irq::IrqReturn, InterruptIndexAPIC};
 use crate::{serial_print, serial_println};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Time a single test may run before the test kernel is stopped
+pub const TEST_TIMEOUT_MS: u64 = 10_000;
+/// Set while the test runner is active
+static TESTING: AtomicBool = AtomicBool::new(false);
+/// PIT tick at which the running test times out, 0 when no test is running
+static TEST_DEADLINE: AtomicU64 = AtomicU64::new(0);
+
 pub trait Testable {
     fn run(&self) -> ();
 }
@@ -16,11 +26,40 @@ where
     }
 }
 
-// #[cfg(test)]
+/// Returns true if the kernel is running tests
+pub fn is_testing() -> bool {
+    TESTING.load(Ordering::Relaxed)
+}
+/// Fails the running test if it is past its deadline
+///
+/// Shares the PIT vector with the timer handler, which counts the ticks.
+fn test_watchdog(_vector: u8) -> IrqReturn {
+    use crate::interrupts::handlers::PIT_COUNTER;
+    let deadline = TEST_DEADLINE.load(Ordering::Relaxed);
+    if deadline != 0 && PIT_COUNTER.load(Ordering::Relaxed) >= deadline {
+        serial_println!("[timeout]\n");
+        serial_println!("Error: test ran for more than {}ms\n", TEST_TIMEOUT_MS);
+        exit_qemu(QemuExitCode::Failed);
+    }
+    IrqReturn::NotHandled
+}
+
+/// Runs every test case and exits QEMU with the result
+///
+/// The kernel must be initialized before, the watchdog needs the heap and interrupts.
 pub fn test_runner(tests: &[&dyn Testable]) {
+    use crate::interrupts::handlers::PIT_COUNTER;
+    use crate::timer::pit::PIT;
+    TESTING.store(true, Ordering::Relaxed);
+    irq::register_irq_handler(InterruptIndexAPIC::Timer.as_u8(), test_watchdog)
+        .expect("[TEST]: Failed to register the watchdog");
+    PIT::start_millisecond_ticks();
     serial_println!("Running {} tests", tests.len());
     for test in tests {
+        let deadline = PIT_COUNTER.load(Ordering::Relaxed) + TEST_TIMEOUT_MS;
+        TEST_DEADLINE.store(deadline, Ordering::Relaxed);
         test.run();
     }
+    TEST_DEADLINE.store(0, Ordering::Relaxed);
     exit_qemu(QemuExitCode::Success);
 }
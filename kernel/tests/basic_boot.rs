@@ -0,0 +1,23 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(ferrum_os::utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use ferrum_os::{println, serial_println};
+
+#[no_mangle]
+unsafe extern "C" fn _start() -> ! {
+    ferrum_os::init();
+    test_main();
+    ferrum_os::hlt_loop();
+}
+
+#[test_case]
+fn test_println() {
+    println!("test_println output");
+}
+#[test_case]
+fn test_serial_println() {
+    serial_println!("test_serial_println output");
+}
@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(ferrum_os::utils::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, vec::Vec};
+use ferrum_os::allocator::HEAP_INITIAL_SIZE;
+
+#[no_mangle]
+unsafe extern "C" fn _start() -> ! {
+    ferrum_os::init();
+    test_main();
+    ferrum_os::hlt_loop();
+}
+
+#[test_case]
+fn simple_allocation() {
+    let heap_value_1 = Box::new(41);
+    let heap_value_2 = Box::new(13);
+    assert_eq!(*heap_value_1, 41);
+    assert_eq!(*heap_value_2, 13);
+}
+#[test_case]
+fn large_vec() {
+    let n = 1000;
+    let mut vec = Vec::new();
+    for i in 0..n {
+        vec.push(i);
+    }
+    assert_eq!(vec.iter().sum::<u64>(), (n - 1) * n / 2);
+}
+#[test_case]
+fn many_boxes() {
+    for i in 0..HEAP_INITIAL_SIZE {
+        let x = Box::new(i);
+        assert_eq!(*x, i);
+    }
+}
+#[test_case]
+fn heap_grows_past_initial_size() {
+    // Needs more than the initially mapped heap, so the heap has to grow
+    let vec: Vec<u8> = alloc::vec![1; HEAP_INITIAL_SIZE * 2];
+    assert_eq!(vec.len(), HEAP_INITIAL_SIZE * 2);
+}